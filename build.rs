@@ -1,4 +1,6 @@
+#[cfg(feature = "rofi")]
 use std::{env};
+#[cfg(feature = "rofi")]
 use std::path::PathBuf;
 
 // tried doing it individually, but it messed up the Mode structure, so switched to wrapper.h.
@@ -10,6 +12,10 @@ use std::path::PathBuf;
 //     "/usr/include/rofi/helper.h",
 //     ];
 
+#[cfg(not(feature = "rofi"))]
+fn main() {}
+
+#[cfg(feature = "rofi")]
 fn main() {
     let out_path = PathBuf::from(env::var("OUT_DIR").unwrap());
 
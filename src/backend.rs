@@ -0,0 +1,234 @@
+use crate::{
+    connman, iwd, network_manager, reason,
+    secret_agent::SecretAgentEvent,
+    structure::{AccessPoint, BSSID, ConnectionDetails, EapCredentials, Radio},
+    wpa_supplicant,
+};
+use anyhow::bail;
+use futures_channel::mpsc;
+use futures_util::{
+    future::{self, Either, LocalBoxFuture},
+    stream::BoxStream,
+};
+use std::pin::pin;
+use zbus::{Connection, zvariant::OwnedObjectPath};
+
+// The daemon that manages Wi-Fi, NetworkManager by default.
+// The rest of the plugin only sees `Backend`, so the states and the rofi side don't know which one is in use.
+//
+// Object paths identify access points and profiles, every daemon supported speaks D-Bus.
+// Failures are told by NMDeviceStateReason, see `reason::describe`, other backends map their errors onto it.
+
+pub enum AccessPointEvent {
+    Added(OwnedObjectPath),
+    Removed(OwnedObjectPath),
+    Strength(OwnedObjectPath, u8),
+    /// Profiles were saved or deleted, the access points are matched again by `Backend::match_profiles`.
+    Profiles,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConnectionState {
+    Disconnected,
+    Connecting,
+    Connected,
+}
+
+// The futures are polled on glib's main context, so they don't need to be Send.
+pub trait Backend: std::fmt::Debug {
+    /// Names of every Wi-Fi interface, the last picked first.
+    fn interfaces(&self) -> &[String];
+    /// The interface in use.
+    fn iface(&self) -> &str;
+    /// The same backend on another interface. It shares the connection, so e.g. the secret agent stays registered.
+    fn select_interface<'a>(
+        &'a self,
+        iface: &'a str,
+    ) -> LocalBoxFuture<'a, anyhow::Result<Box<dyn Backend>>>;
+
+    fn radio(&self) -> LocalBoxFuture<'_, anyhow::Result<Radio>>;
+    fn set_radio(&self, radio: Radio) -> LocalBoxFuture<'_, anyhow::Result<()>>;
+    /// Yields whenever a radio switch is flipped, whether by the plugin, a hardware key or externally.
+    fn radio_changes(&self) -> LocalBoxFuture<'_, anyhow::Result<BoxStream<'static, ()>>>;
+
+    /// Scans, and resolves once the scan is done.
+    fn scan(&self) -> LocalBoxFuture<'_, anyhow::Result<()>>;
+    /// The access points in range, along with their saved profiles.
+    fn access_points(&self) -> LocalBoxFuture<'_, anyhow::Result<Vec<AccessPoint>>>;
+    /// A single access point, e.g. one that was just added, along with its saved profile.
+    fn access_point(
+        &self,
+        path: OwnedObjectPath,
+    ) -> LocalBoxFuture<'_, anyhow::Result<AccessPoint>>;
+    /// The changes of the access points, so the list can be updated one access point at a time.
+    fn access_point_events(
+        &self,
+    ) -> LocalBoxFuture<'_, anyhow::Result<BoxStream<'static, AccessPointEvent>>>;
+    /// Marks the access points with their saved profiles, from what the backend already knows.
+    /// Only backends that yield `AccessPointEvent::Profiles` need it.
+    fn match_profiles(&self, _aps: &mut [AccessPoint]) {}
+
+    /// The BSSID of the connected access point, and its profile.
+    fn active_ap(&self) -> LocalBoxFuture<'_, anyhow::Result<Option<(BSSID, OwnedObjectPath)>>>;
+    /// The access point the device is connected, or connecting, to.
+    fn active_access_point(&self) -> LocalBoxFuture<'_, anyhow::Result<Option<AccessPoint>>>;
+    fn connection_state(&self) -> LocalBoxFuture<'_, anyhow::Result<ConnectionState>>;
+    /// Yields whenever the connection state may have changed.
+    fn state_changes(&self) -> LocalBoxFuture<'_, anyhow::Result<BoxStream<'static, ()>>>;
+    /// Addresses of the active connection, `None` when it isn't connected.
+    fn connection_details(&self) -> LocalBoxFuture<'_, anyhow::Result<Option<ConnectionDetails>>>;
+    /// Yields whenever the connection details may have changed.
+    fn details_changes(&self) -> LocalBoxFuture<'_, anyhow::Result<BoxStream<'static, ()>>>;
+
+    /// Whether `connect` takes 802.1X credentials, otherwise enterprise networks only connect with a saved profile.
+    fn supports_enterprise(&self) -> bool {
+        true
+    }
+    /// Connects with a new profile. Returns the profile, and the activation in progress.
+    fn connect<'a>(
+        &'a self,
+        access_point: &'a AccessPoint,
+        password: Option<String>,
+        hidden: bool,
+        eap: Option<EapCredentials>,
+        lock_bssid: bool,
+    ) -> LocalBoxFuture<'a, anyhow::Result<(OwnedObjectPath, OwnedObjectPath)>>;
+    /// Connects with the saved profile of the access point. Returns the profile, and the activation in progress.
+    fn connect_saved<'a>(
+        &'a self,
+        access_point: &'a AccessPoint,
+    ) -> LocalBoxFuture<'a, anyhow::Result<(OwnedObjectPath, OwnedObjectPath)>>;
+    /// Resolves once the activation in progress is done, 0 when connected, otherwise the NMDeviceStateReason.
+    /// The activation is followed from before it started, by `connect` or `connect_saved`, so a quick verdict isn't missed.
+    fn connect_result(&self) -> LocalBoxFuture<'_, anyhow::Result<u32>>;
+    /// Stops an activation that is still in progress, e.g. timed out or canceled.
+    fn deactivate<'a>(
+        &'a self,
+        active: &'a OwnedObjectPath,
+    ) -> LocalBoxFuture<'a, anyhow::Result<()>>;
+    /// Deletes a profile, e.g. the one created by a failed connect.
+    fn forget_profile<'a>(
+        &'a self,
+        profile: &'a OwnedObjectPath,
+    ) -> LocalBoxFuture<'a, anyhow::Result<()>>;
+
+    /// Deletes every profile of the network, and returns how many there were.
+    fn forget<'a>(&'a self, ssid: &'a str) -> LocalBoxFuture<'a, anyhow::Result<usize>>;
+    /// Deactivates the active connection, its profile stays saved.
+    fn disconnect(&self) -> LocalBoxFuture<'_, anyhow::Result<()>>;
+
+    /// Secrets the backend lacks while connecting, answered by the password prompt.
+    fn register_agent(
+        &self,
+    ) -> LocalBoxFuture<'_, anyhow::Result<mpsc::UnboundedReceiver<SecretAgentEvent>>>;
+}
+
+pub const BACKENDS: [&str; 4] = ["networkmanager", "connman", "iwd", "wpa_supplicant"];
+
+// The D-Bus name each backend owns, in the order they are looked for.
+// NetworkManager and ConnMan run iwd or wpa_supplicant underneath, so those come last.
+const BUS_NAMES: [(&str, &str); 4] = [
+    ("networkmanager", "org.freedesktop.NetworkManager"),
+    ("connman", "net.connman"),
+    ("iwd", "net.connman.iwd"),
+    ("wpa_supplicant", "fi.w1.wpa_supplicant1"),
+];
+
+/// Connects to `name`, or to the first backend running when `None`.
+pub async fn setup(name: Option<&str>, iface: Option<&str>) -> anyhow::Result<Box<dyn Backend>> {
+    let name = match name {
+        Some(name) => name.to_string(),
+        None => running_backend().await?,
+    };
+
+    match name.as_str() {
+        "networkmanager" => Ok(Box::new(network_manager::setup_dbus(iface).await?)),
+        "connman" => Ok(Box::new(connman::setup(iface).await?)),
+        "iwd" => Ok(Box::new(iwd::setup(iface).await?)),
+        "wpa_supplicant" => Ok(Box::new(wpa_supplicant::setup(iface).await?)),
+        _ => bail!(
+            "Unknown backend {}, expected one of {}",
+            name,
+            BACKENDS.join(", ")
+        ),
+    }
+}
+
+async fn running_backend() -> anyhow::Result<String> {
+    let con = Connection::system().await?;
+    let dbus = zbus::fdo::DBusProxy::new(&con).await?;
+    for (name, bus_name) in BUS_NAMES {
+        if dbus.name_has_owner(bus_name.try_into()?).await? {
+            return Ok(name.to_string());
+        }
+    }
+    bail!("None of {} is running", BACKENDS.join(", "))
+}
+
+/// The credentials of the profile `connect_and_wait` creates.
+pub struct NewProfile {
+    pub password: Option<String>,
+    pub eap: Option<EapCredentials>,
+    /// Restricts the profile to the access point, rather than any of its network.
+    pub lock_bssid: bool,
+}
+
+/// How a connect ended, see `connect_and_wait`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Verdict {
+    /// Connected with this profile.
+    Connected(OwnedObjectPath),
+    /// The NMDeviceStateReason, or `reason::CONNECT_TIMEOUT`.
+    Failed(u32),
+    /// Canceled, or the backend couldn't be followed.
+    Canceled,
+}
+
+/// Connects with a new profile, or with the saved one when `new_profile` is `None`, and waits for the backend's verdict,
+/// unless `timed_out` or `canceled` resolve first.
+/// An activation still in progress then is stopped. A profile created for a connect that didn't succeed is deleted,
+/// while a saved one is kept, it may have been created externally.
+pub async fn connect_and_wait(
+    backend: &dyn Backend,
+    access_point: &AccessPoint,
+    new_profile: Option<NewProfile>,
+    timed_out: impl Future<Output = ()>,
+    canceled: impl Future<Output = ()>,
+) -> anyhow::Result<Verdict> {
+    let is_new = new_profile.is_some();
+    let (profile, active) = match new_profile {
+        Some(new) => {
+            let hidden = access_point.bssid.is_empty();
+            backend
+                .connect(access_point, new.password, hidden, new.eap, new.lock_bssid)
+                .await?
+        }
+        None => backend.connect_saved(access_point).await?,
+    };
+
+    let (timed_out, canceled) = (pin!(timed_out), pin!(canceled));
+    let ended = future::select(timed_out, canceled);
+    let verdict = match future::select(backend.connect_result(), ended).await {
+        Either::Left((Ok(0), _)) => return Ok(Verdict::Connected(profile)),
+        Either::Left((Ok(reason), _)) => Verdict::Failed(reason),
+        Either::Left((Err(e), _)) => {
+            eprintln!("Failed to follow the connection: {}", e);
+            Verdict::Canceled
+        }
+        Either::Right((Either::Left(_), _)) => Verdict::Failed(reason::CONNECT_TIMEOUT),
+        Either::Right((Either::Right(_), _)) => Verdict::Canceled,
+    };
+
+    // The backend is still activating, the device is freed for the next attempt.
+    if matches!(
+        verdict,
+        Verdict::Failed(reason::CONNECT_TIMEOUT) | Verdict::Canceled
+    ) && let Err(e) = backend.deactivate(&active).await
+    {
+        eprintln!("Failed to stop connecting: {}", e);
+    }
+    if is_new && let Err(e) = backend.forget_profile(&profile).await {
+        eprintln!("Failed to delete the profile: {}", e);
+    }
+    Ok(verdict)
+}
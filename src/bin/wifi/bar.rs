@@ -0,0 +1,118 @@
+// A long running status for bars, e.g. waybar, polybar or i3blocks.
+// A line is printed whenever the connection changes, with the same icons as the menu.
+use crate::{
+    backend::{AccessPointEvent, ConnectionState},
+    cli::{Value, json_object},
+    structure::*,
+};
+use futures_util::{StreamExt, stream};
+
+// The icons of the menu's radio row, and of a radio that isn't connected.
+const ICON_AIRPLANE: char = '󰀝';
+const ICON_OFF: char = '󰖪';
+const ICON_DISCONNECTED: char = '󰤮';
+
+pub async fn watch(pd: &PrivateData, json: bool) -> anyhow::Result<()> {
+    // The strength of every access point comes through, only the active one's is followed.
+    let strength = pd
+        .backend
+        .access_point_events()
+        .await?
+        .filter_map(|event| async move {
+            match event {
+                AccessPointEvent::Strength(path, _) => Some(Some(path)),
+                _ => None,
+            }
+        });
+    let mut changes = stream::select_all([
+        pd.backend.state_changes().await?.map(|_| None).boxed(),
+        pd.backend.radio_changes().await?.map(|_| None).boxed(),
+        strength.boxed(),
+    ]);
+
+    let mut last_line = String::new();
+    loop {
+        let (state, access_point) = read_status(pd).await?;
+        // A state change may repeat the last line, e.g. another device connected.
+        let line = format_line(pd, state, access_point.as_ref(), json);
+        if line != last_line {
+            println!("{}", line);
+            last_line = line;
+        }
+
+        // Waits for a change that may show, the strength of other access points doesn't.
+        loop {
+            match changes.next().await {
+                None => return Ok(()),
+                Some(Some(path)) if access_point.as_ref().is_none_or(|ap| ap.path != path) => {
+                    continue;
+                }
+                Some(_) => break,
+            }
+        }
+    }
+}
+
+async fn read_status(pd: &PrivateData) -> anyhow::Result<(&'static str, Option<AccessPoint>)> {
+    let radio = pd.backend.radio().await?;
+    if radio.airplane_mode() {
+        return Ok(("airplane", None));
+    } else if !radio.wifi {
+        return Ok(("off", None));
+    }
+
+    let state = match pd.backend.connection_state().await? {
+        ConnectionState::Connected => "connected",
+        ConnectionState::Connecting => "connecting",
+        ConnectionState::Disconnected => return Ok(("disconnected", None)),
+    };
+    let access_point = pd.backend.active_access_point().await?;
+    Ok((state, access_point))
+}
+
+fn format_line(
+    pd: &PrivateData,
+    state: &'static str,
+    access_point: Option<&AccessPoint>,
+    json: bool,
+) -> String {
+    let icon = match (state, access_point) {
+        ("airplane", _) => ICON_AIRPLANE,
+        ("off", _) => ICON_OFF,
+        (_, Some(ap)) => pd.icons.for_signal(ap.security.class, ap.signal_strength),
+        _ => ICON_DISCONNECTED,
+    };
+    let text = match access_point {
+        Some(ap) => format!("{}  {}", icon, ap.ssid),
+        None => icon.to_string(),
+    };
+    if !json {
+        return text;
+    }
+
+    // `text`, `tooltip`, `class` and `percentage` are the keys waybar reads.
+    let tooltip = match access_point {
+        Some(ap) => format!(
+            "{} · {} · {}% · {}",
+            ap.ssid,
+            ap.security.label(),
+            ap.signal_strength,
+            state
+        ),
+        None => state.to_string(),
+    };
+    let signal = access_point.map_or(0, |ap| ap.signal_strength);
+    json_object(&[
+        ("text", Value::Text(text)),
+        ("tooltip", Value::Text(tooltip)),
+        ("class", Value::Text(state.to_string())),
+        ("percentage", Value::Number(signal.into())),
+        ("state", Value::Text(state.to_string())),
+        (
+            "ssid",
+            Value::Text(access_point.map(|ap| ap.ssid.clone()).unwrap_or_default()),
+        ),
+        ("signal", Value::Number(signal.into())),
+        ("icon", Value::Text(icon.to_string())),
+    ])
+}
@@ -0,0 +1,341 @@
+// Subcommands for scripts and keybinds. They connect the same way as the plugin, so the profiles they
+// create and reuse are the ones the menu knows about.
+use crate::{
+    backend::{self, NewProfile, Verdict},
+    bar,
+    reason::{self, FailureAction},
+    structure::*,
+};
+use anyhow::{Context, anyhow, bail};
+use futures_util::future;
+
+pub const COMMANDS: [&str; 5] = ["list", "connect", "forget", "status", "watch"];
+
+pub enum Command {
+    List { json: bool },
+    Connect { ssid: String, password_stdin: bool },
+    Forget { ssid: String },
+    Status { json: bool },
+    Watch { json: bool },
+}
+
+impl Command {
+    /// `None` when the arguments don't fit the command.
+    pub fn parse(args: &[String]) -> Option<Command> {
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        let command = match args.as_slice() {
+            ["list"] => Command::List { json: false },
+            ["list", "--json"] => Command::List { json: true },
+            ["connect", ssid] => Command::Connect {
+                ssid: ssid.to_string(),
+                password_stdin: false,
+            },
+            ["connect", ssid, "--password-stdin"] | ["connect", "--password-stdin", ssid] => {
+                Command::Connect {
+                    ssid: ssid.to_string(),
+                    password_stdin: true,
+                }
+            }
+            ["forget", ssid] => Command::Forget {
+                ssid: ssid.to_string(),
+            },
+            ["status"] => Command::Status { json: false },
+            ["status", "--json"] => Command::Status { json: true },
+            ["watch"] => Command::Watch { json: false },
+            ["watch", "--json"] => Command::Watch { json: true },
+            _ => return None,
+        };
+        Some(command)
+    }
+}
+
+pub async fn run(command: Command, pd: &mut PrivateData, rescan: bool) -> anyhow::Result<()> {
+    match command {
+        Command::List { json } => list(pd, rescan, json).await,
+        Command::Connect {
+            ssid,
+            password_stdin,
+        } => connect(pd, &ssid, password_stdin).await,
+        Command::Forget { ssid } => forget(pd, &ssid).await,
+        Command::Status { json } => status(pd, json).await,
+        Command::Watch { json } => bar::watch(pd, json).await,
+    }
+}
+
+async fn refresh(pd: &mut PrivateData, rescan: bool) -> anyhow::Result<()> {
+    pd.radio = pd.backend.radio().await?;
+    // NetworkManager refuses to scan while the radio is off.
+    if rescan && pd.radio.wifi {
+        pd.backend.scan().await?;
+    }
+
+    let aps = pd.backend.access_points().await?;
+    let active_ap_bssid_opt = pd.backend.active_ap().await?;
+    pd.aps = aps;
+    pd.active_connection = None;
+    pd.set_connected(active_ap_bssid_opt);
+    pd.sort_accesspoints();
+    Ok(())
+}
+
+// A value of the output, written either as JSON or as plain text.
+pub enum Value {
+    Text(String),
+    Number(u32),
+    Bool(bool),
+    List(Vec<String>),
+}
+
+impl Value {
+    fn plain(&self) -> String {
+        match self {
+            Value::Text(text) => text.clone(),
+            Value::Number(number) => number.to_string(),
+            Value::Bool(flag) => flag.to_string(),
+            Value::List(items) => items.join(", "),
+        }
+    }
+
+    fn json(&self) -> String {
+        match self {
+            Value::Text(text) => json_string(text),
+            Value::Number(number) => number.to_string(),
+            Value::Bool(flag) => flag.to_string(),
+            Value::List(items) => {
+                let items: Vec<String> = items.iter().map(|item| json_string(item)).collect();
+                format!("[{}]", items.join(","))
+            }
+        }
+    }
+}
+
+// The output is flat enough to write the JSON by hand.
+fn json_string(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() + 2);
+    out.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c < ' ' => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+pub fn json_object(fields: &[(&str, Value)]) -> String {
+    let fields: Vec<String> = fields
+        .iter()
+        .map(|(key, value)| format!("{}:{}", json_string(key), value.json()))
+        .collect();
+    format!("{{{}}}", fields.join(","))
+}
+
+fn ap_fields(ap: &AccessPoint, connected: bool) -> Vec<(&'static str, Value)> {
+    vec![
+        ("ssid", Value::Text(ap.ssid.clone())),
+        ("bssid", Value::Text(ap.bssid.clone())),
+        ("signal", Value::Number(ap.signal_strength.into())),
+        ("frequency", Value::Number(ap.frequency)),
+        ("channel", Value::Number(ap.channel())),
+        ("security", Value::Text(ap.security.label().to_string())),
+        ("saved", Value::Bool(ap.setting_path.is_some())),
+        ("connected", Value::Bool(connected)),
+    ]
+}
+
+// One access point per line or per object, the connected one first, then by signal.
+async fn list(pd: &mut PrivateData, rescan: bool, json: bool) -> anyhow::Result<()> {
+    refresh(pd, rescan).await?;
+
+    let rows: Vec<Vec<(&str, Value)>> = pd
+        .aps
+        .iter()
+        .map(|ap| ap_fields(ap, pd.active_connection.as_ref() == Some(&ap.bssid)))
+        .collect();
+
+    if json {
+        let objects: Vec<String> = rows.iter().map(|fields| json_object(fields)).collect();
+        println!("[{}]", objects.join(","));
+    } else {
+        // Tab separated, so names with spaces stay a single field, e.g. for `cut -f1`.
+        for fields in rows {
+            let values: Vec<String> = fields.iter().map(|(_, value)| value.plain()).collect();
+            println!("{}", values.join("\t"));
+        }
+    }
+    Ok(())
+}
+
+// One line, without its newline, e.g. `pass wifi/home | wifi connect home --password-stdin`.
+fn read_password() -> anyhow::Result<String> {
+    let mut line = String::new();
+    std::io::stdin()
+        .read_line(&mut line)
+        .context("Failed to read the password from stdin")?;
+    Ok(line.trim_end_matches(['\n', '\r']).to_string())
+}
+
+// Connects like `state::set_mode_connecting_and_handle`, without asking anything.
+// A saved profile is reused unless a password is given, a new profile is then created as when the menu asks for it.
+async fn connect(pd: &mut PrivateData, ssid: &str, password_stdin: bool) -> anyhow::Result<()> {
+    if let Err(prompt) = validate_ssid(ssid) {
+        bail!("Invalid network name: {}", prompt.to_string_lossy());
+    }
+    let password = if password_stdin {
+        Some(read_password()?)
+    } else {
+        None
+    };
+    refresh(pd, false).await?;
+
+    // The connected or the strongest access point of the network, as the first one in the menu.
+    let access_point = pd
+        .aps
+        .iter()
+        .find(|ap| ap.ssid == ssid)
+        .with_context(|| format!("{} is not in range", ssid))?
+        .clone();
+    let security = access_point.security.class;
+
+    if password.is_none()
+        && pd
+            .aps
+            .iter()
+            .any(|ap| ap.ssid == ssid && pd.active_connection.as_ref() == Some(&ap.bssid))
+    {
+        println!("Already connected to {}", ssid);
+        return Ok(());
+    }
+
+    let password = password.filter(|_| security.needs_password());
+    if let Some(ref password) = password {
+        security.validate_password(password).map_err(|prompt| {
+            anyhow!(
+                "Invalid password for {}: {}",
+                ssid,
+                prompt.to_string_lossy()
+            )
+        })?;
+    }
+
+    let is_pre_existing = access_point.setting_path.is_some() && password.is_none();
+    if !is_pre_existing && security == Security::Enterprise {
+        bail!(
+            "{} is an enterprise network, connect to it from the menu once to save its credentials",
+            ssid
+        );
+    }
+    if !is_pre_existing && password.is_none() && security.needs_password() {
+        bail!("{} needs a password, pass it with --password-stdin", ssid);
+    }
+
+    eprintln!("Connecting to {}…", ssid);
+    let new_profile = (!is_pre_existing).then_some(NewProfile {
+        password,
+        eap: None,
+        lock_bssid: false,
+    });
+    let timeout = pd.connect_timeout;
+    let timed_out = async {
+        if timeout.is_zero() {
+            future::pending::<()>().await;
+        }
+        glib::timeout_future(timeout).await;
+    };
+    // Nothing cancels but Ctrl-C, which ends the process anyway.
+    let verdict = backend::connect_and_wait(
+        &*pd.backend,
+        &access_point,
+        new_profile,
+        timed_out,
+        future::pending(),
+    )
+    .await?;
+
+    let reason = match verdict {
+        Verdict::Connected(_) => {
+            println!("Connected to {}", ssid);
+            return Ok(());
+        }
+        Verdict::Failed(reason) => reason,
+        Verdict::Canceled => bail!("Gave up on connecting to {}", ssid),
+    };
+
+    let failure = reason::describe(reason);
+    let mut message = format!("Failed to connect to {}: {}", ssid, failure.message);
+    if reason != reason::CONNECT_TIMEOUT {
+        message.push_str(&format!(" (reason {})", reason));
+    }
+    // The secrets of a saved profile may be kept by a desktop's agent, which isn't asked here.
+    if is_pre_existing && failure.action == FailureAction::AskPassword {
+        message.push_str("\nPass the password with --password-stdin.");
+    }
+    bail!(message)
+}
+
+// Every profile of the network, like the forget key of the plugin.
+async fn forget(pd: &PrivateData, ssid: &str) -> anyhow::Result<()> {
+    let forgotten = pd.backend.forget(ssid).await?;
+    if forgotten == 0 {
+        bail!("{} is not saved", ssid);
+    }
+    println!("Forgot {}", ssid);
+    Ok(())
+}
+
+async fn status(pd: &mut PrivateData, json: bool) -> anyhow::Result<()> {
+    refresh(pd, false).await?;
+
+    let active_ap = pd
+        .active_connection
+        .as_ref()
+        .and_then(|bssid| pd.find_ap(bssid));
+    let state = if pd.radio.airplane_mode() {
+        "airplane"
+    } else if !pd.radio.wifi {
+        "off"
+    } else if pd.active_connection.is_some() {
+        "connected"
+    } else {
+        "disconnected"
+    };
+
+    let mut fields = vec![("state", Value::Text(state.to_string()))];
+    if let Some(ap) = active_ap {
+        // `connected` is already told by the state.
+        fields.extend(
+            ap_fields(ap, true)
+                .into_iter()
+                .filter(|(key, _)| *key != "connected"),
+        );
+    }
+    if pd.active_connection.is_some()
+        && let Some(details) = pd.backend.connection_details().await?
+    {
+        fields.push(("ipv4", Value::List(details.ipv4)));
+        fields.push(("ipv6", Value::List(details.ipv6)));
+        if let Some(gateway) = details.gateway4 {
+            fields.push(("gateway4", Value::Text(gateway)));
+        }
+        if let Some(gateway) = details.gateway6 {
+            fields.push(("gateway6", Value::Text(gateway)));
+        }
+        fields.push(("dns", Value::List(details.dns)));
+        fields.push(("bitrate", Value::Number(details.bitrate)));
+    }
+
+    if json {
+        println!("{}", json_object(&fields));
+    } else {
+        for (key, value) in fields {
+            println!("{}: {}", key, value.plain());
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,143 @@
+use anyhow::Context;
+use std::{
+    io::Write,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+// dmenu compatible launchers all read the rows from stdin and print the picked one, or what was typed, to stdout.
+// They only differ in how they are told the prompt, and to hide what is typed.
+struct Flavor {
+    program: &'static str,
+    /// Switches the launcher to its dmenu mode, for launchers that aren't dmenu only.
+    dmenu: &'static [&'static str],
+    prompt: &'static str,
+    /// Appended to the prompt, for launchers that don't separate it from the entry.
+    prompt_suffix: &'static str,
+    /// Hides what is typed, empty if the launcher can't.
+    password: &'static [&'static str],
+}
+
+const FLAVORS: [Flavor; 6] = [
+    Flavor {
+        program: "rofi",
+        dmenu: &["-dmenu"],
+        prompt: "-p",
+        prompt_suffix: "",
+        password: &["-password"],
+    },
+    Flavor {
+        program: "fuzzel",
+        dmenu: &["--dmenu"],
+        prompt: "--prompt",
+        prompt_suffix: ": ",
+        password: &["--password"],
+    },
+    Flavor {
+        program: "wofi",
+        dmenu: &["--dmenu"],
+        prompt: "--prompt",
+        prompt_suffix: "",
+        password: &["--password"],
+    },
+    Flavor {
+        program: "tofi",
+        dmenu: &[],
+        prompt: "--prompt-text",
+        prompt_suffix: ": ",
+        password: &["--hide-input", "true"],
+    },
+    Flavor {
+        program: "bemenu",
+        dmenu: &[],
+        prompt: "-p",
+        prompt_suffix: "",
+        password: &["-x", "indicator"],
+    },
+    Flavor {
+        program: "dmenu",
+        dmenu: &[],
+        prompt: "-p",
+        prompt_suffix: "",
+        password: &[],
+    },
+];
+
+pub struct Launcher {
+    program: String,
+    /// Passed after the flags of the flavor, so they can override them.
+    args: Vec<String>,
+    /// `None` for an unknown launcher, it's run with `args` only.
+    flavor: Option<&'static Flavor>,
+}
+
+impl Launcher {
+    /// `command` is the launcher along with its own arguments, `dmenu` when empty.
+    pub fn new(mut command: Vec<String>) -> Self {
+        let program = if command.is_empty() {
+            "dmenu".to_string()
+        } else {
+            command.remove(0)
+        };
+
+        let name = Path::new(&program)
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let flavor = FLAVORS.iter().find(|flavor| flavor.program == name);
+
+        Launcher {
+            program,
+            args: command,
+            flavor,
+        }
+    }
+
+    /// Shows `rows`, and returns the picked one or whatever was typed. `None` when dismissed, e.g. Esc.
+    pub fn pick(&self, prompt: &str, rows: &[String]) -> anyhow::Result<Option<String>> {
+        self.run(prompt, rows, false)
+    }
+
+    /// Asks for a line of text, hidden while typed if `secret` and the launcher supports it.
+    pub fn input(&self, prompt: &str, secret: bool) -> anyhow::Result<Option<String>> {
+        self.run(prompt, &[], secret)
+    }
+
+    fn run(&self, prompt: &str, rows: &[String], secret: bool) -> anyhow::Result<Option<String>> {
+        let mut command = Command::new(&self.program);
+        if let Some(flavor) = self.flavor {
+            command
+                .args(flavor.dmenu)
+                .arg(flavor.prompt)
+                .arg(format!("{}{}", prompt, flavor.prompt_suffix));
+            if secret {
+                command.args(flavor.password);
+            }
+        }
+        command
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped());
+
+        let mut child = command
+            .spawn()
+            .with_context(|| format!("Failed to run {}", self.program))?;
+
+        // Dropped once written, so the launcher sees the end of the list.
+        let mut stdin = child.stdin.take().context("Launcher has no stdin")?;
+        for row in rows {
+            writeln!(stdin, "{}", row)?;
+        }
+        drop(stdin);
+
+        let output = child.wait_with_output()?;
+        // Dismissing exits with a non zero status.
+        if !output.status.success() {
+            return Ok(None);
+        }
+
+        // Only the newline is trimmed, a password may end with spaces.
+        let picked = String::from_utf8_lossy(&output.stdout);
+        Ok(Some(picked.trim_end_matches(['\n', '\r']).to_string()))
+    }
+}
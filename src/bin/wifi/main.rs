@@ -0,0 +1,103 @@
+// The Wi-Fi handling of the rofi plugin outside of rofi, as a menu for dmenu compatible launchers,
+// e.g. fuzzel, wofi, tofi, bemenu or `rofi -dmenu`, and as subcommands for scripts.
+//
+// Built on the core of the library, so it builds without rofi, i.e. with `--no-default-features`.
+mod bar;
+mod cli;
+mod launcher;
+mod menu;
+mod rasi;
+
+use cli::Command;
+use launcher::Launcher;
+use menu::Menu;
+use std::{path::Path, process::ExitCode, time::Duration};
+use structure::PrivateData;
+use wifi::{backend, reason, state, structure, view};
+
+const USAGE: &str =
+    "Usage: wifi [-backend <name>] [-iface <interface>] [-timeout <seconds>] [-rescan] [-config <file>] [launcher [args...]]
+       wifi [-backend <name>] [-iface <interface>] [-timeout <seconds>] [-rescan] [-config <file>] <command>
+
+Commands:
+  list [--json]                      The access points in range
+  connect <ssid> [--password-stdin]  Connects, the password is read from stdin
+  forget <ssid>                      Deletes the saved profiles of the network
+  status [--json]                    The state of the connection
+  watch [--json]                     The state again on each change, for bars";
+
+fn main() -> ExitCode {
+    let mut args = std::env::args().skip(1).peekable();
+    let mut backend_name = None;
+    let mut iface = None;
+    let mut timeout = None;
+    let mut rescan = false;
+    let mut config = None;
+
+    // The plugin's own flags come first, then either a command or the launcher.
+    while let Some(arg) = args.next_if(|arg| arg.starts_with('-')) {
+        match arg.as_str() {
+            "-backend" => backend_name = args.next(),
+            "-iface" => iface = args.next(),
+            "-timeout" => timeout = args.next().and_then(|secs| secs.parse().ok()),
+            "-rescan" => rescan = true,
+            "-config" => config = args.next(),
+            _ => {
+                eprintln!("{}", USAGE);
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    let rest: Vec<String> = args.collect();
+
+    let command = match rest.first() {
+        Some(first) if cli::COMMANDS.contains(&first.as_str()) => match Command::parse(&rest) {
+            Some(command) => Some(command),
+            None => {
+                eprintln!("{}", USAGE);
+                return ExitCode::FAILURE;
+            }
+        },
+        _ => None,
+    };
+
+    let glib_context = glib::MainContext::default();
+    let result = glib_context.block_on(async {
+        let icons = rasi::load_icons(config.as_deref().map(Path::new))?;
+        let Some(command) = command else {
+            let mut menu = Menu::new(
+                Launcher::new(rest),
+                backend_name.as_deref(),
+                iface.as_deref(),
+            )
+            .await?;
+            {
+                let mut pd = menu.pd();
+                pd.icons = icons;
+                if let Some(timeout) = timeout {
+                    pd.connect_timeout = Duration::from_secs(timeout);
+                }
+            }
+            if rescan {
+                menu.rescan();
+            }
+            return menu.run().await;
+        };
+
+        let backend = backend::setup(backend_name.as_deref(), iface.as_deref()).await?;
+        let mut pd = PrivateData::new(backend, Vec::new());
+        pd.icons = icons;
+        if let Some(timeout) = timeout {
+            pd.connect_timeout = Duration::from_secs(timeout);
+        }
+        cli::run(command, &mut pd, rescan).await
+    });
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
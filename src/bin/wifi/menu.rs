@@ -0,0 +1,278 @@
+// The plugin's menu through a dmenu compatible launcher.
+// The launcher is run once per prompt, and its answer is handed to the plugin's state machine, like rofi's would be.
+use crate::{
+    backend,
+    launcher::Launcher,
+    state,
+    structure::*,
+    view::{PromptView, View},
+};
+use futures_channel::mpsc;
+use futures_util::{StreamExt, future};
+use std::{
+    cell::{RefCell, RefMut},
+    ffi::CString,
+    rc::Rc,
+    task::Poll,
+};
+
+pub struct Menu {
+    view: Rc<RefCell<PromptView>>,
+    pd: SharedData,
+    launcher: Launcher,
+    changes: mpsc::UnboundedReceiver<()>,
+}
+
+impl Menu {
+    pub async fn new(
+        launcher: Launcher,
+        backend_name: Option<&str>,
+        iface: Option<&str>,
+    ) -> anyhow::Result<Self> {
+        let backend = backend::setup(backend_name, iface).await?;
+        let aps = backend.access_points().await?;
+        let active_ap_bssid_opt = backend.active_ap().await?;
+        // Only the switch row depends on it, so a failure is not fatal.
+        let radio = backend
+            .radio()
+            .await
+            .inspect_err(|e| eprintln!("Failed to read the radio state: {}", e))
+            .unwrap_or_default();
+
+        // Without the agent, profiles lacking secrets simply fail with NO_SECRETS.
+        let secret_events = backend
+            .register_agent()
+            .await
+            .inspect_err(|e| eprintln!("Failed to register the secret agent: {}", e))
+            .ok();
+
+        let mut pd = PrivateData::new(backend, aps);
+        pd.radio = radio;
+        pd.set_connected(active_ap_bssid_opt);
+        pd.sort_accesspoints();
+        // Several Wi-Fi interfaces, e.g. internal card and USB dongle, the user picks one first.
+        let prompt = if iface.is_none() && pd.backend.interfaces().len() > 1 {
+            pd.state = AppState::InterfaceSelect;
+            c"interface".into()
+        } else {
+            pd.display_name.clone()
+        };
+
+        let (view, changes) = PromptView::new(pd, prompt);
+        let pd = view.private_data().expect("The view owns the private data");
+        let view = Rc::new(RefCell::new(view));
+
+        // The list follows the backend from then on, like the plugin's, rather than being read again for each prompt.
+        let glib_context = glib::MainContext::default();
+        glib_context.spawn_local(state::access_point_events_task(view.clone()));
+        glib_context.spawn_local(state::radio_task(view.clone()));
+        let connection_view = view.clone();
+        glib_context.spawn_local(async move {
+            let _ = state::connection_background_task(connection_view).await;
+        });
+        if let Some(events) = secret_events {
+            glib_context.spawn_local(state::secret_agent_task(view.clone(), events));
+        }
+
+        Ok(Menu {
+            view,
+            pd,
+            launcher,
+            changes,
+        })
+    }
+
+    pub fn pd(&self) -> RefMut<'_, PrivateData> {
+        self.pd.borrow_mut()
+    }
+
+    // Rescans before the list is first shown, the menu waits for it like for a connect.
+    // Does nothing with the radio off.
+    pub fn rescan(&self) {
+        state::spawn_scan(self.view.clone());
+    }
+
+    pub async fn run(&mut self) -> anyhow::Result<()> {
+        // The network being connected, to report the outcome once the state machine is done with it.
+        let mut connecting: Option<String> = None;
+
+        loop {
+            // The launcher can't be updated while open, so the tasks catch up before it's shown,
+            // e.g. with the access points found meanwhile, a scan just started or a secret request.
+            yield_now().await;
+
+            // Driven by the backend, the launcher is shown again once the state machine moves on.
+            let driven = {
+                let pd = self.pd.borrow();
+                if let Some(ssid) =
+                    connecting.take_if(|_| !matches!(pd.state, AppState::Connecting(_)))
+                {
+                    if let Some(failure) = pd.last_failure {
+                        eprintln!("{}", failure.message);
+                    } else if pd.state == AppState::Idle && pd.active_connection.is_some() {
+                        println!("Connected to {}", ssid);
+                        return Ok(());
+                    } else if pd.state != AppState::Idle {
+                        // The secret agent asks for the password meanwhile.
+                        connecting = Some(ssid);
+                    }
+                }
+                if let AppState::Connecting(ref bssid) = pd.state
+                    && connecting.is_none()
+                {
+                    let ssid = pd
+                        .find_ap(bssid)
+                        .map(|ap| ap.ssid.clone())
+                        .unwrap_or_default();
+                    eprintln!("Connecting to {}…", ssid);
+                    connecting = Some(ssid);
+                }
+                matches!(pd.state, AppState::Connecting(_) | AppState::Scanning)
+            };
+            if driven {
+                self.changes.next().await;
+                continue;
+            }
+
+            let pd = self.pd.borrow();
+            let prompt = self.view.borrow().prompt.to_string_lossy().to_string();
+            let (selected_line, input) = match pd.state {
+                AppState::Connecting(_) | AppState::Scanning => continue,
+                AppState::Idle => {
+                    let rows: Vec<String> = pd.rows.iter().map(|row| row_label(&pd, row)).collect();
+                    drop(pd);
+                    let Some(picked) = self.launcher.pick(&prompt, &rows)? else {
+                        return Ok(());
+                    };
+                    (rows.iter().position(|row| *row == picked), picked)
+                }
+                AppState::InterfaceSelect => {
+                    let interfaces = pd.backend.interfaces().to_vec();
+                    drop(pd);
+                    let Some(picked) = self.launcher.pick(&prompt, &interfaces)? else {
+                        return Ok(());
+                    };
+                    (interfaces.iter().position(|iface| *iface == picked), picked)
+                }
+                AppState::EnterpriseInput {
+                    step: EapStep::Method,
+                    ..
+                } => {
+                    drop(pd);
+                    let methods: Vec<String> = EAP_METHODS.iter().map(|m| m.to_string()).collect();
+                    let Some(picked) = self.launcher.pick(&prompt, &methods)? else {
+                        self.dismiss_input();
+                        continue;
+                    };
+                    (methods.iter().position(|m| *m == picked), picked)
+                }
+                AppState::HiddenInput(HiddenStep::Security { .. }) => {
+                    drop(pd);
+                    let labels: Vec<String> = HIDDEN_SECURITY
+                        .iter()
+                        .map(|(_, label)| label.to_string())
+                        .collect();
+                    let Some(picked) = self.launcher.pick(&prompt, &labels)? else {
+                        self.dismiss_input();
+                        continue;
+                    };
+                    (labels.iter().position(|label| *label == picked), picked)
+                }
+                AppState::PasswordInput { .. }
+                | AppState::EnterpriseInput { .. }
+                | AppState::HiddenInput(HiddenStep::Ssid) => {
+                    let is_secret = pd.is_secret_input();
+                    drop(pd);
+                    let Some(typed) = self.launcher.input(&prompt, is_secret)? else {
+                        self.dismiss_input();
+                        continue;
+                    };
+                    (None, typed)
+                }
+            };
+
+            // Selecting the connected network disconnects from it, which is all the menu is for then.
+            let pd = self.pd.borrow();
+            let disconnecting = (pd.state == AppState::Idle)
+                .then(|| selected_line.and_then(|line| pd.row_ap(line)))
+                .flatten()
+                .map(|index| &pd.aps[index])
+                .filter(|ap| pd.active_connection.as_ref() == Some(&ap.bssid))
+                .map(|ap| ap.ssid.clone());
+            drop(pd);
+
+            // Anything typed that matches no row is a custom input, e.g. the name of a hidden network.
+            let input = CString::new(input).unwrap_or_default();
+            state::handle_state(
+                self.view.clone(),
+                selected_line.unwrap_or(usize::MAX),
+                &self.pd,
+                &input,
+            );
+
+            if let Some(ssid) = disconnecting
+                && self.pd.borrow().active_connection.is_none()
+            {
+                println!("Disconnected from {}", ssid);
+                return Ok(());
+            }
+        }
+    }
+
+    fn dismiss_input(&self) {
+        state::dismiss_input(&mut *self.view.borrow_mut(), &mut self.pd.borrow_mut());
+    }
+}
+
+// Returns to the main context once, so the tasks ready meanwhile run first.
+async fn yield_now() {
+    let mut yielded = false;
+    future::poll_fn(|cx| {
+        if yielded {
+            return Poll::Ready(());
+        }
+        yielded = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    })
+    .await
+}
+
+// The plugin's rows as plain text, launchers don't agree on markup.
+fn row_label(pd: &PrivateData, row: &Row) -> String {
+    match row {
+        Row::Radio if pd.radio.airplane_mode() => "󰀝  Airplane mode on".into(),
+        Row::Radio if pd.radio.wifi => "󰖩  Wi-Fi on".into(),
+        Row::Radio => "󰖪  Wi-Fi off".into(),
+        Row::Hidden => "󰈉  Connect to hidden network…".into(),
+        Row::Group(_) | Row::Member(_) => {
+            let members: Vec<&AccessPoint> = row
+                .indices()
+                .iter()
+                .filter_map(|&i| pd.aps.get(i))
+                .collect();
+            let Some(ap) = members.iter().max_by_key(|ap| ap.signal_strength) else {
+                return String::new();
+            };
+
+            let icon = pd.icons.for_signal(ap.security.class, ap.signal_strength);
+            // Hidden access points don't share a name, so they are told apart by their BSSID.
+            let ssid = if ap.ssid.is_empty() {
+                format!("[hidden] {}", ap.bssid)
+            } else {
+                ap.ssid.clone()
+            };
+
+            let mut label = format!("{icon}  {ssid}  {}", ap.security.label());
+            if members.len() > 1 {
+                label.push_str(&format!(" · {} APs", members.len()));
+            }
+            if let Some(ref bssid) = pd.active_connection
+                && members.iter().any(|ap| ap.bssid == *bssid)
+            {
+                label.push_str("  (connected)");
+            }
+            label
+        }
+    }
+}
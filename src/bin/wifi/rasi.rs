@@ -0,0 +1,183 @@
+// The plugin reads its settings through rofi, out of the `wifi` section of rofi's config.
+// Outside of rofi, the icons are picked out of the config file itself, so the menu and the bar show the plugin's icons.
+// Only that file is read, `@import`ed files aren't followed.
+use crate::structure::WiFiIcon;
+use anyhow::Context;
+use std::path::{Path, PathBuf};
+
+// The config rofi reads when it isn't given one.
+fn default_config_path() -> Option<PathBuf> {
+    let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_dir.join("rofi").join("config.rasi"))
+}
+
+/// The icons of the config at `path`, or of rofi's default config. The defaults are kept for the ones it lacks.
+pub fn load_icons(path: Option<&Path>) -> anyhow::Result<WiFiIcon> {
+    let mut icons = WiFiIcon::default();
+    let config = match path {
+        Some(path) => std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?,
+        // Rofi runs without a config as well.
+        None => match default_config_path().and_then(|path| std::fs::read_to_string(path).ok()) {
+            Some(config) => config,
+            None => return Ok(icons),
+        },
+    };
+    let config = strip_comments(&config);
+    let Some(section) = wifi_section(&config) else {
+        return Ok(icons);
+    };
+
+    for (property, set) in [
+        ("icon-open", &mut icons.open),
+        ("icon-psk", &mut icons.psk),
+        ("icon-owe", &mut icons.owe),
+        ("icon-wep", &mut icons.wep),
+        ("icon-sae", &mut icons.sae),
+        ("icon-eap", &mut icons.eap),
+    ] {
+        // Five icons must be provided, as with the plugin.
+        if let Some(values) = find_array(section, property).filter(|arr| arr.len() == 5) {
+            *set = values
+                .iter()
+                .map(|x| x.chars().next().unwrap_or('￼'))
+                .collect();
+        }
+    }
+    Ok(icons)
+}
+
+// The config without its `//` and `/* */` comments, the strings are kept as they are.
+fn strip_comments(config: &str) -> String {
+    let mut stripped = String::with_capacity(config.len());
+    let mut chars = config.chars().peekable();
+    let mut in_string = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => in_string = !in_string,
+            '\\' if in_string => {
+                stripped.push(c);
+                stripped.extend(chars.next());
+                continue;
+            }
+            '/' if !in_string && chars.peek() == Some(&'/') => {
+                while chars.next_if(|&c| c != '\n').is_some() {}
+                continue;
+            }
+            '/' if !in_string && chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut previous = None;
+                for c in chars.by_ref() {
+                    if previous == Some('*') && c == '/' {
+                        break;
+                    }
+                    previous = Some(c);
+                }
+                // Keeps the tokens on both sides apart.
+                stripped.push(' ');
+                continue;
+            }
+            _ => {}
+        }
+        stripped.push(c);
+    }
+    stripped
+}
+
+// The body of the `wifi { ... }` section, wherever it's nested, e.g. in `configuration { ... }`.
+fn wifi_section(config: &str) -> Option<&str> {
+    for (start, _) in config.match_indices("wifi") {
+        // Part of another name, e.g. `icon-wifi`.
+        if config[..start]
+            .chars()
+            .next_back()
+            .is_some_and(|c| c.is_alphanumeric() || c == '-' || c == '_')
+        {
+            continue;
+        }
+        // Not a section, e.g. `modi: "drun,wifi";`.
+        let Some(body) = config[start + "wifi".len()..]
+            .trim_start()
+            .strip_prefix('{')
+        else {
+            continue;
+        };
+
+        let mut depth = 0;
+        let mut in_string = false;
+        for (end, c) in body.char_indices() {
+            match c {
+                '"' => in_string = !in_string,
+                '{' if !in_string => depth += 1,
+                '}' if !in_string && depth == 0 => return Some(&body[..end]),
+                '}' if !in_string => depth -= 1,
+                _ => {}
+            }
+        }
+        return None;
+    }
+    None
+}
+
+// The strings of an array property of a section, e.g. `icon-psk: [ "󰤪", "󰤧", "󰤤", "󰤡", "󰤬"];`.
+fn find_array(section: &str, property: &str) -> Option<Vec<String>> {
+    for (start, _) in section.match_indices(property) {
+        // Part of another name, e.g. `xicon-psk`.
+        if section[..start]
+            .chars()
+            .next_back()
+            .is_some_and(|c| !(c.is_whitespace() || c == ';' || c == '{'))
+        {
+            continue;
+        }
+
+        let rest = section[start + property.len()..].trim_start();
+        let Some(rest) = rest.strip_prefix(':') else {
+            continue;
+        };
+        let Some(rest) = rest.trim_start().strip_prefix('[') else {
+            continue;
+        };
+        let array = &rest[..rest.find(']')?];
+        // Every other piece between the quotes is a value.
+        return Some(
+            array
+                .split('"')
+                .skip(1)
+                .step_by(2)
+                .map(str::to_string)
+                .collect(),
+        );
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn icons_are_read_from_the_wifi_section_only() {
+        let config = strip_comments(
+            r#"
+            configuration {
+                modi: "drun,wifi";
+                // icon-psk: [ "a", "a", "a", "a", "a"];
+                /* wifi {
+                    icon-psk: [ "b", "b", "b", "b", "b"];
+                } */
+            }
+            other { icon-psk: [ "c", "c", "c", "c", "c"]; }
+            wifi {
+                /* icon-psk: [ "d", "d", "d", "d", "d"]; */
+                icon-psk: [ "e", "e", "e", "e", "e"]; // icon-psk: [ "f" ];
+            }
+            "#,
+        );
+        let section = wifi_section(&config).unwrap();
+        assert_eq!(find_array(section, "icon-psk").unwrap(), ["e"; 5]);
+        assert_eq!(find_array(section, "icon-open"), None);
+    }
+}
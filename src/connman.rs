@@ -0,0 +1,830 @@
+use crate::{
+    backend::{AccessPointEvent, Backend, ConnectionState},
+    secret_agent::{SecretAgentEvent, SecretRequest},
+    structure::*,
+    utils,
+};
+use anyhow::{Context, bail};
+use futures_channel::{mpsc, oneshot};
+use futures_util::{
+    FutureExt, StreamExt,
+    future::LocalBoxFuture,
+    stream::{self, BoxStream},
+};
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use zbus::{
+    Connection, MatchRule, MessageStream, Proxy,
+    zvariant::{Dict, ObjectPath, OwnedObjectPath, OwnedValue, Value},
+};
+
+// ConnMan, on embedded and ConnMan based desktops.
+// https://git.kernel.org/pub/scm/network/connman/connman.git/tree/doc
+//
+// ConnMan lists networks as services, one per network like iwd, which stand for the profiles as well once saved,
+// i.e. favorite. Passphrases are handed over through the agent, like iwd.
+
+const CONNMAN: &str = "net.connman";
+const MANAGER: &str = "net.connman.Manager";
+const TECHNOLOGY: &str = "net.connman.Technology";
+const SERVICE: &str = "net.connman.Service";
+const WIFI_TECHNOLOGY: &str = "/net/connman/technology/wifi";
+const SERVICE_PATHS: &str = "/net/connman/service";
+
+pub const AGENT_PATH: &str = "/io/github/bjn7/rofi_wifi/connman_agent";
+
+type Services = Vec<(OwnedObjectPath, HashMap<String, OwnedValue>)>;
+
+pub struct ConnMan {
+    con: Connection,
+    iface: String,
+    interfaces: Vec<String>,
+    agent: Arc<Mutex<AgentState>>,
+    // The Connect call of the connect in progress, awaited by `connect_result`.
+    pending: RefCell<Option<LocalBoxFuture<'static, u32>>>,
+}
+
+impl std::fmt::Debug for ConnMan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ConnMan")
+            .field("iface", &self.iface)
+            .finish_non_exhaustive()
+    }
+}
+
+// Shared with the agent, which zbus serves from its own thread.
+#[derive(Debug, Default)]
+struct AgentState {
+    /// The passphrase of the connect in progress, handed over to ConnMan's request.
+    password: Option<String>,
+    /// The name of the hidden network being connected.
+    name: Option<String>,
+    /// The service of the request forwarded to the prompt, ConnMan's Cancel doesn't tell it.
+    service: Option<OwnedObjectPath>,
+    events: Option<mpsc::UnboundedSender<SecretAgentEvent>>,
+}
+
+// Without an interface, the last picked one is used, otherwise the first found.
+pub async fn setup(iface: Option<&str>) -> anyhow::Result<ConnMan> {
+    let con = Connection::system().await?;
+    let mut interfaces = wifi_interfaces(&services(&con).await?);
+
+    // The last picked interface goes first.
+    if let Some(last_iface) = utils::load_last_interface()
+        && let Some(index) = interfaces.iter().position(|name| *name == last_iface)
+    {
+        let interface = interfaces.remove(index);
+        interfaces.insert(0, interface);
+    }
+
+    // ConnMan only tells the interfaces through their services, there are none with the radio off.
+    let iface_name = match iface {
+        Some(iface) if interfaces.is_empty() => iface.to_string(),
+        Some(iface) => interfaces
+            .iter()
+            .find(|name| *name == iface)
+            .cloned()
+            .with_context(|| format!("Wi-Fi interface {} not found", iface))?,
+        None => interfaces.first().cloned().unwrap_or_default(),
+    };
+
+    // Registered right away, the passphrase of every new network goes through it.
+    let agent = Arc::new(Mutex::new(AgentState::default()));
+    con.object_server()
+        .at(
+            AGENT_PATH,
+            Agent {
+                con: con.clone(),
+                state: Arc::clone(&agent),
+            },
+        )
+        .await?;
+    let manager = Proxy::new(&con, CONNMAN, "/", MANAGER).await?;
+    let _: () = manager
+        .call("RegisterAgent", &(ObjectPath::try_from(AGENT_PATH)?))
+        .await?;
+
+    Ok(ConnMan {
+        con,
+        iface: iface_name,
+        interfaces,
+        agent,
+        pending: RefCell::new(None),
+    })
+}
+
+async fn services(con: &Connection) -> anyhow::Result<Services> {
+    let manager = Proxy::new(con, CONNMAN, "/", MANAGER).await?;
+    Ok(manager.call("GetServices", &()).await?)
+}
+
+fn text<'a>(properties: &'a HashMap<String, OwnedValue>, key: &str) -> Option<&'a str> {
+    properties.get(key)?.try_into().ok()
+}
+
+fn dict(properties: &HashMap<String, OwnedValue>, key: &str) -> HashMap<String, OwnedValue> {
+    properties
+        .get(key)
+        .and_then(|value| HashMap::try_from(value.clone()).ok())
+        .unwrap_or_default()
+}
+
+fn strings(properties: &HashMap<String, OwnedValue>, key: &str) -> Vec<String> {
+    properties
+        .get(key)
+        .and_then(|value| Vec::try_from(value.clone()).ok())
+        .unwrap_or_default()
+}
+
+// The interface a service belongs to, out of its `Ethernet` settings.
+fn service_iface(properties: &HashMap<String, OwnedValue>) -> Option<String> {
+    text(&dict(properties, "Ethernet"), "Interface").map(str::to_string)
+}
+
+fn is_wifi(properties: &HashMap<String, OwnedValue>) -> bool {
+    text(properties, "Type") == Some("wifi")
+}
+
+// The names of the interfaces with any Wi-Fi service, in order.
+fn wifi_interfaces(services: &Services) -> Vec<String> {
+    let mut interfaces: Vec<String> = services
+        .iter()
+        .filter(|(_, properties)| is_wifi(properties))
+        .filter_map(|(_, properties)| service_iface(properties))
+        .collect();
+    interfaces.sort();
+    interfaces.dedup();
+    interfaces
+}
+
+// ConnMan doesn't tell WPA3 apart from WPA2 nor OWE from open.
+fn security_of(security: &[String]) -> Security {
+    if security.iter().any(|s| s == "ieee8021x") {
+        Security::Enterprise
+    } else if security.iter().any(|s| s == "psk") {
+        Security::WpaPsk
+    } else if security.iter().any(|s| s == "wep") {
+        Security::Wep
+    } else {
+        Security::Open
+    }
+}
+
+// A service as an access point. ConnMan keeps the BSSIDs to itself, so the service's object path stands for it,
+// and the frequency is unknown. A hidden network has no name, like NetworkManager's.
+fn service_access_point(
+    path: OwnedObjectPath,
+    properties: &HashMap<String, OwnedValue>,
+) -> AccessPoint {
+    let signal_strength = properties
+        .get("Strength")
+        .and_then(|value| u8::try_from(value).ok())
+        .unwrap_or_default();
+    let favorite = properties
+        .get("Favorite")
+        .and_then(|value| bool::try_from(value).ok())
+        .unwrap_or_default();
+
+    AccessPoint {
+        ssid: text(properties, "Name").unwrap_or_default().to_string(),
+        bssid: path.to_string(),
+        frequency: 0,
+        signal_strength,
+        security: SecurityDescriptor::from_class(security_of(&strings(properties, "Security"))),
+        setting_path: favorite.then(|| path.clone()),
+        path,
+    }
+}
+
+fn service_state(properties: &HashMap<String, OwnedValue>) -> ConnectionState {
+    match text(properties, "State") {
+        Some("ready" | "online") => ConnectionState::Connected,
+        Some("association" | "configuration") => ConnectionState::Connecting,
+        _ => ConnectionState::Disconnected,
+    }
+}
+
+// ConnMan's errors as the NMDeviceStateReason they are closest to.
+// A failed Connect only tells `Failed`, the service's `Error` tells why.
+fn failure_reason(error: &zbus::Error, service_error: Option<&str>) -> u32 {
+    let zbus::Error::MethodError(name, _, _) = error else {
+        // NM_DEVICE_STATE_REASON_UNKNOWN
+        return 1;
+    };
+    match (name.as_str(), service_error) {
+        ("net.connman.Error.AlreadyConnected", _) => 0,
+        (_, Some("invalid-key")) => 7,
+        (_, Some("auth-failed" | "connect-failed")) => 8,
+        (_, Some("dhcp-failed")) => 16,
+        (_, Some("out-of-range")) => 53,
+        ("net.connman.Error.OperationAborted", _) => 39,
+        ("net.connman.Error.InvalidArguments", _) => 9,
+        ("net.connman.Error.OperationTimeout", _) => 11,
+        _ => 1,
+    }
+}
+
+// `192.168.1.0` style netmasks as a prefix length.
+fn prefix_length(netmask: &str) -> u32 {
+    netmask
+        .split('.')
+        .filter_map(|octet| octet.parse::<u8>().ok())
+        .map(u8::count_ones)
+        .sum()
+}
+
+impl ConnMan {
+    // The Wi-Fi services of the interface, the connected one first, then by signal, as ConnMan sorts them.
+    async fn wifi_services(&self) -> anyhow::Result<Services> {
+        Ok(services(&self.con)
+            .await?
+            .into_iter()
+            .filter(|(_, properties)| {
+                is_wifi(properties)
+                    && (self.iface.is_empty()
+                        || service_iface(properties).as_deref() == Some(self.iface.as_str()))
+            })
+            .collect())
+    }
+
+    // The service that is connected, or connecting.
+    async fn active_service(&self) -> anyhow::Result<Option<(OwnedObjectPath, ConnectionState)>> {
+        Ok(self
+            .wifi_services()
+            .await?
+            .into_iter()
+            .map(|(path, properties)| (path, service_state(&properties)))
+            .find(|(_, state)| *state != ConnectionState::Disconnected))
+    }
+
+    async fn technology(&self) -> zbus::Result<Proxy<'static>> {
+        Proxy::new(&self.con, CONNMAN, WIFI_TECHNOLOGY, TECHNOLOGY).await
+    }
+
+    // Yields whenever one of `properties` of any service changes.
+    async fn service_changes(
+        &self,
+        properties: &'static [&'static str],
+    ) -> anyhow::Result<BoxStream<'static, ()>> {
+        let rule = MatchRule::builder()
+            .msg_type(zbus::message::Type::Signal)
+            .sender(CONNMAN)?
+            .interface(SERVICE)?
+            .member("PropertyChanged")?
+            .path_namespace(SERVICE_PATHS)?
+            .build();
+        Ok(MessageStream::for_match_rule(rule, &self.con, None)
+            .await?
+            .filter_map(move |msg| async move {
+                let (name, _): (String, OwnedValue) = msg.ok()?.body().deserialize().ok()?;
+                properties.contains(&name.as_str()).then_some(())
+            })
+            .boxed())
+    }
+
+    // Starts a Connect, awaited later by `connect_result`, so it can be timed out and canceled meanwhile.
+    async fn start_connect(
+        &self,
+        service: OwnedObjectPath,
+        password: Option<String>,
+        name: Option<String>,
+    ) -> anyhow::Result<(OwnedObjectPath, OwnedObjectPath)> {
+        {
+            let mut agent = self.agent.lock().unwrap();
+            agent.password = password;
+            agent.name = name;
+        }
+
+        let proxy = Proxy::new(&self.con, CONNMAN, service.clone(), SERVICE).await?;
+        *self.pending.borrow_mut() = Some(
+            async move {
+                let Err(e) = proxy.call::<_, _, ()>("Connect", &()).await else {
+                    return 0;
+                };
+                let service_error: Option<String> = proxy.get_property("Error").await.ok();
+                failure_reason(&e, service_error.as_deref())
+            }
+            .boxed_local(),
+        );
+
+        // The service is the profile too, and it's disconnected to stop the connect.
+        Ok((service.clone(), service))
+    }
+}
+
+impl Backend for ConnMan {
+    fn interfaces(&self) -> &[String] {
+        &self.interfaces
+    }
+
+    fn iface(&self) -> &str {
+        &self.iface
+    }
+
+    fn select_interface<'a>(
+        &'a self,
+        iface: &'a str,
+    ) -> LocalBoxFuture<'a, anyhow::Result<Box<dyn Backend>>> {
+        async move {
+            let interfaces = wifi_interfaces(&services(&self.con).await?);
+            if !interfaces.iter().any(|name| name == iface) {
+                bail!("Wi-Fi interface {} not found", iface);
+            }
+            Ok(Box::new(ConnMan {
+                con: self.con.clone(),
+                iface: iface.to_string(),
+                interfaces,
+                agent: Arc::clone(&self.agent),
+                // A connect in progress stays with the interface it was started on.
+                pending: RefCell::new(None),
+            }) as Box<dyn Backend>)
+        }
+        .boxed_local()
+    }
+
+    // ConnMan's offline mode is airplane mode, it turns every technology off.
+    fn radio(&self) -> LocalBoxFuture<'_, anyhow::Result<Radio>> {
+        async move {
+            let manager = Proxy::new(&self.con, CONNMAN, "/", MANAGER).await?;
+            let manager_properties: HashMap<String, OwnedValue> =
+                manager.call("GetProperties", &()).await?;
+            let offline = manager_properties
+                .get("OfflineMode")
+                .and_then(|value| bool::try_from(value).ok())
+                .unwrap_or_default();
+            if offline {
+                return Ok(Radio {
+                    wifi: false,
+                    wwan: false,
+                });
+            }
+
+            let technology_properties: HashMap<String, OwnedValue> =
+                self.technology().await?.call("GetProperties", &()).await?;
+            let wifi = technology_properties
+                .get("Powered")
+                .and_then(|value| bool::try_from(value).ok())
+                .unwrap_or_default();
+            Ok(Radio { wifi, wwan: true })
+        }
+        .boxed_local()
+    }
+
+    fn set_radio(&self, radio: Radio) -> LocalBoxFuture<'_, anyhow::Result<()>> {
+        async move {
+            let manager = Proxy::new(&self.con, CONNMAN, "/", MANAGER).await?;
+            let offline = radio.airplane_mode();
+            let _: () = manager
+                .call("SetProperty", &("OfflineMode", Value::from(offline)))
+                .await?;
+            if !offline {
+                let _: () = self
+                    .technology()
+                    .await?
+                    .call("SetProperty", &("Powered", Value::from(radio.wifi)))
+                    .await?;
+            }
+            Ok(())
+        }
+        .boxed_local()
+    }
+
+    fn radio_changes(&self) -> LocalBoxFuture<'_, anyhow::Result<BoxStream<'static, ()>>> {
+        async move {
+            let manager = Proxy::new(&self.con, CONNMAN, "/", MANAGER).await?;
+            let offline =
+                manager
+                    .receive_signal("PropertyChanged")
+                    .await?
+                    .filter_map(|msg| async move {
+                        let (name, _): (String, OwnedValue) = msg.body().deserialize().ok()?;
+                        (name == "OfflineMode").then_some(())
+                    });
+            let powered = self
+                .technology()
+                .await?
+                .receive_signal("PropertyChanged")
+                .await?
+                .filter_map(|msg| async move {
+                    let (name, _): (String, OwnedValue) = msg.body().deserialize().ok()?;
+                    (name == "Powered").then_some(())
+                });
+            Ok(stream::select(offline, powered).boxed())
+        }
+        .boxed_local()
+    }
+
+    // Technology.Scan only returns once the scan is done.
+    fn scan(&self) -> LocalBoxFuture<'_, anyhow::Result<()>> {
+        async move {
+            let _: () = self.technology().await?.call("Scan", &()).await?;
+            Ok(())
+        }
+        .boxed_local()
+    }
+
+    fn access_points(&self) -> LocalBoxFuture<'_, anyhow::Result<Vec<AccessPoint>>> {
+        async move {
+            Ok(self
+                .wifi_services()
+                .await?
+                .into_iter()
+                .map(|(path, properties)| service_access_point(path, &properties))
+                .collect())
+        }
+        .boxed_local()
+    }
+
+    fn access_point(
+        &self,
+        path: OwnedObjectPath,
+    ) -> LocalBoxFuture<'_, anyhow::Result<AccessPoint>> {
+        async move {
+            let (path, properties) = self
+                .wifi_services()
+                .await?
+                .into_iter()
+                .find(|(service, _)| *service == path)
+                .with_context(|| format!("{} is not a Wi-Fi service of {}", path, self.iface))?;
+            Ok(service_access_point(path, &properties))
+        }
+        .boxed_local()
+    }
+
+    fn access_point_events(
+        &self,
+    ) -> LocalBoxFuture<'_, anyhow::Result<BoxStream<'static, AccessPointEvent>>> {
+        async move {
+            let manager = Proxy::new(&self.con, CONNMAN, "/", MANAGER).await?;
+            // New services come with their properties, the unchanged ones without.
+            // The ones already listed are skipped by the caller.
+            let changes = manager
+                .receive_signal("ServicesChanged")
+                .await?
+                .flat_map(|msg| {
+                    let (changed, removed): (Services, Vec<OwnedObjectPath>) =
+                        msg.body().deserialize().unwrap_or_default();
+                    let added = changed
+                        .into_iter()
+                        .filter(|(_, properties)| !properties.is_empty())
+                        .map(|(path, _)| AccessPointEvent::Added(path));
+                    let removed = removed.into_iter().map(AccessPointEvent::Removed);
+                    stream::iter(added.chain(removed).collect::<Vec<_>>())
+                });
+
+            let rule = MatchRule::builder()
+                .msg_type(zbus::message::Type::Signal)
+                .sender(CONNMAN)?
+                .interface(SERVICE)?
+                .member("PropertyChanged")?
+                .path_namespace(SERVICE_PATHS)?
+                .arg(0, "Strength")?
+                .build();
+            let strength = MessageStream::for_match_rule(rule, &self.con, None)
+                .await?
+                .filter_map(|msg| async move {
+                    let msg = msg.ok()?;
+                    let path = OwnedObjectPath::from(msg.header().path()?.to_owned());
+                    let (_, strength): (String, OwnedValue) = msg.body().deserialize().ok()?;
+                    Some(AccessPointEvent::Strength(
+                        path,
+                        u8::try_from(&strength).ok()?,
+                    ))
+                });
+
+            Ok(stream::select(changes.boxed(), strength.boxed()).boxed())
+        }
+        .boxed_local()
+    }
+
+    fn active_ap(&self) -> LocalBoxFuture<'_, anyhow::Result<Option<(BSSID, OwnedObjectPath)>>> {
+        async move {
+            Ok(self
+                .active_service()
+                .await?
+                .filter(|(_, state)| *state == ConnectionState::Connected)
+                .map(|(path, _)| (path.to_string(), path)))
+        }
+        .boxed_local()
+    }
+
+    fn active_access_point(&self) -> LocalBoxFuture<'_, anyhow::Result<Option<AccessPoint>>> {
+        async move {
+            let Some((path, _)) = self.active_service().await? else {
+                return Ok(None);
+            };
+            Ok(Some(self.access_point(path).await?))
+        }
+        .boxed_local()
+    }
+
+    fn connection_state(&self) -> LocalBoxFuture<'_, anyhow::Result<ConnectionState>> {
+        async move {
+            Ok(self
+                .active_service()
+                .await?
+                .map_or(ConnectionState::Disconnected, |(_, state)| state))
+        }
+        .boxed_local()
+    }
+
+    fn state_changes(&self) -> LocalBoxFuture<'_, anyhow::Result<BoxStream<'static, ()>>> {
+        self.service_changes(&["State"]).boxed_local()
+    }
+
+    // ConnMan doesn't tell the bitrate.
+    fn connection_details(&self) -> LocalBoxFuture<'_, anyhow::Result<Option<ConnectionDetails>>> {
+        async move {
+            let Some((_, properties)) = self
+                .wifi_services()
+                .await?
+                .into_iter()
+                .find(|(_, properties)| service_state(properties) == ConnectionState::Connected)
+            else {
+                return Ok(None);
+            };
+
+            let ipv4 = dict(&properties, "IPv4");
+            let ipv6 = dict(&properties, "IPv6");
+            let address4 = text(&ipv4, "Address").map(|address| {
+                let prefix = text(&ipv4, "Netmask").map_or(32, prefix_length);
+                format!("{}/{}", address, prefix)
+            });
+            let address6 = text(&ipv6, "Address").map(|address| {
+                let prefix = ipv6
+                    .get("PrefixLength")
+                    .and_then(|value| u8::try_from(value).ok())
+                    .unwrap_or(128);
+                format!("{}/{}", address, prefix)
+            });
+
+            Ok(Some(ConnectionDetails {
+                ipv4: address4.into_iter().collect(),
+                ipv6: address6.into_iter().collect(),
+                gateway4: text(&ipv4, "Gateway").map(str::to_string),
+                gateway6: text(&ipv6, "Gateway").map(str::to_string),
+                dns: strings(&properties, "Nameservers"),
+                bitrate: 0,
+            }))
+        }
+        .boxed_local()
+    }
+
+    fn details_changes(&self) -> LocalBoxFuture<'_, anyhow::Result<BoxStream<'static, ()>>> {
+        self.service_changes(&["State", "IPv4", "IPv6", "Nameservers"])
+            .boxed_local()
+    }
+
+    // Enterprise networks are provisioned from a file instead.
+    fn supports_enterprise(&self) -> bool {
+        false
+    }
+
+    // ConnMan connects to a network, not to one of its access points, so it can't be locked to one.
+    fn connect<'a>(
+        &'a self,
+        access_point: &'a AccessPoint,
+        password: Option<String>,
+        hidden: bool,
+        eap: Option<EapCredentials>,
+        _lock_bssid: bool,
+    ) -> LocalBoxFuture<'a, anyhow::Result<(OwnedObjectPath, OwnedObjectPath)>> {
+        async move {
+            if eap.is_some() {
+                bail!(
+                    "ConnMan connects to enterprise networks from a provisioning file, see connman-service.config(5)"
+                );
+            }
+            if !hidden {
+                return self
+                    .start_connect(access_point.path.clone(), password, None)
+                    .await;
+            }
+
+            // A hidden network is connected through any nameless service of its security, ConnMan then asks for the name.
+            let class = access_point.security.class;
+            let (service, _) = self
+                .wifi_services()
+                .await?
+                .into_iter()
+                .find(|(path, properties)| {
+                    let ap = service_access_point(path.clone(), properties);
+                    ap.ssid.is_empty() && ap.security.class == class
+                })
+                .context("No hidden network of that security is in range")?;
+            self.start_connect(service, password, Some(access_point.ssid.clone()))
+                .await
+        }
+        .boxed_local()
+    }
+
+    fn connect_saved<'a>(
+        &'a self,
+        access_point: &'a AccessPoint,
+    ) -> LocalBoxFuture<'a, anyhow::Result<(OwnedObjectPath, OwnedObjectPath)>> {
+        self.start_connect(access_point.path.clone(), None, None)
+            .boxed_local()
+    }
+
+    fn connect_result(&self) -> LocalBoxFuture<'_, anyhow::Result<u32>> {
+        async move {
+            let connecting = self
+                .pending
+                .borrow_mut()
+                .take()
+                .context("No connect in progress")?;
+            let reason = connecting.await;
+            // Unused when ConnMan didn't ask, e.g. the network was saved after all.
+            let mut agent = self.agent.lock().unwrap();
+            agent.password = None;
+            agent.name = None;
+            Ok(reason)
+        }
+        .boxed_local()
+    }
+
+    fn deactivate<'a>(
+        &'a self,
+        active: &'a OwnedObjectPath,
+    ) -> LocalBoxFuture<'a, anyhow::Result<()>> {
+        async move {
+            let service = Proxy::new(&self.con, CONNMAN, active, SERVICE).await?;
+            let _: () = service.call("Disconnect", &()).await?;
+            Ok(())
+        }
+        .boxed_local()
+    }
+
+    fn forget_profile<'a>(
+        &'a self,
+        profile: &'a OwnedObjectPath,
+    ) -> LocalBoxFuture<'a, anyhow::Result<()>> {
+        async move {
+            let service = Proxy::new(&self.con, CONNMAN, profile, SERVICE).await?;
+            // A service that was never saved can't be removed, there's nothing to forget then.
+            let _: zbus::Result<()> = service.call("Remove", &()).await;
+            Ok(())
+        }
+        .boxed_local()
+    }
+
+    fn forget<'a>(&'a self, ssid: &'a str) -> LocalBoxFuture<'a, anyhow::Result<usize>> {
+        async move {
+            let manager = Proxy::new(&self.con, CONNMAN, "/", MANAGER).await?;
+            let services: Services = manager.call("GetServices", &()).await?;
+
+            let mut forgotten = 0;
+            for (path, properties) in services {
+                let ap = service_access_point(path.clone(), &properties);
+                if is_wifi(&properties) && ap.ssid == ssid && ap.setting_path.is_some() {
+                    let service = Proxy::new(&self.con, CONNMAN, &path, SERVICE).await?;
+                    let _: () = service.call("Remove", &()).await?;
+                    forgotten += 1;
+                }
+            }
+            Ok(forgotten)
+        }
+        .boxed_local()
+    }
+
+    fn disconnect(&self) -> LocalBoxFuture<'_, anyhow::Result<()>> {
+        async move {
+            let manager = Proxy::new(&self.con, CONNMAN, "/", MANAGER).await?;
+            let services: Services = manager.call("GetServices", &()).await?;
+
+            let active = services.into_iter().find(|(_, properties)| {
+                is_wifi(properties)
+                    && service_iface(properties).as_deref() == Some(self.iface.as_str())
+                    && service_state(properties) != ConnectionState::Disconnected
+            });
+            let Some((path, _)) = active else {
+                return Ok(());
+            };
+            let service = Proxy::new(&self.con, CONNMAN, &path, SERVICE).await?;
+            let _: () = service.call("Disconnect", &()).await?;
+            Ok(())
+        }
+        .boxed_local()
+    }
+
+    fn register_agent(
+        &self,
+    ) -> LocalBoxFuture<'_, anyhow::Result<mpsc::UnboundedReceiver<SecretAgentEvent>>> {
+        async move {
+            let (events, receiver) = mpsc::unbounded();
+            self.agent.lock().unwrap().events = Some(events);
+            Ok(receiver)
+        }
+        .boxed_local()
+    }
+}
+
+#[derive(zbus::DBusError, Debug)]
+#[zbus(prefix = "net.connman.Agent.Error")]
+pub enum AgentError {
+    #[zbus(error)]
+    ZBus(zbus::Error),
+    Canceled(String),
+}
+
+// Answers ConnMan's input requests. The connect in progress brings its passphrase, and the name of a hidden network,
+// along. A passphrase asked for otherwise, e.g. autoconnect to a network whose passphrase changed, goes to the password prompt.
+struct Agent {
+    con: Connection,
+    state: Arc<Mutex<AgentState>>,
+}
+
+#[zbus::interface(name = "net.connman.Agent")]
+impl Agent {
+    async fn request_input(
+        &self,
+        service: OwnedObjectPath,
+        fields: HashMap<String, OwnedValue>,
+    ) -> Result<HashMap<String, Value<'static>>, AgentError> {
+        let (password, name, events) = {
+            let mut state = self.state.lock().unwrap();
+            (
+                state.password.take(),
+                state.name.take(),
+                state.events.clone(),
+            )
+        };
+
+        let mut input = HashMap::new();
+        for field in ["Name", "SSID"] {
+            if let Some(name) = name.clone().filter(|_| fields.contains_key(field)) {
+                input.insert(field.to_string(), Value::from(name));
+            }
+        }
+        if !fields.contains_key("Passphrase") {
+            // e.g. Identity, enterprise networks are left to provisioning files.
+            if input.is_empty() {
+                return Err(AgentError::Canceled("Only passphrases are asked".into()));
+            }
+            return Ok(input);
+        }
+        if let Some(password) = password {
+            input.insert("Passphrase".to_string(), Value::from(password));
+            return Ok(input);
+        }
+
+        let events =
+            events.ok_or_else(|| AgentError::Canceled("Prompt is not available".into()))?;
+        let ssid = match name {
+            Some(name) => name,
+            None => Proxy::new(&self.con, CONNMAN, &service, SERVICE)
+                .await?
+                .call::<_, _, HashMap<String, OwnedValue>>("GetProperties", &())
+                .await?
+                .get("Name")
+                .and_then(|value| <&str>::try_from(value).ok())
+                .unwrap_or_default()
+                .to_string(),
+        };
+        // The passphrase of a WPA2 and of a WPA3 network are both of the "psk" type, only WEP is told apart.
+        let passphrase_type = fields
+            .get("Passphrase")
+            .and_then(|field| field.downcast_ref::<Dict>().ok())
+            .and_then(|field| field.get::<&str, String>(&"Type").ok().flatten());
+        let security = (passphrase_type.as_deref() == Some("wep")).then_some(Security::Wep);
+        let (reply, answer) = oneshot::channel();
+        self.state.lock().unwrap().service = Some(service.clone());
+        events
+            .unbounded_send(SecretAgentEvent::Request(SecretRequest {
+                ssid,
+                connection_path: service,
+                security,
+                reply,
+            }))
+            .map_err(|_| AgentError::Canceled("Prompt is not available".into()))?;
+
+        let passphrase = answer
+            .await
+            .map_err(|_| AgentError::Canceled("Prompt was dismissed".into()))?;
+        input.insert("Passphrase".to_string(), Value::from(passphrase));
+        Ok(input)
+    }
+
+    // The error ends the connect, which tells it as well.
+    fn report_error(&self, _service: OwnedObjectPath, _error: String) {}
+
+    fn request_browser(&self, _service: OwnedObjectPath, _url: String) -> Result<(), AgentError> {
+        Err(AgentError::Canceled(
+            "Captive portals are not supported".into(),
+        ))
+    }
+
+    fn cancel(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.password = None;
+        state.name = None;
+        if let (Some(service), Some(events)) = (state.service.take(), &state.events) {
+            let _ = events.unbounded_send(SecretAgentEvent::Cancel(service));
+        }
+    }
+
+    fn release(&self) {}
+}
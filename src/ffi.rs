@@ -5,9 +5,10 @@
 
 
 use std::{ffi::c_char, os::raw::c_void};
-use super::{
-    wifi_mode_destory, wifi_mode_get_display_value, wifi_mode_get_num_entries, wifi_mode_init,
-    wifi_mode_result, wifi_mode_token_match,
+use crate::mode::{
+    wifi_mode_destory, wifi_mode_get_display_value, wifi_mode_get_message,
+    wifi_mode_get_num_entries, wifi_mode_init, wifi_mode_preprocess_input, wifi_mode_result,
+    wifi_mode_token_match,
 };
 
 include!(concat!(env!("OUT_DIR"), "/binding.rs"));
@@ -31,10 +32,6 @@ unsafe extern "C" fn wifi_mode_get_display_value_callee(
     _attr_list: *mut *mut _GList,
     get_entry: i32,
 ) -> *mut i8 {
-    // let x = unsafe {
-    //     glib::translate::from_glib_none::<List::<>>(attr_list);
-
-    // };
     let result = wifi_mode_get_display_value(
         unsafe { &*sw },
         selected_line as usize,
@@ -67,6 +64,28 @@ unsafe extern "C" fn wifi_mode_result_callee(
     )
 }
 
+unsafe extern "C" fn wifi_mode_get_message_callee(sw: *const Mode) -> *mut i8 {
+    let result = wifi_mode_get_message(unsafe { &*sw });
+
+    let str_ptr = result.map(|s| {
+        let c_str = std::ffi::CString::new(s).expect("string contained internal null byte");
+        c_str.into_raw()
+    });
+    str_ptr.unwrap_or_else(|| std::ptr::null_mut())
+}
+
+unsafe extern "C" fn wifi_mode_preprocess_input_callee(
+    sw: *mut Mode,
+    input: *const c_char,
+) -> *mut c_char {
+    let input = unsafe { std::ffi::CStr::from_ptr(input) }.to_string_lossy();
+    let result = wifi_mode_preprocess_input(unsafe { &mut *sw }, &input);
+
+    // rofi frees it with g_free
+    let c_str = std::ffi::CString::new(result).expect("string contained internal null byte");
+    unsafe { glib::ffi::g_strdup(c_str.as_ptr()) }
+}
+
 unsafe extern "C" fn wifi_mode_token_match_callee(
     sw: *const Mode,
     tokens: *mut *mut rofi_int_matcher_t,
@@ -95,10 +114,10 @@ pub static mut mode: Mode = Mode {
     _token_match: Some(wifi_mode_token_match_callee),
     _result: Some(wifi_mode_result_callee),
     _completer_result: None,
-    _preprocess_input: None,
+    _preprocess_input: Some(wifi_mode_preprocess_input_callee),
     _get_completion: None,
     _get_icon: None,
-    _get_message: None,
+    _get_message: Some(wifi_mode_get_message_callee),
     private_data: std::ptr::null_mut() as *mut _,
     free: None,
     _create: None,
@@ -0,0 +1,761 @@
+use crate::{
+    backend::{AccessPointEvent, Backend, ConnectionState},
+    secret_agent::{SecretAgentEvent, SecretRequest},
+    structure::{
+        AccessPoint, BSSID, ConnectionDetails, EapCredentials, Radio, Security, SecurityDescriptor,
+    },
+    utils,
+};
+use anyhow::{Context, bail};
+use futures_channel::{mpsc, oneshot};
+use futures_util::{
+    FutureExt, StreamExt,
+    future::LocalBoxFuture,
+    stream::{self, BoxStream},
+};
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use zbus::{
+    Connection, Proxy,
+    fdo::PropertiesProxy,
+    zvariant::{ObjectPath, OwnedObjectPath, OwnedValue},
+};
+
+// iwd on its own, without NetworkManager.
+// https://git.kernel.org/pub/scm/network/wireless/iwd.git/tree/doc
+//
+// iwd has no profiles to create, a network becomes a known network once connected, and its passphrase
+// is handed over through the agent. It roams between the access points of a network on its own,
+// so each network is listed as a single access point, the network's object standing for it.
+
+const IWD: &str = "net.connman.iwd";
+const OBJECT_MANAGER: &str = "org.freedesktop.DBus.ObjectManager";
+const ADAPTER: &str = "net.connman.iwd.Adapter";
+const DEVICE: &str = "net.connman.iwd.Device";
+const STATION: &str = "net.connman.iwd.Station";
+const NETWORK: &str = "net.connman.iwd.Network";
+const KNOWN_NETWORK: &str = "net.connman.iwd.KnownNetwork";
+
+pub const AGENT_PATH: &str = "/io/github/bjn7/rofi_wifi/iwd_agent";
+
+type ManagedObjects = HashMap<OwnedObjectPath, HashMap<String, HashMap<String, OwnedValue>>>;
+
+pub struct Iwd {
+    con: Connection,
+    iface: String,
+    interfaces: Vec<String>,
+    // The device in use, it carries the Station interface.
+    dev_path: OwnedObjectPath,
+    // The adapter of the device, it carries the radio.
+    adapter_path: OwnedObjectPath,
+    agent: Arc<Mutex<AgentState>>,
+    // The Connect call of the connect in progress, awaited by `connect_result`.
+    pending: RefCell<Option<LocalBoxFuture<'static, u32>>>,
+}
+
+impl std::fmt::Debug for Iwd {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Iwd")
+            .field("iface", &self.iface)
+            .field("dev_path", &self.dev_path)
+            .finish_non_exhaustive()
+    }
+}
+
+// Shared with the agent, which zbus serves from its own thread.
+#[derive(Debug, Default)]
+struct AgentState {
+    /// The passphrase of the connect in progress, handed over to iwd's request.
+    password: Option<String>,
+    /// The network of the request forwarded to the prompt, iwd's Cancel doesn't tell it.
+    network: Option<OwnedObjectPath>,
+    events: Option<mpsc::UnboundedSender<SecretAgentEvent>>,
+}
+
+// Without an interface, the last picked one is used, otherwise the first found.
+pub async fn setup(iface: Option<&str>) -> anyhow::Result<Iwd> {
+    let con = Connection::system().await?;
+    let mut devices = stations(&con).await?;
+
+    // The last picked interface goes first.
+    if let Some(last_iface) = utils::load_last_interface()
+        && let Some(index) = devices.iter().position(|(name, ..)| *name == last_iface)
+    {
+        let device = devices.remove(index);
+        devices.insert(0, device);
+    }
+
+    let (iface_name, dev_path, adapter_path) = match iface {
+        Some(iface) => devices
+            .iter()
+            .find(|(name, ..)| name == iface)
+            .cloned()
+            .with_context(|| format!("Wi-Fi interface {} not found", iface))?,
+        None => devices
+            .first()
+            .cloned()
+            .context("No Wi-Fi interface found")?,
+    };
+
+    // Registered right away, the passphrase of every new network goes through it.
+    let agent = Arc::new(Mutex::new(AgentState::default()));
+    con.object_server()
+        .at(
+            AGENT_PATH,
+            Agent {
+                con: con.clone(),
+                state: Arc::clone(&agent),
+            },
+        )
+        .await?;
+    let agent_manager = Proxy::new(
+        &con,
+        IWD,
+        "/net/connman/iwd",
+        "net.connman.iwd.AgentManager",
+    )
+    .await?;
+    let _: () = agent_manager
+        .call("RegisterAgent", &(ObjectPath::try_from(AGENT_PATH)?))
+        .await?;
+
+    Ok(Iwd {
+        interfaces: devices.into_iter().map(|(name, ..)| name).collect(),
+        iface: iface_name,
+        con,
+        dev_path,
+        adapter_path,
+        agent,
+        pending: RefCell::new(None),
+    })
+}
+
+async fn managed_objects(con: &Connection) -> anyhow::Result<ManagedObjects> {
+    let object_manager = Proxy::new(con, IWD, "/", OBJECT_MANAGER).await?;
+    Ok(object_manager.call("GetManagedObjects", &()).await?)
+}
+
+// Lists the interface name, device path and adapter path of every device in station mode.
+async fn stations(
+    con: &Connection,
+) -> anyhow::Result<Vec<(String, OwnedObjectPath, OwnedObjectPath)>> {
+    let mut stations: Vec<_> = managed_objects(con)
+        .await?
+        .into_iter()
+        .filter(|(_, interfaces)| interfaces.contains_key(STATION))
+        .filter_map(|(path, interfaces)| {
+            let device = interfaces.get(DEVICE)?;
+            let name: &str = device.get("Name")?.try_into().ok()?;
+            let adapter = OwnedObjectPath::try_from(device.get("Adapter")?.clone()).ok()?;
+            Some((name.to_string(), path, adapter))
+        })
+        .collect();
+    // The objects come in no particular order.
+    stations.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(stations)
+}
+
+// iwd tells the signal in 100 * dBm.
+fn signal_percent(signal: i16) -> u8 {
+    utils::dbm_to_percent(i32::from(signal) / 100)
+}
+
+// iwd's network types, it doesn't tell WPA3 apart from WPA2 nor OWE from open.
+fn security_of(network_type: &str) -> Security {
+    match network_type {
+        "psk" => Security::WpaPsk,
+        "wep" => Security::Wep,
+        "8021x" => Security::Enterprise,
+        _ => Security::Open,
+    }
+}
+
+// iwd's errors as the NMDeviceStateReason they are closest to.
+fn failure_reason(error: &zbus::Error, class: Security) -> u32 {
+    let zbus::Error::MethodError(name, _, _) = error else {
+        // NM_DEVICE_STATE_REASON_UNKNOWN
+        return 1;
+    };
+    match name.as_str() {
+        // A wrong passphrase fails the handshake, which iwd reports as a plain failure.
+        "net.connman.iwd.Failed" if class.needs_password() => 8,
+        "net.connman.iwd.NoAgent" => 7,
+        "net.connman.iwd.InvalidFormat"
+        | "net.connman.iwd.NotConfigured"
+        | "net.connman.iwd.NotSupported" => 9,
+        "net.connman.iwd.NotFound" => 53,
+        "net.connman.iwd.Aborted" => 39,
+        _ => 1,
+    }
+}
+
+impl Iwd {
+    async fn proxy(
+        &self,
+        path: &OwnedObjectPath,
+        interface: &'static str,
+    ) -> zbus::Result<Proxy<'static>> {
+        Proxy::new(&self.con, IWD, path.clone(), interface).await
+    }
+
+    async fn ordered_networks(&self) -> anyhow::Result<Vec<(OwnedObjectPath, u8)>> {
+        let station = self.proxy(&self.dev_path, STATION).await?;
+        let networks: Vec<(OwnedObjectPath, i16)> = station.call("GetOrderedNetworks", &()).await?;
+        Ok(networks
+            .into_iter()
+            .map(|(path, signal)| (path, signal_percent(signal)))
+            .collect())
+    }
+
+    // A network as an access point. Its BSSID is the lowest of its access points when iwd tells them,
+    // so it stays the same while iwd roams, otherwise the network's object path.
+    async fn fetch_network(
+        &self,
+        path: OwnedObjectPath,
+        signal_strength: u8,
+    ) -> anyhow::Result<AccessPoint> {
+        let network = self.proxy(&path, NETWORK).await?;
+        let ssid: String = network.get_property("Name").await?;
+        let network_type: String = network.get_property("Type").await?;
+        // Only a known network has one.
+        let setting_path = network
+            .get_property::<OwnedObjectPath>("KnownNetwork")
+            .await
+            .ok();
+
+        let mut addresses = Vec::new();
+        let bss_paths: Vec<OwnedObjectPath> = network
+            .get_property("ExtendedServiceSet")
+            .await
+            .unwrap_or_default();
+        for bss_path in bss_paths {
+            let bss = self
+                .proxy(&bss_path, "net.connman.iwd.BasicServiceSet")
+                .await?;
+            if let Ok(address) = bss.get_property::<String>("Address").await {
+                addresses.push(address.to_uppercase());
+            }
+        }
+        let bssid: BSSID = addresses
+            .into_iter()
+            .min()
+            .unwrap_or_else(|| path.to_string());
+
+        Ok(AccessPoint {
+            path,
+            ssid,
+            bssid,
+            frequency: 0,
+            signal_strength,
+            security: SecurityDescriptor::from_class(security_of(&network_type)),
+            setting_path,
+        })
+    }
+
+    async fn station_state(&self) -> anyhow::Result<String> {
+        let station = self.proxy(&self.dev_path, STATION).await?;
+        Ok(station.get_property("State").await?)
+    }
+
+    // Yields whenever one of `properties` of the station changes.
+    async fn station_changes(
+        &self,
+        properties: &'static [&'static str],
+    ) -> anyhow::Result<BoxStream<'static, ()>> {
+        let properties_proxy = PropertiesProxy::new(&self.con, IWD, self.dev_path.clone()).await?;
+        let changes = properties_proxy.receive_properties_changed().await?;
+        Ok(changes
+            .filter_map(move |signal| async move {
+                let args = signal.args().ok()?;
+                properties
+                    .iter()
+                    .any(|property| args.changed_properties.contains_key(*property))
+                    .then_some(())
+            })
+            .boxed())
+    }
+
+    // Starts a Connect, awaited later by `connect_result`, so it can be timed out and canceled meanwhile.
+    async fn start_connect(
+        &self,
+        access_point: &AccessPoint,
+        password: Option<String>,
+        hidden: bool,
+    ) -> anyhow::Result<(OwnedObjectPath, OwnedObjectPath)> {
+        self.agent.lock().unwrap().password = password;
+
+        let class = access_point.security.class;
+        let connecting = if hidden {
+            let station = self.proxy(&self.dev_path, STATION).await?;
+            let ssid = access_point.ssid.clone();
+            async move {
+                station
+                    .call::<_, _, ()>("ConnectHiddenNetwork", &(ssid))
+                    .await
+            }
+            .boxed_local()
+        } else {
+            let network = self.proxy(&access_point.path, NETWORK).await?;
+            async move { network.call::<_, _, ()>("Connect", &()).await }.boxed_local()
+        };
+        *self.pending.borrow_mut() = Some(
+            connecting
+                .map(move |result| match result {
+                    Ok(()) => 0,
+                    Err(e) => failure_reason(&e, class),
+                })
+                .boxed_local(),
+        );
+
+        // Nothing is saved until connected, the activation is stopped through the station.
+        Ok((ObjectPath::try_from("/")?.into(), self.dev_path.clone()))
+    }
+}
+
+impl Backend for Iwd {
+    fn interfaces(&self) -> &[String] {
+        &self.interfaces
+    }
+
+    fn iface(&self) -> &str {
+        &self.iface
+    }
+
+    fn select_interface<'a>(
+        &'a self,
+        iface: &'a str,
+    ) -> LocalBoxFuture<'a, anyhow::Result<Box<dyn Backend>>> {
+        async move {
+            let (iface_name, dev_path, adapter_path) = stations(&self.con)
+                .await?
+                .into_iter()
+                .find(|(name, ..)| name == iface)
+                .with_context(|| format!("Wi-Fi interface {} not found", iface))?;
+            Ok(Box::new(Iwd {
+                con: self.con.clone(),
+                iface: iface_name,
+                interfaces: self.interfaces.clone(),
+                dev_path,
+                adapter_path,
+                agent: Arc::clone(&self.agent),
+                // A connect in progress stays with the interface it was started on.
+                pending: RefCell::new(None),
+            }) as Box<dyn Backend>)
+        }
+        .boxed_local()
+    }
+
+    // iwd has no mobile broadband, so the switch row only turns the adapter on or off, never airplane mode.
+    fn radio(&self) -> LocalBoxFuture<'_, anyhow::Result<Radio>> {
+        async move {
+            let adapter = self.proxy(&self.adapter_path, ADAPTER).await?;
+            let wifi: bool = adapter.get_property("Powered").await?;
+            Ok(Radio { wifi, wwan: true })
+        }
+        .boxed_local()
+    }
+
+    fn set_radio(&self, radio: Radio) -> LocalBoxFuture<'_, anyhow::Result<()>> {
+        async move {
+            let adapter = self.proxy(&self.adapter_path, ADAPTER).await?;
+            adapter.set_property("Powered", radio.wifi).await?;
+            Ok(())
+        }
+        .boxed_local()
+    }
+
+    fn radio_changes(&self) -> LocalBoxFuture<'_, anyhow::Result<BoxStream<'static, ()>>> {
+        async move {
+            let properties_proxy =
+                PropertiesProxy::new(&self.con, IWD, self.adapter_path.clone()).await?;
+            let changes = properties_proxy.receive_properties_changed().await?;
+            Ok(changes
+                .filter_map(|signal| async move {
+                    let args = signal.args().ok()?;
+                    args.changed_properties
+                        .contains_key("Powered")
+                        .then_some(())
+                })
+                .boxed())
+        }
+        .boxed_local()
+    }
+
+    fn scan(&self) -> LocalBoxFuture<'_, anyhow::Result<()>> {
+        async move {
+            let properties_proxy =
+                PropertiesProxy::new(&self.con, IWD, self.dev_path.clone()).await?;
+            let mut changes = properties_proxy.receive_properties_changed().await?;
+            let station = self.proxy(&self.dev_path, STATION).await?;
+            let _: () = station.call("Scan", &()).await?;
+
+            while let Some(signal) = changes.next().await {
+                let scanning = signal
+                    .args()?
+                    .changed_properties
+                    .get("Scanning")
+                    .and_then(|value| value.downcast_ref::<bool>().ok());
+                if scanning == Some(false) {
+                    return Ok(());
+                }
+            }
+            bail!("Expected Some, got None")
+        }
+        .boxed_local()
+    }
+
+    fn access_points(&self) -> LocalBoxFuture<'_, anyhow::Result<Vec<AccessPoint>>> {
+        async move {
+            let mut aps = Vec::new();
+            for (path, signal_strength) in self.ordered_networks().await? {
+                aps.push(self.fetch_network(path, signal_strength).await?);
+            }
+            Ok(aps)
+        }
+        .boxed_local()
+    }
+
+    fn access_point(
+        &self,
+        path: OwnedObjectPath,
+    ) -> LocalBoxFuture<'_, anyhow::Result<AccessPoint>> {
+        async move {
+            let signal_strength = self
+                .ordered_networks()
+                .await?
+                .into_iter()
+                .find(|(network, _)| *network == path)
+                .map_or(0, |(_, signal_strength)| signal_strength);
+            self.fetch_network(path, signal_strength).await
+        }
+        .boxed_local()
+    }
+
+    fn access_point_events(
+        &self,
+    ) -> LocalBoxFuture<'_, anyhow::Result<BoxStream<'static, AccessPointEvent>>> {
+        async move {
+            let object_manager = Proxy::new(&self.con, IWD, "/", OBJECT_MANAGER).await?;
+            let dev_path = self.dev_path.clone();
+            let added = object_manager
+                .receive_signal("InterfacesAdded")
+                .await?
+                .filter_map(move |msg| {
+                    let dev_path = dev_path.clone();
+                    async move {
+                        let (path, interfaces): (
+                            OwnedObjectPath,
+                            HashMap<String, HashMap<String, OwnedValue>>,
+                        ) = msg.body().deserialize().ok()?;
+                        // The networks of the other devices are left out.
+                        let device = interfaces.get(NETWORK)?.get("Device")?.clone();
+                        (OwnedObjectPath::try_from(device).ok()? == dev_path)
+                            .then_some(AccessPointEvent::Added(path))
+                    }
+                });
+
+            let removed = object_manager
+                .receive_signal("InterfacesRemoved")
+                .await?
+                .filter_map(|msg| async move {
+                    let (path, interfaces): (OwnedObjectPath, Vec<String>) =
+                        msg.body().deserialize().ok()?;
+                    interfaces
+                        .iter()
+                        .any(|interface| interface == NETWORK)
+                        .then_some(AccessPointEvent::Removed(path))
+                });
+
+            // The signal is only told by the ordered networks, which are read again once a scan is done.
+            let station = self.proxy(&self.dev_path, STATION).await?;
+            let properties_proxy =
+                PropertiesProxy::new(&self.con, IWD, self.dev_path.clone()).await?;
+            let strength = properties_proxy
+                .receive_properties_changed()
+                .await?
+                .filter_map(|signal| async move {
+                    let args = signal.args().ok()?;
+                    let scanning = args.changed_properties.get("Scanning")?;
+                    // Done scanning.
+                    (!scanning.downcast_ref::<bool>().ok()?).then_some(())
+                })
+                .then(move |()| {
+                    let station = station.clone();
+                    async move {
+                        let networks: Vec<(OwnedObjectPath, i16)> = station
+                            .call("GetOrderedNetworks", &())
+                            .await
+                            .unwrap_or_default();
+                        stream::iter(networks.into_iter().map(|(path, signal)| {
+                            AccessPointEvent::Strength(path, signal_percent(signal))
+                        }))
+                    }
+                })
+                .flatten();
+
+            Ok(stream::select_all([added.boxed(), removed.boxed(), strength.boxed()]).boxed())
+        }
+        .boxed_local()
+    }
+
+    fn active_ap(&self) -> LocalBoxFuture<'_, anyhow::Result<Option<(BSSID, OwnedObjectPath)>>> {
+        async move {
+            // Still connecting, like NetworkManager's activation without an access point yet.
+            if self.station_state().await? != "connected" {
+                return Ok(None);
+            }
+            let Some(ap) = self.active_access_point().await? else {
+                return Ok(None);
+            };
+            let profile = ap.setting_path.unwrap_or(ap.path);
+            Ok(Some((ap.bssid, profile)))
+        }
+        .boxed_local()
+    }
+
+    fn active_access_point(&self) -> LocalBoxFuture<'_, anyhow::Result<Option<AccessPoint>>> {
+        async move {
+            let station = self.proxy(&self.dev_path, STATION).await?;
+            // Only set while connecting or connected.
+            let Ok(network_path) = station
+                .get_property::<OwnedObjectPath>("ConnectedNetwork")
+                .await
+            else {
+                return Ok(None);
+            };
+            Ok(Some(self.access_point(network_path).await?))
+        }
+        .boxed_local()
+    }
+
+    fn connection_state(&self) -> LocalBoxFuture<'_, anyhow::Result<ConnectionState>> {
+        async move {
+            Ok(match self.station_state().await?.as_str() {
+                "connected" => ConnectionState::Connected,
+                "connecting" | "roaming" => ConnectionState::Connecting,
+                _ => ConnectionState::Disconnected,
+            })
+        }
+        .boxed_local()
+    }
+
+    fn state_changes(&self) -> LocalBoxFuture<'_, anyhow::Result<BoxStream<'static, ()>>> {
+        self.station_changes(&["State", "ConnectedNetwork"])
+            .boxed_local()
+    }
+
+    // iwd keeps the addresses to itself, even when it configures them, so only the bitrate is told.
+    fn connection_details(&self) -> LocalBoxFuture<'_, anyhow::Result<Option<ConnectionDetails>>> {
+        async move {
+            if self.station_state().await? != "connected" {
+                return Ok(None);
+            }
+            let diagnostic = self
+                .proxy(&self.dev_path, "net.connman.iwd.StationDiagnostic")
+                .await?;
+            let diagnostics: HashMap<String, OwnedValue> =
+                diagnostic.call("GetDiagnostics", &()).await?;
+            // In 100 kbit/s.
+            let bitrate = diagnostics
+                .get("TxBitrate")
+                .and_then(|value| u32::try_from(value).ok())
+                .unwrap_or_default();
+            Ok(Some(ConnectionDetails {
+                bitrate: bitrate * 100,
+                ..Default::default()
+            }))
+        }
+        .boxed_local()
+    }
+
+    fn details_changes(&self) -> LocalBoxFuture<'_, anyhow::Result<BoxStream<'static, ()>>> {
+        self.station_changes(&["State", "ConnectedNetwork"])
+            .boxed_local()
+    }
+
+    // Enterprise networks are provisioned from a file instead.
+    fn supports_enterprise(&self) -> bool {
+        false
+    }
+
+    // iwd roams between the access points of a network on its own, so it can't be locked to one.
+    fn connect<'a>(
+        &'a self,
+        access_point: &'a AccessPoint,
+        password: Option<String>,
+        hidden: bool,
+        eap: Option<EapCredentials>,
+        _lock_bssid: bool,
+    ) -> LocalBoxFuture<'a, anyhow::Result<(OwnedObjectPath, OwnedObjectPath)>> {
+        async move {
+            if eap.is_some() {
+                bail!(
+                    "iwd connects to enterprise networks from a provisioning file, see iwd.network(5)"
+                );
+            }
+            self.start_connect(access_point, password, hidden).await
+        }
+        .boxed_local()
+    }
+
+    fn connect_saved<'a>(
+        &'a self,
+        access_point: &'a AccessPoint,
+    ) -> LocalBoxFuture<'a, anyhow::Result<(OwnedObjectPath, OwnedObjectPath)>> {
+        self.start_connect(access_point, None, false).boxed_local()
+    }
+
+    fn connect_result(&self) -> LocalBoxFuture<'_, anyhow::Result<u32>> {
+        async move {
+            let connecting = self
+                .pending
+                .borrow_mut()
+                .take()
+                .context("No connect in progress")?;
+            let reason = connecting.await;
+            // Unused when iwd didn't ask, e.g. the network was known after all.
+            self.agent.lock().unwrap().password = None;
+            Ok(reason)
+        }
+        .boxed_local()
+    }
+
+    fn deactivate<'a>(
+        &'a self,
+        _active: &'a OwnedObjectPath,
+    ) -> LocalBoxFuture<'a, anyhow::Result<()>> {
+        async move {
+            let station = self.proxy(&self.dev_path, STATION).await?;
+            let _: () = station.call("Disconnect", &()).await?;
+            Ok(())
+        }
+        .boxed_local()
+    }
+
+    fn forget_profile<'a>(
+        &'a self,
+        profile: &'a OwnedObjectPath,
+    ) -> LocalBoxFuture<'a, anyhow::Result<()>> {
+        async move {
+            // A failed connect saves nothing.
+            if profile.as_str() == "/" {
+                return Ok(());
+            }
+            let known_network = self.proxy(profile, KNOWN_NETWORK).await?;
+            let _: () = known_network.call("Forget", &()).await?;
+            Ok(())
+        }
+        .boxed_local()
+    }
+
+    fn forget<'a>(&'a self, ssid: &'a str) -> LocalBoxFuture<'a, anyhow::Result<usize>> {
+        async move {
+            let object_manager = Proxy::new(&self.con, IWD, "/", OBJECT_MANAGER).await?;
+            let objects: ManagedObjects = object_manager.call("GetManagedObjects", &()).await?;
+
+            let mut forgotten = 0;
+            for (path, interfaces) in objects {
+                let Some(known_network) = interfaces.get(KNOWN_NETWORK) else {
+                    continue;
+                };
+                let name: Option<&str> = known_network
+                    .get("Name")
+                    .and_then(|value| value.try_into().ok());
+                if name == Some(ssid) {
+                    let known_network = Proxy::new(&self.con, IWD, &path, KNOWN_NETWORK).await?;
+                    let _: () = known_network.call("Forget", &()).await?;
+                    forgotten += 1;
+                }
+            }
+            Ok(forgotten)
+        }
+        .boxed_local()
+    }
+
+    fn disconnect(&self) -> LocalBoxFuture<'_, anyhow::Result<()>> {
+        async move {
+            let station = Proxy::new(&self.con, IWD, &self.dev_path, STATION).await?;
+            let state: String = station.get_property("State").await?;
+            if state == "disconnected" {
+                return Ok(());
+            }
+            let _: () = station.call("Disconnect", &()).await?;
+            Ok(())
+        }
+        .boxed_local()
+    }
+
+    fn register_agent(
+        &self,
+    ) -> LocalBoxFuture<'_, anyhow::Result<mpsc::UnboundedReceiver<SecretAgentEvent>>> {
+        async move {
+            let (events, receiver) = mpsc::unbounded();
+            self.agent.lock().unwrap().events = Some(events);
+            Ok(receiver)
+        }
+        .boxed_local()
+    }
+}
+
+#[derive(zbus::DBusError, Debug)]
+#[zbus(prefix = "net.connman.iwd.Agent.Error")]
+pub enum AgentError {
+    #[zbus(error)]
+    ZBus(zbus::Error),
+    Canceled(String),
+}
+
+// Answers iwd's passphrase requests. The connect in progress brings its passphrase along,
+// any other request, e.g. autoconnect to a network whose passphrase changed, goes to the password prompt.
+struct Agent {
+    con: Connection,
+    state: Arc<Mutex<AgentState>>,
+}
+
+#[zbus::interface(name = "net.connman.iwd.Agent")]
+impl Agent {
+    async fn request_passphrase(&self, network: OwnedObjectPath) -> Result<String, AgentError> {
+        let (password, events) = {
+            let mut state = self.state.lock().unwrap();
+            (state.password.take(), state.events.clone())
+        };
+        if let Some(password) = password {
+            return Ok(password);
+        }
+        let events =
+            events.ok_or_else(|| AgentError::Canceled("Prompt is not available".into()))?;
+
+        let ssid: String = Proxy::new(&self.con, IWD, &network, NETWORK)
+            .await?
+            .get_property("Name")
+            .await?;
+        let (reply, answer) = oneshot::channel();
+        self.state.lock().unwrap().network = Some(network.clone());
+        events
+            .unbounded_send(SecretAgentEvent::Request(SecretRequest {
+                ssid,
+                connection_path: network,
+                // iwd asks the same passphrase of PSK and SAE networks, the listed access point tells them apart.
+                security: None,
+                reply,
+            }))
+            .map_err(|_| AgentError::Canceled("Prompt is not available".into()))?;
+
+        answer
+            .await
+            .map_err(|_| AgentError::Canceled("Prompt was dismissed".into()))
+    }
+
+    fn cancel(&self, _reason: String) {
+        let mut state = self.state.lock().unwrap();
+        state.password = None;
+        if let (Some(network), Some(events)) = (state.network.take(), &state.events) {
+            let _ = events.unbounded_send(SecretAgentEvent::Cancel(network));
+        }
+    }
+
+    fn release(&self) {}
+}
@@ -0,0 +1,750 @@
+// The rofi mode, a thin adapter over the state machine of `state`.
+use crate::{
+    backend,
+    ffi::{self, MenuReturn_MENU_CUSTOM_INPUT, Mode, ModeMode_RELOAD_DIALOG},
+    reason::{self, FailureAction},
+    rofi::{self, ModeView},
+    state,
+    structure::*,
+    view::SharedView,
+};
+use glib::MainContext;
+use std::{cell::RefCell, rc::Rc, time::Duration};
+
+//  I was just creating a simple prototype and playing around with Rofi without involving much async,
+// but somehow it turned into an actual useable plugin with all these background tasks and async.
+
+// I initially planned to just use nmcli to connect and disconnect, but ended up using dbus to directly communicate with NetworkManager.
+
+// Todo!(): Add custom prompt.
+// Todo!(): Modify the wifi-icon icon color, including states color.
+
+pub fn wifi_mode_init(sw: &'static mut Mode) -> i32 {
+    if rofi::get_private_state::<SharedData>(&sw).is_some() {
+        return 1;
+    }
+
+    // Optional, auto detected when missing.
+    let interface = rofi::find_arg_str("-iface");
+    // Optional, the first one running when missing.
+    let backend_name = rofi::find_arg_str("-backend");
+
+    let glib_context = MainContext::default();
+    let async_block_result = glib_context.block_on(async {
+        let backend = backend::setup(backend_name.as_deref(), interface.as_deref()).await?;
+
+        let cached_aps = backend.access_points().await?;
+
+        let active_ap_bssid_opt = backend.active_ap().await?;
+
+        // Only the switch row depends on it, so a failure is not fatal.
+        let radio = backend
+            .radio()
+            .await
+            .inspect_err(|e| eprintln!("Failed to read the radio state: {}", e))
+            .unwrap_or_default();
+
+        // Without the agent, profiles lacking secrets simply fail with NO_SECRETS.
+        let secret_events_opt = backend
+            .register_agent()
+            .await
+            .inspect_err(|e| eprintln!("Failed to register the secret agent: {}", e))
+            .ok();
+
+        anyhow::Ok((
+            backend,
+            cached_aps,
+            active_ap_bssid_opt,
+            radio,
+            secret_events_opt,
+        ))
+    });
+
+    let (backend, cached_aps, active_ap_bssid_opt, radio, secret_events_opt) =
+        match async_block_result {
+            Ok(result) => result,
+            Err(e) => {
+                eprintln!("Failed to connect to the backend: {}", e);
+                return 0;
+            }
+        };
+
+    // With the radio off there are simply no access points, and only the switch row is listed.
+    let mut pd = PrivateData::new(backend, cached_aps);
+    pd.radio = radio;
+    pd.set_connected(active_ap_bssid_opt);
+    pd.sort_accesspoints();
+
+    // Several Wi-Fi interfaces, e.g. internal card and USB dongle, the user picks one first.
+    if interface.is_none() && pd.backend.interfaces().len() > 1 {
+        pd.state = AppState::InterfaceSelect;
+        sw.display_name = c"interface".as_ptr() as *mut i8;
+    }
+
+    // If the 'wifi' widget is found, load the theme properties
+    if let Some(theme_widget) = rofi::config_find_widget("wifi") {
+        // Load scan's config
+
+        // Load scan's configuration properties
+        if let Some(fps) = rofi::theme_find_property_int(theme_widget, "state-scan-fps")
+            .filter(|&fps| fps > 0 && fps <= 60)
+        {
+            pd.anim_scan.fps = fps as u8;
+        }
+
+        if let Some(state_scan_indicator) =
+            rofi::theme_find_property_array(theme_widget, "state-scan-indicator")
+                .filter(|arr| !arr.is_empty())
+        {
+            pd.anim_scan.frames = state_scan_indicator
+                .iter()
+                .map(|x| IndicatorAnim::build_scan("wifi", x))
+                .collect();
+        }
+
+        // Load connecting's configuration properties
+        if let Some(fps) = rofi::theme_find_property_int(theme_widget, "state-connecting-fps")
+            .filter(|&fps| fps > 0 && fps <= 60)
+        {
+            pd.anim_connecting.fps = fps as u8;
+        }
+
+        if let Some(state_connecting_indicator) =
+            rofi::theme_find_property_array(theme_widget, "state-connecting-indicator")
+                .filter(|arr| !arr.is_empty())
+        {
+            pd.anim_connecting.frames = state_connecting_indicator
+                .iter()
+                .map(|x| IndicatorAnim::build_connect(x))
+                .collect();
+        }
+
+        // Load icon settings for open and PSK states
+        if let Some(wifi_icon_open) =
+            rofi::theme_find_property_array(theme_widget, "icon-open").filter(|arr| arr.len() == 5)
+        {
+            pd.icons.open = wifi_icon_open
+                .iter()
+                .map(|x| x.chars().next().unwrap_or('￼'))
+                .collect();
+        }
+
+        if let Some(wifi_icon_close) =
+            rofi::theme_find_property_array(theme_widget, "icon-psk").filter(|arr| arr.len() == 5)
+        {
+            pd.icons.psk = wifi_icon_close
+                .iter()
+                .map(|x| x.chars().next().unwrap_or('￼'))
+                .collect();
+        }
+
+        if let Some(wifi_icon_owe) =
+            rofi::theme_find_property_array(theme_widget, "icon-owe").filter(|arr| arr.len() == 5)
+        {
+            pd.icons.owe = wifi_icon_owe
+                .iter()
+                .map(|x| x.chars().next().unwrap_or('￼'))
+                .collect();
+        }
+
+        if let Some(wifi_icon_wep) =
+            rofi::theme_find_property_array(theme_widget, "icon-wep").filter(|arr| arr.len() == 5)
+        {
+            pd.icons.wep = wifi_icon_wep
+                .iter()
+                .map(|x| x.chars().next().unwrap_or('￼'))
+                .collect();
+        }
+
+        if let Some(wifi_icon_sae) =
+            rofi::theme_find_property_array(theme_widget, "icon-sae").filter(|arr| arr.len() == 5)
+        {
+            pd.icons.sae = wifi_icon_sae
+                .iter()
+                .map(|x| x.chars().next().unwrap_or('￼'))
+                .collect();
+        }
+
+        if let Some(wifi_icon_eap) =
+            rofi::theme_find_property_array(theme_widget, "icon-eap").filter(|arr| arr.len() == 5)
+        {
+            pd.icons.eap = wifi_icon_eap
+                .iter()
+                .map(|x| x.chars().next().unwrap_or('￼'))
+                .collect();
+        }
+
+        if let Some(key) = rofi::theme_find_property_int(theme_widget, "kb-lock-bssid")
+            .filter(|&key| key > 0 && key <= 19)
+        {
+            pd.keys.lock_bssid = key as u32;
+        }
+
+        if let Some(key) = rofi::theme_find_property_int(theme_widget, "kb-expand")
+            .filter(|&key| key > 0 && key <= 19)
+        {
+            pd.keys.expand = key as u32;
+        }
+
+        if let Some(key) = rofi::theme_find_property_int(theme_widget, "kb-rescan")
+            .filter(|&key| key > 0 && key <= 19)
+        {
+            pd.keys.rescan = key as u32;
+        }
+
+        if let Some(key) = rofi::theme_find_property_int(theme_widget, "kb-disconnect")
+            .filter(|&key| key > 0 && key <= 19)
+        {
+            pd.keys.disconnect = key as u32;
+        }
+
+        if let Some(key) = rofi::theme_find_property_int(theme_widget, "kb-airplane")
+            .filter(|&key| key > 0 && key <= 19)
+        {
+            pd.keys.airplane = key as u32;
+        }
+
+        if let Some(key) = rofi::theme_find_property_int(theme_widget, "kb-reveal")
+            .filter(|&key| key > 0 && key <= 19)
+        {
+            pd.keys.reveal = key as u32;
+        }
+
+        // Load scan policy
+        if let Some(interval) = rofi::theme_find_property_int(theme_widget, "scan-interval")
+            .filter(|&interval| interval > 0)
+        {
+            pd.scan_policy.interval = interval as u32;
+        }
+
+        if let Some(on_open_only) = rofi::theme_find_property_int(theme_widget, "scan-on-open-only")
+        {
+            pd.scan_policy.on_open_only = on_open_only != 0;
+        }
+
+        if let Some(timeout) = rofi::theme_find_property_int(theme_widget, "connect-timeout")
+            .filter(|&timeout| timeout >= 0)
+        {
+            pd.connect_timeout = Duration::from_secs(timeout as u64);
+        }
+    };
+
+    let scan_interval = Duration::from_secs(pd.scan_policy.interval as u64);
+    let scan_on_open_only = pd.scan_policy.on_open_only;
+
+    // rofi only keeps a pointer, so the handle is leaked until wifi_mode_destory takes it back.
+    // The tasks hold handles of their own, the private data outlives them either way.
+    let leaked_pd: &'static mut SharedData = Box::leak(Box::new(Rc::new(RefCell::new(pd))));
+    rofi::set_private_state::<SharedData>(&sw, &leaked_pd);
+
+    let sw_rc: SharedView = Rc::new(RefCell::new(ModeView(sw)));
+
+    let sw_connect_detection_task = Rc::clone(&sw_rc);
+    MainContext::default().spawn_local(async move {
+        let _ = state::connection_background_task(sw_connect_detection_task).await;
+    });
+
+    if let Some(secret_events) = secret_events_opt {
+        let sw_secret_agent_task = Rc::clone(&sw_rc);
+        MainContext::default().spawn_local(state::secret_agent_task(
+            sw_secret_agent_task,
+            secret_events,
+        ));
+    }
+
+    MainContext::default().spawn_local(state::access_point_events_task(Rc::clone(&sw_rc)));
+    MainContext::default().spawn_local(state::radio_task(Rc::clone(&sw_rc)));
+    MainContext::default().spawn_local(state::connection_details_task(Rc::clone(&sw_rc)));
+
+    let scan = move || state::spawn_scan(Rc::clone(&sw_rc));
+
+    scan(); // initial run
+
+    if !scan_on_open_only {
+        glib::timeout_add_local(scan_interval, move || {
+            scan();
+            glib::ControlFlow::Continue
+        });
+    }
+
+    1
+}
+
+pub fn wifi_mode_get_num_entries(sw: &Mode) -> u32 {
+    rofi::get_private_state::<SharedData>(sw).map_or(0, |pd| {
+        let pd = pd.borrow();
+        if pd.is_text_input() {
+            0
+        } else if matches!(pd.state, AppState::EnterpriseInput { .. }) {
+            EAP_METHODS.len() as u32
+        } else if matches!(pd.state, AppState::HiddenInput(_)) {
+            HIDDEN_SECURITY.len() as u32
+        } else if pd.state == AppState::InterfaceSelect {
+            pd.backend.interfaces().len() as u32
+        } else {
+            pd.rows.len() as u32
+        }
+    })
+}
+
+pub fn wifi_mode_destory(sw: &mut Mode) {
+    if let Some(_) = rofi::get_private_state::<SharedData>(sw) {
+        let _ = rofi::take_private_state::<SharedData>(sw); // leaked mem is back to rust's gc and pd will be droped after this scope
+    };
+}
+
+pub fn wifi_mode_get_display_value(
+    sw: &Mode,
+    selected_line: usize,
+    state: &mut i32,
+    get_entry: i32,
+) -> Option<String> {
+    if get_entry == 0 {
+        return None;
+    }
+
+    let pd = rofi::get_private_state::<SharedData>(sw)?.borrow();
+    if matches!(pd.state, AppState::EnterpriseInput { .. }) {
+        return EAP_METHODS.get(selected_line).map(|m| m.to_string());
+    }
+    if matches!(pd.state, AppState::HiddenInput(_)) {
+        return HIDDEN_SECURITY
+            .get(selected_line)
+            .map(|(_, label)| label.to_string());
+    }
+
+    // Interfaces are listed with the last picked one first.
+    if pd.state == AppState::InterfaceSelect {
+        return pd.backend.interfaces().get(selected_line).cloned();
+    }
+
+    let row = pd.rows.get(selected_line)?;
+    if *row == Row::Radio {
+        return Some(if pd.radio.airplane_mode() {
+            "󰀝  Airplane mode on".into()
+        } else if pd.radio.wifi {
+            "󰖩  Wi-Fi on".into()
+        } else {
+            "󰖪  Wi-Fi off".into()
+        });
+    }
+    if *row == Row::Hidden {
+        // The hidden network being connected isn't listed, so this row shows its progress.
+        if let AppState::Connecting(ref b) = pd.state
+            && b.is_empty()
+            && let Some(ref hidden) = pd.hidden
+        {
+            *state |= 8 | 4; // Markup, Active
+            let anim_frame = &pd.anim_connecting.frames
+                [pd.anim_connecting.index % pd.anim_connecting.frames.len()];
+            return Some(format!(
+                "󰈉  {} <span size='small' foreground='#639ec5ff' alpha='80%'>{}</span>",
+                glib::markup_escape_text(&hidden.ssid),
+                anim_frame.to_string_lossy()
+            ));
+        }
+        return Some("󰈉  Connect to hidden network…".into());
+    }
+
+    let members: Vec<&AccessPoint> = row
+        .indices()
+        .iter()
+        .filter_map(|&i| pd.aps.get(i))
+        .collect();
+
+    // The strongest access point of the row decides the icon.
+    let ap = *members.iter().max_by_key(|ap| ap.signal_strength)?;
+    let icon = pd.icons.for_signal(ap.security.class, ap.signal_strength);
+
+    *state |= 8; // Markup
+    let sub_label = if let AppState::Connecting(ref b) = pd.state
+        && members.iter().any(|ap| ap.bssid == *b)
+    {
+        *state |= 4; // Active
+        let anim_frame =
+            &pd.anim_connecting.frames[pd.anim_connecting.index % pd.anim_connecting.frames.len()]; // index will be updated in async task from handle_state
+        Some(anim_frame.to_string_lossy().to_string())
+    } else if let Some(ref b) = pd.active_connection
+        && members.iter().any(|ap| ap.bssid == *b)
+    {
+        *state |= 4; // Active
+        Some("(connected)".into())
+    } else {
+        None
+    };
+
+    let ssid = if ap.ssid.is_empty() {
+        "[hidden]".into()
+    } else {
+        glib::markup_escape_text(&ap.ssid).to_string()
+    };
+
+    let (indent, title) = match row {
+        Row::Group(group) if group.len() > 1 => {
+            let mut bands: Vec<&str> = members
+                .iter()
+                .map(|ap| ap.band())
+                .filter(|band| !band.is_empty())
+                .collect();
+            bands.sort();
+            bands.dedup();
+
+            (
+                "",
+                format!(
+                    "{ssid} <span size='small' alpha='60%'>{} · {} APs · {} GHz</span>",
+                    ap.security.label(),
+                    group.len(),
+                    bands.join("/")
+                ),
+            )
+        }
+        Row::Radio | Row::Hidden | Row::Group(_) => (
+            "",
+            format!(
+                "{ssid} <span size='small' alpha='60%'>{}</span>",
+                ap.security.label()
+            ),
+        ),
+        Row::Member(_) => (
+            "    ",
+            format!(
+                "{} <span size='small' alpha='60%'>{} GHz · {}%</span>",
+                ap.bssid,
+                ap.band(),
+                ap.signal_strength
+            ),
+        ),
+    };
+
+    match sub_label {
+        Some(text) => Some(format!(
+            // TODO!: add customization
+            "{indent}{icon}  {title} <span size='small' foreground='#639ec5ff' alpha='80%'>{text}</span>",
+            // "{icon}  {ssid} {text}",
+        )),
+        None => Some(format!("{indent}{icon}  {title}")),
+    }
+}
+
+// Details of the connected network, shown in the message bar below the entry.
+pub fn wifi_mode_get_message(sw: &Mode) -> Option<String> {
+    let pd = rofi::get_private_state::<SharedData>(sw)?.borrow();
+
+    // Until the next connect, a failed one is explained instead of the active connection.
+    if let Some(failure) = pd.last_failure
+        && !matches!(
+            pd.state,
+            AppState::Connecting(_) | AppState::InterfaceSelect
+        )
+    {
+        // A hidden network isn't listed, so its password is asked again whatever the failure.
+        let mut hint = match (pd.is_text_input(), failure.action) {
+            (true, FailureAction::AskPassword) => "Enter the password again.".to_string(),
+            (true, FailureAction::Retry) => "Enter the password to retry.".to_string(),
+            (false, _) => "Select the network again to retry.".to_string(),
+        };
+        if failure.reason != reason::CONNECT_TIMEOUT {
+            hint.push_str(&format!(" (reason {})", failure.reason));
+        }
+        return Some(format!(
+            "{}\n<span alpha='60%'>{}</span>",
+            glib::markup_escape_text(failure.message),
+            hint
+        ));
+    }
+
+    // The password being typed, masked unless revealed.
+    if pd.is_secret_input() {
+        let password = if pd.reveal_password {
+            glib::markup_escape_text(&pd.secret_input).to_string()
+        } else {
+            PASSWORD_MASK
+                .to_string()
+                .repeat(pd.secret_input.chars().count())
+        };
+        return Some(format!("<span alpha='60%'>Password</span>  {}", password));
+    }
+
+    if !matches!(pd.state, AppState::Idle | AppState::Scanning) {
+        return None;
+    }
+
+    let mut lines = Vec::new();
+    if let Some(ap) = pd.active_connection.as_ref().and_then(|b| pd.find_ap(b)) {
+        lines.push(format!(
+            "<b>{}</b> <span alpha='60%'>{}</span>",
+            glib::markup_escape_text(&ap.ssid),
+            ap.bssid
+        ));
+
+        // Empty until the addresses are read, e.g. right after connecting.
+        let details = pd.connection_details.clone().unwrap_or_default();
+        let mut radio_info = radio_info(ap);
+        if details.bitrate > 0 {
+            radio_info.insert(
+                radio_info.len() - 1,
+                format!("{} Mb/s", details.bitrate / 1000),
+            );
+        }
+        lines.push(radio_info.join(" · "));
+
+        for (label, addresses, gateway) in [
+            ("IPv4", &details.ipv4, &details.gateway4),
+            ("IPv6", &details.ipv6, &details.gateway6),
+        ] {
+            if addresses.is_empty() {
+                continue;
+            }
+            let mut line = format!("{label}  {}", addresses.join(", "));
+            if let Some(gateway) = gateway {
+                line.push_str(&format!(" <span alpha='60%'>via</span> {gateway}"));
+            }
+            lines.push(line);
+        }
+
+        if !details.dns.is_empty() {
+            lines.push(format!("DNS  {}", details.dns.join(", ")));
+        }
+    }
+
+    // rofi only tells the selected line to `wifi_mode_result`, so the highlighted row is the one it last passed.
+    if let Some(ap) = pd
+        .highlighted
+        .as_ref()
+        .filter(|bssid| pd.active_connection.as_ref() != Some(*bssid))
+        .and_then(|bssid| pd.find_ap(bssid))
+    {
+        lines.push(format!(
+            "<b>{}</b> <span alpha='60%'>{}</span>",
+            glib::markup_escape_text(&ap.ssid),
+            ap.bssid
+        ));
+        let mut radio_info = radio_info(ap);
+        radio_info.insert(radio_info.len() - 1, format!("{}%", ap.signal_strength));
+        lines.push(radio_info.join(" · "));
+    }
+
+    (!lines.is_empty()).then(|| lines.join("\n"))
+}
+
+// The band, channel and security of an access point, joined by the message bar.
+fn radio_info(ap: &AccessPoint) -> Vec<String> {
+    let mut radio_info = vec![format!("{} GHz", ap.band())];
+    if ap.channel() > 0 {
+        radio_info.push(format!("channel {}", ap.channel()));
+    }
+    radio_info.push(ap.security.label().into());
+    radio_info
+}
+
+// Keeps the password being typed, so the message bar can show it masked, see `wifi_mode_get_message`.
+// rofi only masks the entry of `-dmenu -password`, a mode can't ask for it, so the entry is left as rofi draws it.
+// A redraw by the state machine filters the same entry again, so it's skipped while the private data is borrowed.
+pub fn wifi_mode_preprocess_input(sw: &mut Mode, input: &str) -> String {
+    let Some(Ok(mut pd)) = rofi::get_private_state::<SharedData>(sw).map(|pd| pd.try_borrow_mut())
+    else {
+        return input.into();
+    };
+    if !pd.is_secret_input() {
+        return input.into();
+    }
+    pd.secret_input = input.into();
+
+    // Nothing is listed while typing a password, so there is nothing to filter.
+    String::new()
+}
+
+pub fn wifi_mode_token_match(
+    sw: &Mode,
+    tokens: *mut *mut ffi::rofi_int_matcher_t,
+    index: usize,
+) -> i32 {
+    let match_result = rofi::get_private_state::<SharedData>(sw)
+        .and_then(|pd| {
+            let pd = pd.borrow();
+            if pd.is_text_input() {
+                None
+            } else if matches!(pd.state, AppState::EnterpriseInput { .. }) {
+                EAP_METHODS.get(index).map(|m| m.to_string())
+            } else if matches!(pd.state, AppState::HiddenInput(_)) {
+                HIDDEN_SECURITY
+                    .get(index)
+                    .map(|(_, label)| label.to_string())
+            } else if pd.state == AppState::InterfaceSelect {
+                pd.backend.interfaces().get(index).cloned()
+            } else {
+                match pd.rows.get(index)? {
+                    Row::Radio => Some("Wi-Fi radio airplane mode".into()),
+                    Row::Hidden => Some("Connect to hidden network".into()),
+                    // Networks can be matched by their security as well, e.g. "wpa3".
+                    Row::Group(group) => pd
+                        .aps
+                        .get(group[0])
+                        .map(|ap| format!("{} {}", ap.ssid, ap.security.label())),
+                    // Members can be matched by their BSSID and band as well.
+                    Row::Member(index) => pd
+                        .aps
+                        .get(*index)
+                        .map(|ap| format!("{} {} {} GHz", ap.ssid, ap.bssid, ap.band())),
+                }
+            }
+        })
+        .map(|entry| {
+            let c_ssid = std::ffi::CString::new(entry).expect("SSID contained internal null byte");
+            rofi::helper_token_match(tokens, c_ssid)
+        });
+    match_result.unwrap_or(0)
+}
+
+// The state machine always leaves the menu on a new dialog.
+fn handle_state(
+    sw: &'static mut Mode,
+    selected_line: usize,
+    data: &SharedData,
+    input: &std::ffi::CStr,
+) -> u32 {
+    state::handle_state(
+        Rc::new(RefCell::new(ModeView(sw))),
+        selected_line,
+        data,
+        input,
+    );
+    ffi::ModeMode_RESET_DIALOG
+}
+
+pub fn wifi_mode_result(
+    sw: &'static mut Mode,
+    menu_retv: i32,
+    input: &std::ffi::CStr,
+    selected_line: usize,
+) -> u32 {
+    let menu_retv = menu_retv as u32;
+    let Some(data) = rofi::get_private_state::<SharedData>(sw).cloned() else {
+        return ffi::ModeMode_MODE_EXIT;
+    };
+    let mut pd = data.borrow_mut();
+
+    // Listed rows are access points only while idle, otherwise they are the choices of a prompt.
+    if matches!(pd.state, AppState::Idle | AppState::Scanning)
+        && let Some(index) = pd.row_ap(selected_line)
+    {
+        pd.highlighted = Some(pd.aps[index].bssid.clone());
+    }
+
+    match menu_retv {
+        retv if retv & ffi::MenuReturn_MENU_NEXT != 0 => ffi::ModeMode_NEXT_DIALOG,
+        retv if retv & ffi::MenuReturn_MENU_PREVIOUS != 0 => ffi::ModeMode_PREVIOUS_DIALOG,
+        retv if retv & ffi::MenuReturn_MENU_QUICK_SWITCH != 0 => {
+            retv & ffi::MenuReturn_MENU_LOWER_MASK
+        }
+        retv if retv & ffi::MenuReturn_MENU_CUSTOM_COMMAND != 0 => {
+            // kb-custom-N is reported as N - 1
+            let custom_key = (retv & ffi::MenuReturn_MENU_LOWER_MASK) + 1;
+            if custom_key == pd.keys.rescan {
+                drop(pd);
+                // Does nothing unless idle, i.e. a scan is already running.
+                state::spawn_scan(Rc::new(RefCell::new(ModeView(sw))));
+                return ModeMode_RELOAD_DIALOG;
+            }
+
+            if custom_key == pd.keys.reveal {
+                // Shown by wifi_mode_get_message once the dialog reloads.
+                if pd.is_secret_input() {
+                    pd.reveal_password = !pd.reveal_password;
+                }
+                return ModeMode_RELOAD_DIALOG;
+            }
+
+            if custom_key == pd.keys.airplane {
+                if matches!(pd.state, AppState::Idle | AppState::Scanning) {
+                    let airplane_mode = !pd.radio.airplane_mode();
+                    drop(pd);
+                    state::set_radio(
+                        &data,
+                        Radio {
+                            wifi: !airplane_mode,
+                            wwan: !airplane_mode,
+                        },
+                    );
+                }
+                return ModeMode_RELOAD_DIALOG;
+            }
+
+            if custom_key == pd.keys.disconnect {
+                if matches!(pd.state, AppState::Idle | AppState::Scanning) {
+                    drop(pd);
+                    state::disconnect(&data);
+                }
+                return ModeMode_RELOAD_DIALOG;
+            }
+
+            if !matches!(pd.state, AppState::Idle | AppState::Scanning)
+                || pd.row_ap(selected_line).is_none()
+            {
+                return ModeMode_RELOAD_DIALOG;
+            }
+
+            if custom_key == pd.keys.lock_bssid {
+                pd.lock_bssid = true;
+                drop(pd);
+                return handle_state(sw, selected_line, &data, input);
+            } else if custom_key == pd.keys.expand {
+                pd.toggle_expanded(selected_line);
+            }
+            ModeMode_RELOAD_DIALOG
+        }
+        retv if retv & ffi::MenuReturn_MENU_OK != 0 => {
+            drop(pd);
+            handle_state(sw, selected_line, &data, input)
+        }
+        retv if retv & ffi::MenuReturn_MENU_ENTRY_DELETE != 0 => {
+            if let Some(index) = pd.row_ap(selected_line)
+                && pd.aps[index].setting_path.is_some()
+                && !matches!(
+                    pd.state,
+                    AppState::EnterpriseInput { .. }
+                        | AppState::InterfaceSelect
+                        | AppState::HiddenInput(_)
+                )
+            {
+                let forgotten = pd.aps[index].clone();
+                let backend = Rc::clone(&pd.backend);
+                drop(pd);
+                let _ = glib::MainContext::default().block_on(backend.forget(&forgotten.ssid));
+
+                // The whole network is forgotten, not just the selected access point.
+                let mut pd = data.borrow_mut();
+                for ap in pd
+                    .aps
+                    .iter_mut()
+                    .filter(|ap| ap.path == forgotten.path || ap.same_network(&forgotten))
+                {
+                    ap.setting_path = None;
+                }
+            }
+            ModeMode_RELOAD_DIALOG
+        }
+        retv if retv & MenuReturn_MENU_CUSTOM_INPUT != 0 => {
+            drop(pd);
+            handle_state(sw, selected_line, &data, input)
+        }
+        _ => {
+            if matches!(pd.state, AppState::Connecting(_)) {
+                drop(pd);
+                state::cancel_connect(&mut ModeView(sw), &data);
+                return ffi::ModeMode_RELOAD_DIALOG;
+            }
+            if matches!(
+                pd.state,
+                AppState::PasswordInput { .. }
+                    | AppState::EnterpriseInput { .. }
+                    | AppState::HiddenInput(_)
+            ) {
+                state::dismiss_input(&mut ModeView(sw), &mut pd);
+                return ffi::ModeMode_RELOAD_DIALOG;
+            }
+            ffi::ModeMode_MODE_EXIT
+        }
+    }
+}
@@ -1,23 +1,106 @@
 use crate::{
-    structure::{AccessPoint, AppState, BSSID, NetworkManagerDbusProxy, PrivateData},
+    backend::{AccessPointEvent, Backend, ConnectionState},
+    secret_agent::{self, SecretAgentEvent},
+    structure::{
+        AccessPoint, BSSID, ConnectionDetails, EapCredentials, NetworkManagerDbusProxy, Radio,
+        SavedProfile, Security, SecurityDescriptor, is_wep_key,
+    },
     utils,
 };
 use anyhow::{self, Context};
-use futures_util::{StreamExt, try_join};
-use std::collections::HashMap;
+use futures_channel::mpsc;
+use futures_util::{
+    FutureExt, StreamExt,
+    future::LocalBoxFuture,
+    stream::{self, BoxStream, SelectAll},
+    try_join,
+};
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
 use zbus::{
-    Connection, Proxy, blocking,
+    Connection, MatchRule, MessageStream, Proxy,
     fdo::PropertiesProxy,
+    proxy::SignalStream,
     zvariant::{Array, ObjectPath, OwnedObjectPath, OwnedValue, Value},
 };
 
 // well, maybe should have just used zbus-xmlgen...
 
 // Using async so, the scanning animation can be shown while, zbus requests to networkmanager for rescan.
-pub async fn setup_dbus(iface: &str) -> anyhow::Result<NetworkManagerDbusProxy> {
-    let con = Connection::system().await?;
+// Without an interface, the last picked one is used, otherwise the first found.
+// Every Wi-Fi interface is listed in `interfaces`, so the caller can offer a choice when there are several.
+pub async fn setup_dbus(iface: Option<&str>) -> anyhow::Result<NetworkManagerDbusProxy> {
+    setup_dbus_with(Connection::system().await?, iface).await
+}
+
+// Same as `setup_dbus`, on a bus of the caller's, e.g. the private one of the tests.
+pub async fn setup_dbus_with(
+    con: Connection,
+    iface: Option<&str>,
+) -> anyhow::Result<NetworkManagerDbusProxy> {
+    let mut devices = wifi_devices(&con).await?;
+
+    // The last picked interface goes first.
+    if let Some(last_iface) = utils::load_last_interface()
+        && let Some(index) = devices.iter().position(|(name, _)| *name == last_iface)
+    {
+        let device = devices.remove(index);
+        devices.insert(0, device);
+    }
+
+    let (iface_name, dev_path) = match iface {
+        Some(iface) => devices
+            .iter()
+            .find(|(name, _)| name == iface)
+            .cloned()
+            .with_context(|| format!("Wi-Fi interface {} not found", iface))?,
+        None => devices
+            .first()
+            .cloned()
+            .context("No Wi-Fi interface found")?,
+    };
+
+    let (wifi_proxy, property_proxy) = wireless_proxies(&con, &dev_path).await?;
+
+    Ok(NetworkManagerDbusProxy {
+        interfaces: devices.into_iter().map(|(name, _)| name).collect(),
+        iface: iface_name,
+        con,
+        dev_path,
+        property_proxy,
+        wifi_proxy,
+        profiles: Arc::default(),
+        pending: RefCell::default(),
+    })
+}
+
+// Points the proxies to another Wi-Fi interface.
+pub async fn select_interface(
+    nm_dbus: &mut NetworkManagerDbusProxy,
+    iface: &str,
+) -> anyhow::Result<()> {
+    let (iface_name, dev_path) = wifi_devices(&nm_dbus.con)
+        .await?
+        .into_iter()
+        .find(|(name, _)| name == iface)
+        .with_context(|| format!("Wi-Fi interface {} not found", iface))?;
+
+    let (wifi_proxy, property_proxy) = wireless_proxies(&nm_dbus.con, &dev_path).await?;
+
+    nm_dbus.iface = iface_name;
+    nm_dbus.dev_path = dev_path;
+    nm_dbus.wifi_proxy = wifi_proxy;
+    nm_dbus.property_proxy = property_proxy;
+    Ok(())
+}
+
+// Lists the interface name and device path of every Wi-Fi device.
+async fn wifi_devices(con: &Connection) -> anyhow::Result<Vec<(String, OwnedObjectPath)>> {
     let nm_proxy = Proxy::new(
-        &con,
+        con,
         "org.freedesktop.NetworkManager",
         "/org/freedesktop/NetworkManager",
         "org.freedesktop.NetworkManager",
@@ -28,9 +111,10 @@ pub async fn setup_dbus(iface: &str) -> anyhow::Result<NetworkManagerDbusProxy>
     // Using Deperacted "GetDevices"
     let devices: Vec<zbus::zvariant::OwnedObjectPath> = nm_proxy.call("GetDevices", &()).await?;
 
+    let mut wifi_devices = Vec::new();
     for dev_path in devices.into_iter() {
         let dev_proxy = Proxy::new(
-            &con,
+            con,
             "org.freedesktop.NetworkManager",
             &dev_path,
             "org.freedesktop.NetworkManager.Device",
@@ -40,27 +124,101 @@ pub async fn setup_dbus(iface: &str) -> anyhow::Result<NetworkManagerDbusProxy>
         let iface_name: String = dev_proxy.get_property("Interface").await?;
         let dev_type: u32 = dev_proxy.get_property("DeviceType").await?;
 
-        if dev_type == 2 && iface_name == iface {
-            let wifi_proxy = Proxy::new(
-                &con,
-                "org.freedesktop.NetworkManager",
-                &*dev_path,
-                "org.freedesktop.NetworkManager.Device.Wireless",
-            )
-            .await?;
-
-            let property_proxy =
-                PropertiesProxy::new(&con, "org.freedesktop.NetworkManager", &*dev_path).await?;
-
-            return Ok(NetworkManagerDbusProxy {
-                con,
-                dev_path,
-                property_proxy,
-                wifi_proxy,
-            });
+        // NM_DEVICE_TYPE_WIFI
+        if dev_type == 2 {
+            wifi_devices.push((iface_name, dev_path));
         }
     }
-    anyhow::bail!("Failed to create dbus proxies")
+    Ok(wifi_devices)
+}
+
+async fn wireless_proxies(
+    con: &Connection,
+    dev_path: &OwnedObjectPath,
+) -> anyhow::Result<(Proxy<'static>, PropertiesProxy<'static>)> {
+    let wifi_proxy = Proxy::new(
+        con,
+        "org.freedesktop.NetworkManager",
+        dev_path.to_owned(),
+        "org.freedesktop.NetworkManager.Device.Wireless",
+    )
+    .await?;
+
+    let property_proxy =
+        PropertiesProxy::new(con, "org.freedesktop.NetworkManager", dev_path.to_owned()).await?;
+
+    Ok((wifi_proxy, property_proxy))
+}
+
+async fn manager_proxy(conn: &Connection) -> zbus::Result<Proxy<'static>> {
+    Proxy::new(
+        conn,
+        "org.freedesktop.NetworkManager",
+        "/org/freedesktop/NetworkManager",
+        "org.freedesktop.NetworkManager",
+    )
+    .await
+}
+
+pub async fn get_radio(conn: &Connection) -> anyhow::Result<Radio> {
+    let nm_proxy = manager_proxy(conn).await?;
+    let (wifi, wwan) = try_join!(
+        nm_proxy.get_property::<bool>("WirelessEnabled"),
+        nm_proxy.get_property::<bool>("WwanEnabled"),
+    )?;
+    Ok(Radio { wifi, wwan })
+}
+
+// NetworkManager takes care of rfkill, and of the devices, e.g. the access points of a disabled radio are removed.
+pub async fn set_radio(conn: &Connection, radio: Radio) -> anyhow::Result<()> {
+    let nm_proxy = manager_proxy(conn).await?;
+    nm_proxy.set_property("WirelessEnabled", radio.wifi).await?;
+    nm_proxy.set_property("WwanEnabled", radio.wwan).await?;
+    Ok(())
+}
+
+// Yields whenever a radio switch is flipped, whether by the plugin, a hardware key or externally.
+pub async fn radio_changes(conn: &Connection) -> anyhow::Result<BoxStream<'static, ()>> {
+    let nm_proxy = manager_proxy(conn).await?;
+    let wifi = nm_proxy
+        .receive_property_changed::<bool>("WirelessEnabled")
+        .await;
+    let wwan = nm_proxy
+        .receive_property_changed::<bool>("WwanEnabled")
+        .await;
+    Ok(stream::select(wifi.map(|_| ()), wwan.map(|_| ())).boxed())
+}
+
+// Yields whenever NetworkManager or the device change state.
+// The device's also tells Wi-Fi changes while e.g. ethernet stays connected.
+pub async fn state_changes(
+    conn: &Connection,
+    dev_path: &OwnedObjectPath,
+) -> anyhow::Result<BoxStream<'static, ()>> {
+    let nm_proxy = manager_proxy(conn).await?;
+    let device_proxy = Proxy::new(
+        conn,
+        "org.freedesktop.NetworkManager",
+        dev_path.clone(),
+        "org.freedesktop.NetworkManager.Device",
+    )
+    .await?;
+
+    let global = nm_proxy.receive_signal("StateChanged").await?;
+    let device = device_proxy.receive_signal("StateChanged").await?;
+    Ok(stream::select(global.map(|_| ()), device.map(|_| ())).boxed())
+}
+
+// https://people.freedesktop.org/~lkundrak/nm-dbus-api/nm-dbus-types.html#NMDeviceState
+pub async fn device_state(conn: &Connection, dev_path: &OwnedObjectPath) -> anyhow::Result<u32> {
+    let device_proxy = Proxy::new(
+        conn,
+        "org.freedesktop.NetworkManager",
+        dev_path,
+        "org.freedesktop.NetworkManager.Device",
+    )
+    .await?;
+    Ok(device_proxy.get_property("State").await?)
 }
 
 pub async fn trigger_rescan(
@@ -83,59 +241,46 @@ pub async fn trigger_rescan(
     anyhow::bail!("Expected Some, got None")
 }
 
-pub async fn fetch_aps(
-    conn: &Connection,
-    wifi_proxy: &Proxy<'_>,
-) -> anyhow::Result<Vec<AccessPoint>> {
-    let raw_aps: Vec<zbus::zvariant::OwnedObjectPath> =
-        wifi_proxy.call("GetAllAccessPoints", &()).await?;
-
-    let mut aps = Vec::with_capacity(raw_aps.len());
-    for ap_path in raw_aps {
-        let ap_proxy = Proxy::new(
-            conn,
-            "org.freedesktop.NetworkManager",
-            &ap_path,
-            "org.freedesktop.NetworkManager.AccessPoint",
-        )
-        .await?;
-
-        let (wpa_flags, rsn_flags, ssid, signal_strength, bssid, frequency, auth_flag): (
-            u32,
-            u32,
-            Vec<u8>,
-            u8,
-            String,
-            u32,
-            u32,
-        ) = try_join!(
-            ap_proxy.get_property::<u32>("WpaFlags"),
-            ap_proxy.get_property::<u32>("RsnFlags"),
-            ap_proxy.get_property::<Vec<u8>>("Ssid"),
-            ap_proxy.get_property::<u8>("Strength"),
-            ap_proxy.get_property::<String>("HwAddress"),
-            ap_proxy.get_property::<u32>("Frequency"),
-            ap_proxy.get_property::<u32>("Flags"),
-        )?;
+pub async fn fetch_ap(conn: &Connection, ap_path: OwnedObjectPath) -> anyhow::Result<AccessPoint> {
+    let ap_proxy = Proxy::new(
+        conn,
+        "org.freedesktop.NetworkManager",
+        &ap_path,
+        "org.freedesktop.NetworkManager.AccessPoint",
+    )
+    .await?;
 
-        // https://people.freedesktop.org/~lkundrak/nm-dbus-api/nm-dbus-types.html#NM80211ApSecurityFlags
+    let (wpa_flags, rsn_flags, ssid, signal_strength, bssid, frequency, auth_flag): (
+        u32,
+        u32,
+        Vec<u8>,
+        u8,
+        String,
+        u32,
+        u32,
+    ) = try_join!(
+        ap_proxy.get_property::<u32>("WpaFlags"),
+        ap_proxy.get_property::<u32>("RsnFlags"),
+        ap_proxy.get_property::<Vec<u8>>("Ssid"),
+        ap_proxy.get_property::<u8>("Strength"),
+        ap_proxy.get_property::<String>("HwAddress"),
+        ap_proxy.get_property::<u32>("Frequency"),
+        ap_proxy.get_property::<u32>("Flags"),
+    )?;
 
-        if (wpa_flags & 512 == 512) || (rsn_flags & 512 == 512) {
-            //flag 256 is NM_802_11_AP_SEC_KEY_MGMT_802_1X
-            continue;
-        }
-        //
-        aps.push(AccessPoint {
-            bssid,
-            frequency,
-            is_protected: auth_flag & 1 == 1, //https://people.freedesktop.org/~lkundrak/nm-dbus-api/nm-dbus-types.html#NM80211ApFlags
-            signal_strength,
-            ssid: String::from_utf8_lossy(&ssid).to_string(),
-            setting_path: None,
-        });
-    }
+    Ok(AccessPoint {
+        path: ap_path,
+        bssid,
+        frequency,
+        security: SecurityDescriptor::from_flags(auth_flag, wpa_flags, rsn_flags),
+        signal_strength,
+        ssid: String::from_utf8_lossy(&ssid).to_string(),
+        setting_path: None,
+    })
+}
 
-    // Looking into all the saved connections.
+// Every saved Wi-Fi profile.
+pub async fn saved_profiles(conn: &Connection) -> anyhow::Result<Vec<SavedProfile>> {
     let settings_proxy = Proxy::new(
         &conn,
         "org.freedesktop.NetworkManager",
@@ -146,69 +291,201 @@ pub async fn fetch_aps(
 
     let paths: Vec<OwnedObjectPath> = settings_proxy.call("ListConnections", &()).await?;
 
+    let mut profiles = Vec::new();
     for path in paths {
-        let conn_proxy = Proxy::new(
-            &conn,
-            "org.freedesktop.NetworkManager",
-            &path,
-            "org.freedesktop.NetworkManager.Settings.Connection",
-        )
-        .await?;
+        if let Some(profile) = saved_profile(conn, path).await? {
+            profiles.push(profile);
+        }
+    }
+    Ok(profiles)
+}
 
-        let settings: HashMap<String, HashMap<String, OwnedValue>> =
-            conn_proxy.call("GetSettings", &()).await?;
-
-        let connection = settings
-            .get("connection")
-            .context("Missing 'connection' key")?;
-
-        let uuid: &str = connection
-            .get("uuid")
-            .and_then(|v| v.try_into().ok())
-            .context("Missing uuid")?;
-        let con_type: &str = connection
-            .get("type")
-            .and_then(|v| v.try_into().ok())
-            .context("Missing type")?;
-
-        if uuid.starts_with(utils::UUIDV4_PREFIX) && con_type == "802-11-wireless" {
-            let Some(raw_bssid) = settings
-                .get("802-11-wireless")
-                .and_then(|x| x.get("bssid"))
-                .and_then(|v| v.downcast_ref::<Array>().ok())
-                .map(|v| v.to_vec())
-            else {
-                continue;
-            };
+// Reads a saved profile, `None` unless it's a Wi-Fi one.
+async fn saved_profile(
+    conn: &Connection,
+    path: OwnedObjectPath,
+) -> anyhow::Result<Option<SavedProfile>> {
+    let conn_proxy = Proxy::new(
+        &conn,
+        "org.freedesktop.NetworkManager",
+        &path,
+        "org.freedesktop.NetworkManager.Settings.Connection",
+    )
+    .await?;
 
-            let bssid_bytes = raw_bssid
-                .iter()
-                .filter_map(|x| x.downcast_ref::<u8>().ok())
-                .collect::<Vec<u8>>();
+    let settings: HashMap<String, HashMap<String, OwnedValue>> =
+        conn_proxy.call("GetSettings", &()).await?;
+
+    let connection = settings
+        .get("connection")
+        .context("Missing 'connection' key")?;
+
+    let con_type: &str = connection
+        .get("type")
+        .and_then(|v| v.try_into().ok())
+        .context("Missing type")?;
 
-            let bssid = bssid_bytes
+    // Externally created profiles are trusted too, missing secrets are asked by the secret agent.
+    if con_type != "802-11-wireless" {
+        return Ok(None);
+    }
+
+    let Some(ssid_bytes) = setting_bytes(&settings, "802-11-wireless", "ssid") else {
+        return Ok(None);
+    };
+
+    // A missing security section means an open network.
+    let key_mgmt: Option<&str> = settings
+        .get("802-11-wireless-security")
+        .and_then(|x| x.get("key-mgmt"))
+        .and_then(|v| v.try_into().ok());
+
+    let bssid = setting_bytes(&settings, "802-11-wireless", "bssid")
+        .filter(|bytes| !bytes.is_empty())
+        .map(|bytes| {
+            bytes
                 .iter()
                 .map(|v| format!("{:02X}", v))
                 .collect::<Vec<String>>()
-                .join(":");
+                .join(":")
+        });
 
-            for ap in &mut aps {
-                if ap.bssid == bssid {
-                    ap.setting_path = Some(path.to_owned());
+    Ok(Some(SavedProfile {
+        path,
+        ssid: String::from_utf8_lossy(&ssid_bytes).to_string(),
+        key_mgmt: key_mgmt.map(str::to_string),
+        bssid,
+    }))
+}
+
+// Marks the access points with their saved profile, the previous marks are dropped.
+pub fn match_profiles(profiles: &[SavedProfile], aps: &mut [AccessPoint]) {
+    for ap in aps.iter_mut() {
+        ap.setting_path = None;
+    }
+
+    for profile in profiles {
+        // A profile locked to a BSSID only applies to that access point,
+        // and wins over a profile matching by SSID.
+        if let Some(bssid) = &profile.bssid {
+            for ap in aps.iter_mut() {
+                if ap.bssid == *bssid {
+                    ap.setting_path = Some(profile.path.clone());
+                }
+            }
+        } else {
+            for ap in aps.iter_mut() {
+                if ap.setting_path.is_none()
+                    && ap.ssid == profile.ssid
+                    && ap.security.class.accepts(profile.key_mgmt.as_deref())
+                {
+                    ap.setting_path = Some(profile.path.clone());
                 }
             }
         }
     }
+}
+
+// Streams the changes of the device's access points, so the list can be updated one access point at a time.
+// The profiles are kept in sync too, as profiles saved or deleted meanwhile change which access points have one.
+pub async fn access_point_events(
+    conn: &Connection,
+    wifi_proxy: &Proxy<'static>,
+    profiles: &Arc<Mutex<Vec<SavedProfile>>>,
+) -> anyhow::Result<SelectAll<BoxStream<'static, AccessPointEvent>>> {
+    let added = wifi_proxy
+        .receive_signal("AccessPointAdded")
+        .await?
+        .filter_map(|msg| async move {
+            let path: OwnedObjectPath = msg.body().deserialize().ok()?;
+            Some(AccessPointEvent::Added(path))
+        });
+
+    let removed = wifi_proxy
+        .receive_signal("AccessPointRemoved")
+        .await?
+        .filter_map(|msg| async move {
+            let path: OwnedObjectPath = msg.body().deserialize().ok()?;
+            Some(AccessPointEvent::Removed(path))
+        });
+
+    // A single match rule covers the properties of every access point, instead of a proxy per access point.
+    let rule = MatchRule::builder()
+        .msg_type(zbus::message::Type::Signal)
+        .sender("org.freedesktop.NetworkManager")?
+        .interface("org.freedesktop.DBus.Properties")?
+        .member("PropertiesChanged")?
+        .path_namespace("/org/freedesktop/NetworkManager/AccessPoint")?
+        .arg(0, "org.freedesktop.NetworkManager.AccessPoint")?
+        .build();
+
+    let strength = MessageStream::for_match_rule(rule, conn, None)
+        .await?
+        .filter_map(|msg| async move {
+            let msg = msg.ok()?;
+            let path = OwnedObjectPath::from(msg.header().path()?.to_owned());
+            let (_, changed, _): (String, HashMap<String, OwnedValue>, Vec<String>) =
+                msg.body().deserialize().ok()?;
+            let strength = u8::try_from(changed.get("Strength")?).ok()?;
+            Some(AccessPointEvent::Strength(path, strength))
+        });
+
+    let settings_proxy = Proxy::new(
+        conn,
+        "org.freedesktop.NetworkManager",
+        "/org/freedesktop/NetworkManager/Settings",
+        "org.freedesktop.NetworkManager.Settings",
+    )
+    .await?;
+
+    let saved = settings_proxy
+        .receive_signal("NewConnection")
+        .await?
+        .filter_map({
+            let conn = conn.clone();
+            let profiles = Arc::clone(profiles);
+            move |msg| {
+                let conn = conn.clone();
+                let profiles = Arc::clone(&profiles);
+                async move {
+                    let path: OwnedObjectPath = msg.body().deserialize().ok()?;
+                    let profile = saved_profile(&conn, path).await.ok()??;
+                    profiles.lock().unwrap().push(profile);
+                    Some(AccessPointEvent::Profiles)
+                }
+            }
+        });
+
+    let deleted = settings_proxy
+        .receive_signal("ConnectionRemoved")
+        .await?
+        .filter_map({
+            let profiles = Arc::clone(profiles);
+            move |msg| {
+                let profiles = Arc::clone(&profiles);
+                async move {
+                    let path: OwnedObjectPath = msg.body().deserialize().ok()?;
+                    let mut profiles = profiles.lock().unwrap();
+                    let index = profiles.iter().position(|p| p.path == path)?;
+                    profiles.remove(index);
+                    Some(AccessPointEvent::Profiles)
+                }
+            }
+        });
 
-    Ok(aps)
+    Ok(stream::select_all([
+        added.boxed(),
+        removed.boxed(),
+        strength.boxed(),
+        saved.boxed(),
+        deleted.boxed(),
+    ]))
 }
 
 pub async fn get_active_ap(
     conn: &zbus::Connection,
     wifi_dev_proxy: &Proxy<'static>,
 ) -> anyhow::Result<Option<(BSSID, OwnedObjectPath)>> {
-    // let active_ap_path: OwnedObjectPath = wifi_dev_proxy.get_property("ActiveAccessPoint").await?;
-
     let device_proxy = Proxy::new(
         &conn,
         "org.freedesktop.NetworkManager",
@@ -233,6 +510,10 @@ pub async fn get_active_ap(
         let settings_path: OwnedObjectPath = active_conn_proxy.get_property("Connection").await?;
         let specific_obj: OwnedObjectPath =
             active_conn_proxy.get_property("SpecificObject").await?;
+        // Still activating, or the radio was just turned off.
+        if specific_obj.as_str() == "/" {
+            return Ok(None);
+        }
 
         let ap_proxy = Proxy::new(
             &conn,
@@ -247,11 +528,137 @@ pub async fn get_active_ap(
     }
 }
 
+// The access point the device is connected, or connecting, to.
+pub async fn active_access_point(
+    conn: &Connection,
+    wifi_proxy: &Proxy<'_>,
+) -> anyhow::Result<Option<AccessPoint>> {
+    let ap_path: OwnedObjectPath = wifi_proxy.get_property("ActiveAccessPoint").await?;
+    if ap_path.as_str() == "/" {
+        return Ok(None);
+    }
+    Ok(Some(fetch_ap(conn, ap_path).await?))
+}
+
+// Reads the addresses of the device's active connection, `None` when it isn't connected.
+pub async fn connection_details(
+    conn: &Connection,
+    dev_path: &OwnedObjectPath,
+) -> anyhow::Result<Option<ConnectionDetails>> {
+    let device_proxy = Proxy::new(
+        conn,
+        "org.freedesktop.NetworkManager",
+        dev_path,
+        "org.freedesktop.NetworkManager.Device",
+    )
+    .await?;
+
+    let active_path: OwnedObjectPath = device_proxy.get_property("ActiveConnection").await?;
+    if active_path.as_str() == "/" {
+        return Ok(None);
+    }
+
+    let wireless_proxy = Proxy::new(
+        conn,
+        "org.freedesktop.NetworkManager",
+        dev_path,
+        "org.freedesktop.NetworkManager.Device.Wireless",
+    )
+    .await?;
+
+    let (ip4_path, ip6_path, bitrate): (OwnedObjectPath, OwnedObjectPath, u32) = try_join!(
+        device_proxy.get_property::<OwnedObjectPath>("Ip4Config"),
+        device_proxy.get_property::<OwnedObjectPath>("Ip6Config"),
+        wireless_proxy.get_property::<u32>("Bitrate"),
+    )?;
+
+    let mut details = ConnectionDetails {
+        bitrate,
+        ..Default::default()
+    };
+
+    // The path is `/` until the configuration is done, e.g. during DHCP.
+    if ip4_path.as_str() != "/" {
+        let ip4_proxy = Proxy::new(
+            conn,
+            "org.freedesktop.NetworkManager",
+            &ip4_path,
+            "org.freedesktop.NetworkManager.IP4Config",
+        )
+        .await?;
+
+        let (addresses, gateway, nameservers): (
+            Vec<HashMap<String, OwnedValue>>,
+            String,
+            Vec<HashMap<String, OwnedValue>>,
+        ) = try_join!(
+            ip4_proxy.get_property::<Vec<HashMap<String, OwnedValue>>>("AddressData"),
+            ip4_proxy.get_property::<String>("Gateway"),
+            ip4_proxy.get_property::<Vec<HashMap<String, OwnedValue>>>("NameserverData"),
+        )?;
+
+        details.ipv4 = address_data(&addresses);
+        details.gateway4 = Some(gateway).filter(|g| !g.is_empty());
+        details.dns = nameservers
+            .iter()
+            .filter_map(|ns| {
+                ns.get("address")
+                    .and_then(|v| String::try_from(v.clone()).ok())
+            })
+            .collect();
+    }
+
+    if ip6_path.as_str() != "/" {
+        let ip6_proxy = Proxy::new(
+            conn,
+            "org.freedesktop.NetworkManager",
+            &ip6_path,
+            "org.freedesktop.NetworkManager.IP6Config",
+        )
+        .await?;
+
+        let (addresses, gateway, nameservers): (
+            Vec<HashMap<String, OwnedValue>>,
+            String,
+            Vec<Vec<u8>>,
+        ) = try_join!(
+            ip6_proxy.get_property::<Vec<HashMap<String, OwnedValue>>>("AddressData"),
+            ip6_proxy.get_property::<String>("Gateway"),
+            ip6_proxy.get_property::<Vec<Vec<u8>>>("Nameservers"),
+        )?;
+
+        details.ipv6 = address_data(&addresses);
+        details.gateway6 = Some(gateway).filter(|g| !g.is_empty());
+        // Unlike IPv4, there is no NameserverData, the addresses are raw bytes.
+        details
+            .dns
+            .extend(nameservers.into_iter().filter_map(|bytes| {
+                let octets: [u8; 16] = bytes.try_into().ok()?;
+                Some(std::net::Ipv6Addr::from(octets).to_string())
+            }));
+    }
+
+    Ok(Some(details))
+}
+
+// Formats the `AddressData` of an IP config as `address/prefix`.
+fn address_data(addresses: &[HashMap<String, OwnedValue>]) -> Vec<String> {
+    addresses
+        .iter()
+        .filter_map(|data| {
+            let address = String::try_from(data.get("address")?.clone()).ok()?;
+            let prefix = u32::try_from(data.get("prefix")?).ok()?;
+            Some(format!("{}/{}", address, prefix))
+        })
+        .collect()
+}
+
+// Returns the profile, and the active connection that is being activated.
 pub async fn connect_pre_existing_access_point(
     conn: &Connection,
     access_point: &AccessPoint,
     dev_path: &OwnedObjectPath,
-) -> anyhow::Result<OwnedObjectPath> {
+) -> anyhow::Result<(OwnedObjectPath, OwnedObjectPath)> {
     let nm_proxy = Proxy::new(
         conn,
         "org.freedesktop.NetworkManager",
@@ -260,17 +667,14 @@ pub async fn connect_pre_existing_access_point(
     )
     .await?;
 
+    let setting_path = access_point
+        .setting_path
+        .as_ref()
+        .context("setting path is required")?;
     let active_ap_path: OwnedObjectPath = nm_proxy
         .call(
             "ActivateConnection",
-            &(
-                &access_point
-                    .setting_path
-                    .as_ref()
-                    .expect("setting path is required"),
-                dev_path,
-                ObjectPath::try_from("/")?,
-            ),
+            &(setting_path, dev_path, ObjectPath::try_from("/")?),
         )
         .await?;
 
@@ -283,18 +687,18 @@ pub async fn connect_pre_existing_access_point(
     .await?;
 
     let settings: OwnedObjectPath = active_conn_proxy.get_property("Connection").await?;
-    Ok(settings)
+    Ok((settings, active_ap_path))
 }
 
-
-// todo!(): Remove the duct tape and handle hidden Wi-Fi properly.
 pub async fn create_and_connect_access_point(
     conn: &Connection,
     access_point: &AccessPoint,
     dev_path: &OwnedObjectPath,
     password: Option<String>,
-    hidden: Option<String>,
-) -> anyhow::Result<OwnedObjectPath> {
+    hidden: bool,
+    eap: Option<EapCredentials>,
+    lock_bssid: bool,
+) -> anyhow::Result<(OwnedObjectPath, OwnedObjectPath)> {
     let nm_proxy = Proxy::new(
         &conn,
         "org.freedesktop.NetworkManager",
@@ -312,25 +716,60 @@ pub async fn create_and_connect_access_point(
 
     let mut wireless_section = HashMap::new();
     wireless_section.insert("ssid", Value::from(access_point.ssid.as_bytes()));
-    wireless_section.insert("hidden", hidden.is_some().into());
+    // A hidden network doesn't answer broadcast probes, so NetworkManager probes for its SSID.
+    wireless_section.insert("hidden", hidden.into());
     wireless_section.insert("mode", Value::from("infrastructure"));
 
-    if hidden.is_none() {
+    // Without a BSSID, the profile follows the SSID across every access point of the network.
+    if lock_bssid && !hidden {
         wireless_section.insert("bssid", Value::from(bssid_to_bytes(&access_point.bssid)));
     }
 
     connection_settings.insert("802-11-wireless", wireless_section);
 
-    if access_point.is_protected {
-        let mut s_wifi_sec = HashMap::new();
-        s_wifi_sec.insert("key-mgmt", Value::from("wpa-psk"));
-        s_wifi_sec.insert(
-            "psk",
-            Value::from(
-                password.expect("If access point is set to protected, password should exist"),
-            ),
-        );
+    let mut s_wifi_sec = HashMap::new();
+    if let Some(key_mgmt) = access_point.key_mgmt() {
+        s_wifi_sec.insert("key-mgmt", Value::from(key_mgmt));
+    }
 
+    match access_point.security.class {
+        Security::Open | Security::Owe => (),
+        Security::Wep => {
+            let key = password.context("WEP access point requires a key")?;
+            // The key itself, or a passphrase hashed into one.
+            // https://people.freedesktop.org/~lkundrak/nm-dbus-api/nm-settings.html, wep-key-type
+            let key_type: u32 = if is_wep_key(&key) { 1 } else { 2 };
+            s_wifi_sec.insert("auth-alg", Value::from("open"));
+            s_wifi_sec.insert("wep-key-type", Value::from(key_type));
+            s_wifi_sec.insert("wep-key0", Value::from(key));
+        }
+        Security::WpaPsk | Security::Sae | Security::Mixed => {
+            let psk = password.context("Protected access point requires a password")?;
+            s_wifi_sec.insert("psk", Value::from(psk));
+        }
+        Security::Enterprise => {
+            let eap = eap.context("Enterprise access point requires 802.1X credentials")?;
+
+            let mut s_8021x = HashMap::new();
+            s_8021x.insert("eap", Value::from(vec![eap.eap().to_string()]));
+            if let Some(phase2) = eap.phase2() {
+                s_8021x.insert("phase2-auth", Value::from(phase2.to_string()));
+            }
+            s_8021x.insert("identity", Value::from(eap.identity));
+            s_8021x.insert("password", Value::from(eap.password));
+
+            // NM expects a path as a NUL terminated "file://" byte string.
+            if let Some(ca_cert) = eap.ca_cert {
+                let mut ca_cert_bytes = format!("file://{}", ca_cert).into_bytes();
+                ca_cert_bytes.push(0);
+                s_8021x.insert("ca-cert", Value::from(ca_cert_bytes));
+            }
+
+            connection_settings.insert("802-1x", s_8021x);
+        }
+    }
+
+    if !s_wifi_sec.is_empty() {
         connection_settings.insert("802-11-wireless-security", s_wifi_sec);
     }
 
@@ -342,43 +781,83 @@ pub async fn create_and_connect_access_point(
         .call_method("AddAndActivateConnection", &body)
         .await?
         .body();
-    let (sys, _): (OwnedObjectPath, OwnedObjectPath) = message.deserialize()?;
+    let (sys, active): (OwnedObjectPath, OwnedObjectPath) = message.deserialize()?;
 
-    Ok(sys.to_owned())
+    Ok((sys, active))
 }
 
-pub async fn network_state<'a>(
+// The device's state changes, subscribed before activating, so a verdict that comes right away isn't missed.
+pub async fn device_states(
     conn: &Connection,
-    // active_conn_path: ObjectPath<'a>,
     device_path: &OwnedObjectPath,
-) -> anyhow::Result<u32> {
-    // let active_proxy = Proxy::new(
-    //     &conn,
-    //     "org.freedesktop.NetworkManager",
-    //     active_conn_path,
-    //     "org.freedesktop.NetworkManager.Connection.Active",
-    // )
-    // .await?;
-
+) -> anyhow::Result<SignalStream<'static>> {
     let device_proxy = Proxy::new(
         conn,
         "org.freedesktop.NetworkManager",
-        device_path,
+        device_path.to_owned(),
         "org.freedesktop.NetworkManager.Device",
     )
     .await?;
-    let mut device_signals = device_proxy.receive_signal("StateChanged").await?;
-    while let Some(mg) = device_signals.next().await {
+    Ok(device_proxy.receive_signal("StateChanged").await?)
+}
+
+// Resolves once the activation `active` is done, 0 when connected, otherwise the NMDeviceStateReason.
+// The device's transitions don't tell the activation, so one only counts when `active` ended the same way,
+// e.g. a previous activation may fail while this one starts.
+pub async fn network_state(
+    conn: Connection,
+    mut device_states: SignalStream<'static>,
+    active: OwnedObjectPath,
+) -> anyhow::Result<u32> {
+    while let Some(mg) = device_states.next().await {
         let (new_state, _old_state, reason): (u32, u32, u32) = mg.body().deserialize()?;
-        if new_state == 100 {
-            return Ok(0);
-        } else if new_state == 120 {
-            return Ok(reason);
+        // https://people.freedesktop.org/~lkundrak/nm-dbus-api/nm-dbus-types.html#NMActiveConnectionState
+        match new_state {
+            // NM_DEVICE_STATE_ACTIVATED, along with NM_ACTIVE_CONNECTION_STATE_ACTIVATED
+            100 if activation_state(&conn, &active).await == 2 => return Ok(0),
+            // NM_DEVICE_STATE_FAILED, along with NM_ACTIVE_CONNECTION_STATE_DEACTIVATED
+            120 if activation_state(&conn, &active).await == 4 => return Ok(reason),
+            _ => (),
         }
     }
     anyhow::bail!("Unexpected result")
 }
 
+// The NMActiveConnectionState of an activation, deactivated once it's gone from the bus.
+async fn activation_state(conn: &Connection, active: &OwnedObjectPath) -> u32 {
+    let state = async {
+        let active_proxy = Proxy::new(
+            conn,
+            "org.freedesktop.NetworkManager",
+            active,
+            "org.freedesktop.NetworkManager.Connection.Active",
+        )
+        .await?;
+        active_proxy.get_property::<u32>("State").await
+    };
+    state.await.unwrap_or(4)
+}
+
+// Reads a byte array property, e.g. ssid or bssid, out of a profile's settings.
+fn setting_bytes(
+    settings: &HashMap<String, HashMap<String, OwnedValue>>,
+    section: &str,
+    key: &str,
+) -> Option<Vec<u8>> {
+    let raw_bytes = settings
+        .get(section)
+        .and_then(|x| x.get(key))
+        .and_then(|v| v.downcast_ref::<Array>().ok())
+        .map(|v| v.to_vec())?;
+
+    Some(
+        raw_bytes
+            .iter()
+            .filter_map(|x| x.downcast_ref::<u8>().ok())
+            .collect(),
+    )
+}
+
 fn bssid_to_bytes(bssid: &str) -> Vec<u8> {
     bssid
         .split(':')
@@ -401,102 +880,270 @@ pub async fn forget_config(
     Ok(())
 }
 
-pub fn forget_ssid_blocking(con: &blocking::Connection, ssid: &str) -> anyhow::Result<()> {
-    let settings_proxy = blocking::Proxy::new(
-        &con,
-        "org.freedesktop.NetworkManager",
-        "/org/freedesktop/NetworkManager/Settings",
-        "org.freedesktop.NetworkManager.Settings",
-    )?;
+// Stops an activation that is still in progress, e.g. timed out or canceled.
+pub async fn deactivate_connection(
+    conn: &Connection,
+    active_path: &OwnedObjectPath,
+) -> anyhow::Result<()> {
+    let nm_proxy = manager_proxy(conn).await?;
+    let _: () = nm_proxy
+        .call("DeactivateConnection", &(active_path))
+        .await?;
+    Ok(())
+}
 
-    let paths: Vec<OwnedObjectPath> = settings_proxy.call("ListConnections", &())?;
+// Deactivates the active connection of the device, its profile stays saved.
+pub async fn disconnect(conn: &Connection, dev_path: &OwnedObjectPath) -> anyhow::Result<()> {
+    let device_proxy = Proxy::new(
+        conn,
+        "org.freedesktop.NetworkManager",
+        dev_path,
+        "org.freedesktop.NetworkManager.Device",
+    )
+    .await?;
 
-    for setting_path in paths {
-        let conn_proxy = blocking::Proxy::new(
-            &con,
-            "org.freedesktop.NetworkManager",
-            &setting_path,
-            "org.freedesktop.NetworkManager.Settings.Connection",
-        )?;
+    let active_path: OwnedObjectPath = device_proxy.get_property("ActiveConnection").await?;
+    if active_path.as_str() == "/" {
+        return Ok(());
+    }
+    deactivate_connection(conn, &active_path).await
+}
 
-        let settings: HashMap<String, HashMap<String, OwnedValue>> =
-            conn_proxy.call("GetSettings", &())?;
-
-        let connection = settings
-            .get("connection")
-            .context("Missing 'connection' key")?;
-
-        let conn_ssid: &str = connection
-            .get("id")
-            .and_then(|v| v.try_into().ok())
-            .context("Missing uuid")?;
-        let con_type: &str = connection
-            .get("type")
-            .and_then(|v| v.try_into().ok())
-            .context("Missing type")?;
-
-        // Filter uuid.starts_with(utils::UUIDV4_PREFIX) will in future.
-        if con_type == "802-11-wireless" && conn_ssid == ssid {
-            // forget_config(&con, &path).await?;
-            let setting = blocking::Proxy::new(
-                con,
-                "org.freedesktop.NetworkManager",
-                &setting_path,
-                "org.freedesktop.NetworkManager.Settings.Connection",
-            )?;
-            setting.call_method("Delete", &())?;
+// Deletes every Wi-Fi profile of `ssid`, and returns how many there were.
+// The profile's name is only a label, it's told by the SSID like `match_profiles` does.
+pub async fn forget_ssid(conn: &Connection, ssid: &str) -> anyhow::Result<usize> {
+    let mut forgotten = 0;
+    for profile in saved_profiles(conn).await? {
+        if profile.ssid == ssid {
+            forget_config(conn, &profile.path).await?;
+            forgotten += 1;
         }
     }
-    anyhow::Ok(())
+    Ok(forgotten)
 }
 
-// There is a chance of a use-after-free bug, but it is virtually impossible
-// because destroy is currently blocking.
-// If destroy were not blocking, this code could receive a network disconnect.
-// Then, since remove has already deleted the private data, an undefined behvaiour would occur.
-//
-// However, this is virtually impossible at the moment.
+impl NetworkManagerDbusProxy {
+    // Keeps the verdict of the activation for `connect_result`, so it can be timed out and canceled meanwhile.
+    fn follow(&self, device_states: SignalStream<'static>, active: OwnedObjectPath) {
+        *self.pending.borrow_mut() =
+            Some(network_state(self.con.clone(), device_states, active).boxed_local());
+    }
+}
 
-pub async fn connection_background_task(pd: &'static mut PrivateData) -> anyhow::Result<()> {
-    let nm_proxy = Proxy::new(
-        &pd.nm_dbus.con,
-        "org.freedesktop.NetworkManager",
-        "/org/freedesktop/NetworkManager",
-        "org.freedesktop.NetworkManager",
-    )
-    .await?;
+impl Backend for NetworkManagerDbusProxy {
+    fn interfaces(&self) -> &[String] {
+        &self.interfaces
+    }
 
-    let mut notifications = nm_proxy.receive_signal("StateChanged").await?;
-    while let Some(signal) = notifications.next().await {
-        // The signal contains the new state as a u32 (index 0)
-        let state: u32 = signal.body().deserialize()?;
-        match state {
-            70 => (),
-            // the disconnection occurred on this interface or another,
-            20 => pd.active_connection = None,
-            // Will show as connected, even tho, it is connecting, only in external case
-            40 if !matches!(pd.state, AppState::Connecting(_))
-                || !matches!(pd.state, AppState::PasswordInput { .. }) =>
-            {
-                pd.active_connection = None
-            }
-            // A connect event has occured due to external reson.
-            _ => {
-                continue;
+    fn iface(&self) -> &str {
+        &self.iface
+    }
+
+    fn select_interface<'a>(
+        &'a self,
+        iface: &'a str,
+    ) -> LocalBoxFuture<'a, anyhow::Result<Box<dyn Backend>>> {
+        async move {
+            let mut nm_dbus = NetworkManagerDbusProxy {
+                con: self.con.clone(),
+                iface: self.iface.clone(),
+                interfaces: self.interfaces.clone(),
+                wifi_proxy: self.wifi_proxy.clone(),
+                property_proxy: self.property_proxy.clone(),
+                dev_path: self.dev_path.clone(),
+                profiles: Arc::clone(&self.profiles),
+                // A connect in progress stays with the interface it was started on.
+                pending: RefCell::default(),
+            };
+            select_interface(&mut nm_dbus, iface).await?;
+            Ok(Box::new(nm_dbus) as Box<dyn Backend>)
+        }
+        .boxed_local()
+    }
+
+    fn radio(&self) -> LocalBoxFuture<'_, anyhow::Result<Radio>> {
+        get_radio(&self.con).boxed_local()
+    }
+
+    fn set_radio(&self, radio: Radio) -> LocalBoxFuture<'_, anyhow::Result<()>> {
+        set_radio(&self.con, radio).boxed_local()
+    }
+
+    fn radio_changes(&self) -> LocalBoxFuture<'_, anyhow::Result<BoxStream<'static, ()>>> {
+        radio_changes(&self.con).boxed_local()
+    }
+
+    fn scan(&self) -> LocalBoxFuture<'_, anyhow::Result<()>> {
+        trigger_rescan(&self.property_proxy, &self.wifi_proxy).boxed_local()
+    }
+
+    fn access_points(&self) -> LocalBoxFuture<'_, anyhow::Result<Vec<AccessPoint>>> {
+        async move {
+            let raw_aps: Vec<OwnedObjectPath> =
+                self.wifi_proxy.call("GetAllAccessPoints", &()).await?;
+            let mut aps = Vec::with_capacity(raw_aps.len());
+            for ap_path in raw_aps {
+                aps.push(fetch_ap(&self.con, ap_path).await?);
             }
+
+            // The cache starts over with every full listing, e.g. when the menu opens.
+            let profiles = saved_profiles(&self.con).await?;
+            match_profiles(&profiles, &mut aps);
+            *self.profiles.lock().unwrap() = profiles;
+            Ok(aps)
+        }
+        .boxed_local()
+    }
+
+    fn access_point(
+        &self,
+        path: OwnedObjectPath,
+    ) -> LocalBoxFuture<'_, anyhow::Result<AccessPoint>> {
+        async move {
+            let mut added = [fetch_ap(&self.con, path).await?];
+            self.match_profiles(&mut added);
+            let [ap] = added;
+            Ok(ap)
         }
+        .boxed_local()
+    }
+
+    fn access_point_events(
+        &self,
+    ) -> LocalBoxFuture<'_, anyhow::Result<BoxStream<'static, AccessPointEvent>>> {
+        async move {
+            let events = access_point_events(&self.con, &self.wifi_proxy, &self.profiles).await?;
+            Ok(events.boxed())
+        }
+        .boxed_local()
+    }
 
-        let Some((bssid, conf)) = get_active_ap(&pd.nm_dbus.con, &pd.nm_dbus.wifi_proxy).await?
-        else {
-            continue;
-        };
+    fn match_profiles(&self, aps: &mut [AccessPoint]) {
+        match_profiles(&self.profiles.lock().unwrap(), aps);
+    }
+
+    fn active_ap(&self) -> LocalBoxFuture<'_, anyhow::Result<Option<(BSSID, OwnedObjectPath)>>> {
+        get_active_ap(&self.con, &self.wifi_proxy).boxed_local()
+    }
+
+    fn active_access_point(&self) -> LocalBoxFuture<'_, anyhow::Result<Option<AccessPoint>>> {
+        active_access_point(&self.con, &self.wifi_proxy).boxed_local()
+    }
 
-        if let Some(ap) = pd.aps.iter_mut().find(|ap| ap.bssid == bssid) {
-            ap.setting_path = Some(conf);
+    fn connection_state(&self) -> LocalBoxFuture<'_, anyhow::Result<ConnectionState>> {
+        async move {
+            Ok(match device_state(&self.con, &self.dev_path).await? {
+                // NM_DEVICE_STATE_ACTIVATED
+                100 => ConnectionState::Connected,
+                // From NM_DEVICE_STATE_PREPARE to NM_DEVICE_STATE_SECONDARIES
+                40..=90 => ConnectionState::Connecting,
+                _ => ConnectionState::Disconnected,
+            })
         }
+        .boxed_local()
+    }
 
-        pd.active_connection = Some(bssid);
-        pd.sort_accesspoints();
+    fn state_changes(&self) -> LocalBoxFuture<'_, anyhow::Result<BoxStream<'static, ()>>> {
+        state_changes(&self.con, &self.dev_path).boxed_local()
+    }
+
+    fn connection_details(&self) -> LocalBoxFuture<'_, anyhow::Result<Option<ConnectionDetails>>> {
+        connection_details(&self.con, &self.dev_path).boxed_local()
+    }
+
+    fn details_changes(&self) -> LocalBoxFuture<'_, anyhow::Result<BoxStream<'static, ()>>> {
+        async move {
+            let changes = self.property_proxy.receive_properties_changed().await?;
+            Ok(changes
+                .filter_map(|signal| async move {
+                    let args = signal.args().ok()?;
+                    ["ActiveConnection", "Ip4Config", "Ip6Config", "Bitrate"]
+                        .iter()
+                        .any(|property| args.changed_properties.contains_key(*property))
+                        .then_some(())
+                })
+                .boxed())
+        }
+        .boxed_local()
+    }
+
+    fn connect<'a>(
+        &'a self,
+        access_point: &'a AccessPoint,
+        password: Option<String>,
+        hidden: bool,
+        eap: Option<EapCredentials>,
+        lock_bssid: bool,
+    ) -> LocalBoxFuture<'a, anyhow::Result<(OwnedObjectPath, OwnedObjectPath)>> {
+        async move {
+            let states = device_states(&self.con, &self.dev_path).await?;
+            let (profile, active) = create_and_connect_access_point(
+                &self.con,
+                access_point,
+                &self.dev_path,
+                password,
+                hidden,
+                eap,
+                lock_bssid,
+            )
+            .await?;
+            self.follow(states, active.clone());
+            Ok((profile, active))
+        }
+        .boxed_local()
+    }
+
+    fn connect_saved<'a>(
+        &'a self,
+        access_point: &'a AccessPoint,
+    ) -> LocalBoxFuture<'a, anyhow::Result<(OwnedObjectPath, OwnedObjectPath)>> {
+        async move {
+            let states = device_states(&self.con, &self.dev_path).await?;
+            let (profile, active) =
+                connect_pre_existing_access_point(&self.con, access_point, &self.dev_path).await?;
+            self.follow(states, active.clone());
+            Ok((profile, active))
+        }
+        .boxed_local()
+    }
+
+    fn connect_result(&self) -> LocalBoxFuture<'_, anyhow::Result<u32>> {
+        async move {
+            let verdict = self
+                .pending
+                .borrow_mut()
+                .take()
+                .context("No connect in progress")?;
+            verdict.await
+        }
+        .boxed_local()
+    }
+
+    fn deactivate<'a>(
+        &'a self,
+        active: &'a OwnedObjectPath,
+    ) -> LocalBoxFuture<'a, anyhow::Result<()>> {
+        deactivate_connection(&self.con, active).boxed_local()
+    }
+
+    fn forget_profile<'a>(
+        &'a self,
+        profile: &'a OwnedObjectPath,
+    ) -> LocalBoxFuture<'a, anyhow::Result<()>> {
+        forget_config(&self.con, profile).boxed_local()
+    }
+
+    fn forget<'a>(&'a self, ssid: &'a str) -> LocalBoxFuture<'a, anyhow::Result<usize>> {
+        forget_ssid(&self.con, ssid).boxed_local()
+    }
+
+    fn disconnect(&self) -> LocalBoxFuture<'_, anyhow::Result<()>> {
+        disconnect(&self.con, &self.dev_path).boxed_local()
+    }
+
+    fn register_agent(
+        &self,
+    ) -> LocalBoxFuture<'_, anyhow::Result<mpsc::UnboundedReceiver<SecretAgentEvent>>> {
+        secret_agent::register(&self.con).boxed_local()
     }
-    Ok(())
 }
@@ -0,0 +1,224 @@
+use std::ffi::CStr;
+
+// Why a connection attempt failed, decoded from the NMDeviceStateReason of the device's FAILED state.
+// https://people.freedesktop.org/~lkundrak/nm-dbus-api/nm-dbus-types.html#NMDeviceStateReason
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FailureAction {
+    /// The credentials were rejected or are missing, so they are asked again.
+    AskPassword,
+    /// The credentials weren't the problem, selecting the network again retries with the same ones.
+    Retry,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Failure {
+    pub reason: u32,
+    /// Short enough for the prompt.
+    pub prompt: &'static CStr,
+    /// Shown in the message bar.
+    pub message: &'static str,
+    pub action: FailureAction,
+}
+
+// Not an NMDeviceStateReason, the plugin gave up waiting on NetworkManager, see `connect-timeout`.
+pub const CONNECT_TIMEOUT: u32 = u32::MAX;
+
+pub fn describe(reason: u32) -> Failure {
+    use FailureAction::*;
+
+    let (prompt, message, action): (&'static CStr, &'static str, FailureAction) = match reason {
+        // NM_DEVICE_STATE_REASON_CONFIG_FAILED
+        4 => (
+            c"config failed",
+            "The device could not be configured.",
+            Retry,
+        ),
+        // NM_DEVICE_STATE_REASON_IP_CONFIG_UNAVAILABLE
+        5 => (
+            c"no ip",
+            "No IP configuration is available, e.g. DHCP didn't answer.",
+            Retry,
+        ),
+        // NM_DEVICE_STATE_REASON_IP_CONFIG_EXPIRED
+        6 => (c"ip expired", "The IP configuration expired.", Retry),
+        // NM_DEVICE_STATE_REASON_NO_SECRETS
+        7 => (
+            c"bad auth",
+            "The password was rejected, or is missing.",
+            AskPassword,
+        ),
+        // NM_DEVICE_STATE_REASON_SUPPLICANT_DISCONNECT, usually a wrong password during the handshake.
+        8 => (
+            c"bad auth",
+            "The access point disconnected during authentication, the password is likely wrong.",
+            AskPassword,
+        ),
+        // NM_DEVICE_STATE_REASON_SUPPLICANT_CONFIG_FAILED
+        9 => (
+            c"bad config",
+            "The supplicant refused the security settings, check the password.",
+            AskPassword,
+        ),
+        // NM_DEVICE_STATE_REASON_SUPPLICANT_FAILED
+        10 => (c"supplicant failed", "The supplicant failed.", Retry),
+        // NM_DEVICE_STATE_REASON_SUPPLICANT_TIMEOUT
+        11 => (
+            c"timeout",
+            "Authentication timed out, the access point may be out of range.",
+            Retry,
+        ),
+        // NM_DEVICE_STATE_REASON_DHCP_START_FAILED
+        15 => (
+            c"dhcp failed",
+            "The DHCP client could not be started.",
+            Retry,
+        ),
+        // NM_DEVICE_STATE_REASON_DHCP_ERROR
+        16 => (c"dhcp failed", "The DHCP client reported an error.", Retry),
+        // NM_DEVICE_STATE_REASON_DHCP_FAILED
+        17 => (
+            c"dhcp failed",
+            "The DHCP server didn't assign an address.",
+            Retry,
+        ),
+        // NM_DEVICE_STATE_REASON_SHARED_START_FAILED, NM_DEVICE_STATE_REASON_SHARED_FAILED
+        18 | 19 => (
+            c"sharing failed",
+            "The connection sharing service failed.",
+            Retry,
+        ),
+        // NM_DEVICE_STATE_REASON_AUTOIP_START_FAILED, NM_DEVICE_STATE_REASON_AUTOIP_ERROR, NM_DEVICE_STATE_REASON_AUTOIP_FAILED
+        20..=22 => (
+            c"autoip failed",
+            "The link-local address could not be set.",
+            Retry,
+        ),
+        // NM_DEVICE_STATE_REASON_FIRMWARE_MISSING
+        35 => (
+            c"no firmware",
+            "The firmware of the device is missing.",
+            Retry,
+        ),
+        // NM_DEVICE_STATE_REASON_REMOVED
+        36 => (c"device removed", "The device was removed.", Retry),
+        // NM_DEVICE_STATE_REASON_SLEEPING
+        37 => (c"sleeping", "NetworkManager went to sleep.", Retry),
+        // NM_DEVICE_STATE_REASON_CONNECTION_REMOVED
+        38 => (
+            c"profile removed",
+            "The profile was deleted while connecting.",
+            Retry,
+        ),
+        // NM_DEVICE_STATE_REASON_USER_REQUESTED
+        39 => (c"canceled", "The connection was canceled.", Retry),
+        // NM_DEVICE_STATE_REASON_CARRIER
+        40 => (
+            c"carrier lost",
+            "The carrier was lost, the access point may be out of range.",
+            Retry,
+        ),
+        // NM_DEVICE_STATE_REASON_CONNECTION_ASSUMED
+        41 => (c"assumed", "An existing connection was assumed.", Retry),
+        // NM_DEVICE_STATE_REASON_SUPPLICANT_AVAILABLE
+        42 => (
+            c"supplicant restarted",
+            "The supplicant became available again.",
+            Retry,
+        ),
+        // NM_DEVICE_STATE_REASON_DEPENDENCY_FAILED
+        50 => (
+            c"dependency failed",
+            "A connection this one depends on failed.",
+            Retry,
+        ),
+        // NM_DEVICE_STATE_REASON_SSID_NOT_FOUND
+        53 => (
+            c"not found",
+            "The network was not found, it may be out of range.",
+            Retry,
+        ),
+        // NM_DEVICE_STATE_REASON_SECONDARY_CONNECTION_FAILED
+        54 => (
+            c"vpn failed",
+            "A secondary connection, e.g. a VPN, failed.",
+            Retry,
+        ),
+        // NM_DEVICE_STATE_REASON_NEW_ACTIVATION
+        60 => (
+            c"superseded",
+            "Another connection was activated instead.",
+            Retry,
+        ),
+        // NM_DEVICE_STATE_REASON_PARENT_CHANGED, NM_DEVICE_STATE_REASON_PARENT_MANAGED_CHANGED
+        61 | 62 => (c"parent changed", "The parent device changed.", Retry),
+        // NM_DEVICE_STATE_REASON_IP_ADDRESS_DUPLICATE
+        64 => (
+            c"duplicate ip",
+            "The IP address is already used on the network.",
+            Retry,
+        ),
+        // NM_DEVICE_STATE_REASON_IP_METHOD_UNSUPPORTED
+        65 => (
+            c"unsupported ip",
+            "The IP method of the profile is not supported.",
+            Retry,
+        ),
+        // NM_DEVICE_STATE_REASON_PEER_NOT_FOUND
+        67 => (c"not found", "The peer was not found.", Retry),
+        // NM_DEVICE_STATE_REASON_DEVICE_HANDLER_FAILED
+        68 => (c"handler failed", "The device handler failed.", Retry),
+        // NM_DEVICE_STATE_REASON_NOW_MANAGED, NM_DEVICE_STATE_REASON_NOW_UNMANAGED,
+        // NM_DEVICE_STATE_REASON_UNMANAGED_*
+        2 | 3 | 69..=77 => (
+            c"unmanaged",
+            "The device is not managed by NetworkManager.",
+            Retry,
+        ),
+        // Modem, PPP, Bluetooth, bridge, team, OVS and other reasons that don't apply to Wi-Fi.
+        12..=14 | 23..=34 | 43..=49 | 51 | 52 | 55..=59 | 63 | 66 => (
+            c"fail",
+            "The device failed for a reason unrelated to Wi-Fi.",
+            Retry,
+        ),
+        CONNECT_TIMEOUT => (
+            c"timeout",
+            "NetworkManager didn't finish connecting in time.",
+            Retry,
+        ),
+        // NM_DEVICE_STATE_REASON_UNKNOWN, and reasons newer than this table.
+        _ => (
+            c"fail",
+            "The connection failed for an unknown reason.",
+            Retry,
+        ),
+    };
+
+    Failure {
+        reason,
+        prompt,
+        message,
+        action,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejected_credentials_are_asked_again() {
+        for reason in [7, 8, 9] {
+            assert_eq!(describe(reason).action, FailureAction::AskPassword);
+        }
+        assert_eq!(describe(53).action, FailureAction::Retry);
+        assert_eq!(describe(CONNECT_TIMEOUT).action, FailureAction::Retry);
+    }
+
+    #[test]
+    fn unknown_reason_still_has_a_prompt() {
+        let failure = describe(1000);
+        assert_eq!(failure.reason, 1000);
+        assert_eq!(failure.prompt, c"fail");
+    }
+}
@@ -1,4 +1,6 @@
 use crate::ffi::{self, Mode, PropertyType_P_INTEGER, PropertyType_P_LIST, PropertyType_P_STRING};
+use crate::structure::SharedData;
+use crate::view::View;
 use std::{
     ffi::{CStr, CString},
     ptr::NonNull,
@@ -90,11 +92,6 @@ pub fn get_private_state<T>(sw: &ffi::Mode) -> Option<&'static T> {
     Some(unsafe { NonNull::new(result as *mut T)?.as_ref() })
 }
 
-pub fn get_private_state_mut<T>(sw: &mut ffi::Mode) -> Option<&'static mut T> {
-    let result: *mut std::ffi::c_void = unsafe { ffi::mode_get_private_data(sw as *const _) };
-    Some(unsafe { NonNull::new(result as *mut T)?.as_mut() })
-}
-
 pub fn set_private_state<T>(sw: &&mut Mode, pd: &&'static mut T) {
     unsafe {
         ffi::mode_set_private_data(
@@ -121,7 +118,7 @@ pub fn reload_view() {
     unsafe { rofi_view_reload() }
 }
 
-pub fn set_prompt(sw: &mut ffi::Mode, text: &CString) {
+pub fn set_prompt(sw: &mut ffi::Mode, text: &CStr) {
     sw.display_name = text.as_ptr() as *mut i8;
     unsafe { rofi_view_switch_mode(rofi_view_get_active(), sw as *mut _) };
 }
@@ -134,3 +131,28 @@ pub fn view_reset(sw: &mut ffi::Mode) {
 pub fn get_display_name(sw: &mut ffi::Mode) -> CString {
     unsafe { CString::from_raw(sw.display_name) }
 }
+
+// The state machine's view, rofi's mode the plugin was loaded as.
+pub struct ModeView(pub &'static mut Mode);
+
+impl View for ModeView {
+    fn private_data(&self) -> Option<SharedData> {
+        get_private_state::<SharedData>(self.0).cloned()
+    }
+
+    fn set_display_name(&mut self, prompt: &CStr) {
+        self.0.display_name = prompt.as_ptr() as *mut i8;
+    }
+
+    fn set_prompt(&mut self, prompt: &CStr) {
+        set_prompt(self.0, prompt);
+    }
+
+    fn reload(&mut self) {
+        reload_view();
+    }
+
+    fn reset(&mut self) {
+        view_reset(self.0);
+    }
+}
@@ -0,0 +1,156 @@
+use crate::structure::Security;
+use futures_channel::{mpsc, oneshot};
+use std::collections::HashMap;
+use zbus::{
+    Connection, Proxy,
+    zvariant::{Array, OwnedObjectPath, OwnedValue, Value},
+};
+
+// NetworkManager asks the registered agents for any secret missing from a profile,
+// whether the profile was created by this plugin or externally, e.g. nmcli.
+// The agent itself can't touch rofi, as zbus serves it from its own thread,
+// so every request is forwarded through a channel to the glib side, which shows the password prompt.
+
+pub const AGENT_PATH: &str = "/org/freedesktop/NetworkManager/SecretAgent";
+pub const AGENT_IDENTIFIER: &str = "io.github.bjn7.rofi-wifi";
+
+// https://people.freedesktop.org/~lkundrak/nm-dbus-api/nm-dbus-types.html#NMSecretAgentGetSecretsFlags
+const ALLOW_INTERACTION: u32 = 1;
+
+pub type SecretSettings = HashMap<String, HashMap<String, Value<'static>>>;
+
+#[derive(Debug)]
+pub enum SecretAgentEvent {
+    /// NetworkManager needs a secret, the prompt's answer must be sent back through `reply`.
+    /// Dropping `reply` cancels the request.
+    Request(SecretRequest),
+    /// NetworkManager no longer needs the secret of this profile, i.e. the activation was aborted.
+    Cancel(OwnedObjectPath),
+}
+
+#[derive(Debug)]
+pub struct SecretRequest {
+    pub ssid: String,
+    pub connection_path: OwnedObjectPath,
+    /// The security the secret is asked for, from the profile itself, `None` when the backend doesn't tell.
+    pub security: Option<Security>,
+    pub reply: oneshot::Sender<String>,
+}
+
+#[derive(zbus::DBusError, Debug)]
+#[zbus(prefix = "org.freedesktop.NetworkManager.SecretAgent")]
+pub enum AgentError {
+    #[zbus(error)]
+    ZBus(zbus::Error),
+    NoSecrets(String),
+    UserCanceled(String),
+}
+
+pub struct SecretAgent {
+    events: mpsc::UnboundedSender<SecretAgentEvent>,
+}
+
+#[zbus::interface(name = "org.freedesktop.NetworkManager.SecretAgent")]
+impl SecretAgent {
+    async fn get_secrets(
+        &self,
+        connection: HashMap<String, HashMap<String, OwnedValue>>,
+        connection_path: OwnedObjectPath,
+        setting_name: String,
+        _hints: Vec<String>,
+        flags: u32,
+    ) -> Result<SecretSettings, AgentError> {
+        if flags & ALLOW_INTERACTION == 0 {
+            return Err(AgentError::NoSecrets("Interaction is not allowed".into()));
+        }
+
+        let ssid_bytes = connection
+            .get("802-11-wireless")
+            .and_then(|x| x.get("ssid"))
+            .and_then(|v| v.downcast_ref::<Array>().ok())
+            .map(|v| {
+                v.iter()
+                    .filter_map(|x| x.downcast_ref::<u8>().ok())
+                    .collect::<Vec<u8>>()
+            })
+            .ok_or_else(|| AgentError::NoSecrets("Not a wireless connection".into()))?;
+
+        let key_mgmt: Option<&str> = connection
+            .get("802-11-wireless-security")
+            .and_then(|x| x.get("key-mgmt"))
+            .and_then(|v| v.try_into().ok());
+
+        let security = match setting_name.as_str() {
+            "802-1x" => Some(Security::Enterprise),
+            _ => key_mgmt.and_then(Security::from_key_mgmt),
+        };
+
+        let (reply, answer) = oneshot::channel();
+        self.events
+            .unbounded_send(SecretAgentEvent::Request(SecretRequest {
+                ssid: String::from_utf8_lossy(&ssid_bytes).to_string(),
+                connection_path,
+                security,
+                reply,
+            }))
+            .map_err(|_| AgentError::NoSecrets("Prompt is not available".into()))?;
+
+        let secret = answer
+            .await
+            .map_err(|_| AgentError::UserCanceled("Prompt was dismissed".into()))?;
+
+        let secret_key = match (setting_name.as_str(), key_mgmt) {
+            ("802-1x", _) => "password",
+            (_, Some("none")) => "wep-key0",
+            _ => "psk",
+        };
+
+        let mut section = HashMap::new();
+        section.insert(secret_key.to_string(), Value::from(secret));
+
+        let mut secrets = HashMap::new();
+        secrets.insert(setting_name, section);
+        Ok(secrets)
+    }
+
+    async fn cancel_get_secrets(&self, connection_path: OwnedObjectPath, _setting_name: String) {
+        let _ = self
+            .events
+            .unbounded_send(SecretAgentEvent::Cancel(connection_path));
+    }
+
+    // Secrets are left for NetworkManager to store within the profile, so there is nothing to save or delete.
+    async fn save_secrets(
+        &self,
+        _connection: HashMap<String, HashMap<String, OwnedValue>>,
+        _connection_path: OwnedObjectPath,
+    ) {
+    }
+
+    async fn delete_secrets(
+        &self,
+        _connection: HashMap<String, HashMap<String, OwnedValue>>,
+        _connection_path: OwnedObjectPath,
+    ) {
+    }
+}
+
+pub async fn register(
+    con: &Connection,
+) -> anyhow::Result<mpsc::UnboundedReceiver<SecretAgentEvent>> {
+    let (events, receiver) = mpsc::unbounded();
+    con.object_server()
+        .at(AGENT_PATH, SecretAgent { events })
+        .await?;
+
+    let agent_manager = Proxy::new(
+        con,
+        "org.freedesktop.NetworkManager",
+        "/org/freedesktop/NetworkManager/AgentManager",
+        "org.freedesktop.NetworkManager.AgentManager",
+    )
+    .await?;
+
+    let _: () = agent_manager.call("Register", &(AGENT_IDENTIFIER)).await?;
+    Ok(receiver)
+}
@@ -1,265 +1,1001 @@
-use crate::network_manager;
+use crate::backend::{self, AccessPointEvent, ConnectionState, NewProfile, Verdict};
+use crate::reason::{self, FailureAction};
+use crate::secret_agent::SecretAgentEvent;
+use crate::utils;
 
-use super::PrivateData;
-use super::ffi;
-use super::rofi;
-use super::structure::*;
+use crate::structure::*;
+use crate::view::{SharedView, View};
+use futures_channel::{mpsc, oneshot};
+use futures_util::{StreamExt, future};
 use glib::MainContext;
-use std::cell::RefCell;
-use std::ops::DerefMut;
 use std::rc::Rc;
 use std::time::Duration;
 
 pub fn handle_state(
-    sw: &'static mut ffi::Mode,
+    view: SharedView,
     selected_line: usize,
-    pd: &'static mut PrivateData,
+    data: &SharedData,
     input: &std::ffi::CStr,
-) -> u32 {
-    let rc_sw = Rc::new(RefCell::new(sw));
+) {
+    let mut guard = data.borrow_mut();
+    let pd = &mut *guard;
+    // The password shown masked is done with, whatever the entry was.
+    if pd.is_secret_input() {
+        pd.reveal_password = false;
+        pd.secret_input.clear();
+    }
+    // The radio and hidden rows are listed along the access points, but aren't any.
+    // Other states list something else, `selected_line` isn't a row there.
+    if matches!(
+        pd.state,
+        AppState::Idle | AppState::Scanning | AppState::Connecting(_)
+    ) {
+        match pd.rows.get(selected_line) {
+            // Left alone until the connection attempt is over.
+            Some(Row::Radio | Row::Hidden) if matches!(pd.state, AppState::Connecting(_)) => {
+                return;
+            }
+            Some(Row::Radio) => {
+                let radio = pd.radio.toggled();
+                drop(guard);
+                set_radio(data, radio);
+                return;
+            }
+            Some(Row::Hidden) => {
+                drop(guard);
+                // Whatever was typed to find the row isn't taken as the name.
+                set_wifi_mode_hidden_step(view, data, HiddenStep::Ssid, selected_line, c"");
+                return;
+            }
+            _ => {}
+        }
+    }
+
+    // Rows are mapped to access points, anything else is a custom input, i.e. the name of a hidden network.
+    let ap_index = pd.row_ap(selected_line).unwrap_or(usize::MAX);
     match pd.state {
         AppState::Idle => {
-            set_wifi_mode_password(rc_sw, pd, selected_line, input, 0);
+            drop(guard);
+            set_wifi_mode_password(view, data, ap_index, input, 0);
         }
 
         AppState::Scanning => {
-            set_wifi_mode_password(rc_sw, pd, selected_line, input, 0);
+            drop(guard);
+            set_wifi_mode_password(view, data, ap_index, input, 0);
         }
 
         AppState::PasswordInput { ref bssid, .. } => {
+            // The secret agent's request tells the security of its profile, which may not be listed.
+            // When neither does, the password is left for the backend to judge.
+            let security = pd
+                .pending_secret
+                .as_ref()
+                .and_then(|request| request.security)
+                .or_else(|| pd.find_ap(bssid).map(|ap| ap.security.class));
+            if let Some(Err(prompt)) =
+                security.map(|security| security.validate_password(&input.to_string_lossy()))
+            {
+                // Stays in PasswordInput, no profile is created for a password that can't work.
+                view.borrow_mut().set_display_name(prompt);
+                return;
+            }
+
             pd.anim_connecting.index = 0;
-            set_mode_connecting_and_handle(rc_sw, pd, bssid.into(), input.into(), 0)
+            let bssid = bssid.clone();
+            if let Some(request) = pd.pending_secret.take() {
+                // NetworkManager is still activating, and only waits for this answer.
+                let _ = request.reply.send(input.to_string_lossy().to_string());
+                // The input it took over is given up for the connection.
+                if pd.interrupted_input.take().is_some() {
+                    pd.eap_credentials = None;
+                    pd.hidden = None;
+                }
+                drop(guard);
+                set_mode_connecting(view, data, bssid);
+            } else {
+                drop(guard);
+                set_mode_connecting_and_handle(view, data, bssid, input.into(), 0)
+            }
         }
         AppState::Connecting(_) => {
-            set_wifi_mode_password(rc_sw, pd, selected_line, input, 0);
+            drop(guard);
+            set_wifi_mode_password(view, data, ap_index, input, 0);
+        }
+        AppState::EnterpriseInput { ref bssid, step } => {
+            let bssid = bssid.clone();
+            drop(guard);
+            set_wifi_mode_enterprise_step(view, data, bssid, step, selected_line, input);
+        }
+        AppState::InterfaceSelect => {
+            drop(guard);
+            set_wifi_mode_interface(view, data, selected_line);
+        }
+        AppState::HiddenInput(ref step) => {
+            let step = step.clone();
+            drop(guard);
+            set_wifi_mode_hidden_step(view, data, step, selected_line, input);
         }
     };
-    ffi::ModeMode_RESET_DIALOG
 }
 
-// seems like incase, if the response wasn't made quick enough or not blocked, then, the
-// same events will be fired again by rofi.
+// Answered right away, rofi fires the same event again when it's kept waiting.
 pub fn set_wifi_mode_password(
-    sw: Rc<RefCell<&'static mut ffi::Mode>>,
-    pd: &'static mut PrivateData,
+    view: SharedView,
+    data: &SharedData,
     selected_line: usize,
     input: &std::ffi::CStr,
     reason: u32,
 ) {
-    if pd.state == AppState::Scanning {
+    if data.borrow().state == AppState::Scanning {
         let glib_context = glib::MainContext::default();
-        glib_context.block_on(async {
-            pd.shut_scan().await;
-        });
-    } else if pd.state == AppState::Scanning {
-        let glib_context = glib::MainContext::default();
-        glib_context.block_on(async {
-            pd.shut_connect().await;
-        });
+        glib_context.block_on(PrivateData::shut_scan(data));
     }
 
-    if reason == 7 {
-        //NM_DEVICE_STATE_REASON_NO_SECRETS
-        sw.borrow_mut().display_name = c"bad auth".as_ptr() as *mut i8;
-    } else if reason == 0 {
-        sw.borrow_mut().display_name = c"password".as_ptr() as *mut i8;
-    } else {
-        sw.borrow_mut().display_name = c"fail".as_ptr() as *mut i8;
+    let mut guard = data.borrow_mut();
+    let pd = &mut *guard;
+
+    // Selecting the connected network disconnects from it.
+    if reason == 0
+        && let Some(ap) = pd.aps.get(selected_line)
+        && pd.active_connection.as_ref() == Some(&ap.bssid)
+    {
+        drop(guard);
+        disconnect(data);
+        return;
     }
 
-    if selected_line > pd.aps.len() {
-        pd.hidden_ssid = Some(input.to_str().unwrap().to_string());
+    // A hidden network isn't listed, the hidden flow keeps it aside instead.
+    // Anything else is a typed name that matches nothing, taken as the name of a hidden network.
+    let ap = match (pd.aps.get(selected_line), &pd.hidden) {
+        // An access point that hides its name is listed, but can't be connected without the name.
+        (Some(ap), _) if ap.ssid.is_empty() => {
+            drop(guard);
+            set_wifi_mode_hidden_step(view, data, HiddenStep::Ssid, selected_line, c"");
+            return;
+        }
+        (Some(ap), _) => {
+            let ap = ap.clone();
+            pd.hidden = None;
+            ap
+        }
+        (None, Some(hidden)) => hidden.clone(),
+        (None, None) => {
+            drop(guard);
+            set_wifi_mode_hidden_step(view, data, HiddenStep::Ssid, selected_line, input);
+            return;
+        }
+    };
+
+    let failure = (reason > 0).then(|| reason::describe(reason));
+    pd.last_failure = failure;
+    let prompt = failure.map_or(c"password", |f| f.prompt);
+    view.borrow_mut().set_display_name(prompt);
+
+    let view_rc = Rc::clone(&view);
+    let bssid = ap.bssid.clone();
+    let is_hidden = bssid.is_empty();
+    if failure.is_some_and(|f| f.action == FailureAction::Retry) && !is_hidden {
+        // The credentials weren't the problem, so they aren't asked again.
+        // A hidden network can't be selected again, so its credentials are asked anyway.
+        pd.state = AppState::Idle;
+        return;
+    }
+    if reason == 0
+        && let Some(retry) = pd.retry.take_if(|r| r.bssid == bssid)
+    {
+        pd.eap_credentials = retry.eap;
+        let password = retry.password.map(|p| std::ffi::CString::new(p).unwrap());
+        drop(guard);
+        set_mode_connecting_and_handle(view_rc, data, bssid, password.as_deref(), reason);
+        return;
+    }
+
+    if ap.setting_path.is_some() && reason == 0 {
+        drop(guard);
+        set_mode_connecting_and_handle(view_rc, data, bssid, None, reason);
+    } else if !ap.security.class.needs_password() && reason == 0 {
+        drop(guard);
+        // Open and OWE networks connect right away, a failure falls through to the prompt to retry.
+        set_mode_connecting_and_handle(view_rc, data, bssid, None, reason);
+    } else if ap.security.class == Security::Enterprise {
+        // Refused before asking anything, the backend would only fail once every step is answered.
+        if !pd.backend.supports_enterprise() {
+            view.borrow_mut()
+                .set_display_name(c"enterprise unsupported");
+            pd.eap_credentials = None;
+            pd.hidden = None;
+            pd.state = AppState::Idle;
+            return;
+        }
+        if reason == 0 {
+            view.borrow_mut().set_display_name(c"eap method");
+        }
+        pd.eap_credentials = Some(EapCredentials::default());
+        pd.state = AppState::EnterpriseInput {
+            bssid,
+            step: EapStep::Method,
+        };
+    } else {
         pd.state = AppState::PasswordInput {
-            bssid: input.to_str().unwrap().to_string(),
+            bssid: bssid,
             reason: reason,
         };
-    } else {
-        pd.hidden_ssid = None;
-        let ap = pd
-            .aps
-            .get(selected_line)
-            .expect("Invalid index in set_wifi_mode_password");
-
-        let sw_rc = Rc::clone(&sw);
-        let bssid = ap.bssid.clone();
-        if ap.setting_path.is_some() && reason == 0 {
-            set_mode_connecting_and_handle(sw_rc, pd, bssid, None, reason);
-        } else {
-            pd.state = AppState::PasswordInput {
-                bssid: bssid,
-                reason: reason,
+    }
+}
+
+// Stores the answer of the current hidden network step and moves to the next one.
+// Once the security is picked, the network goes on like a listed one, i.e. its credentials are asked.
+pub fn set_wifi_mode_hidden_step(
+    view: SharedView,
+    data: &SharedData,
+    step: HiddenStep,
+    selected_line: usize,
+    input: &std::ffi::CStr,
+) {
+    if data.borrow().state == AppState::Scanning {
+        let glib_context = glib::MainContext::default();
+        glib_context.block_on(PrivateData::shut_scan(data));
+    }
+
+    let mut pd = data.borrow_mut();
+    match step {
+        HiddenStep::Ssid => {
+            let ssid = input.to_string_lossy().to_string();
+            // Nothing was typed yet when the name is first asked, e.g. from the hidden row.
+            let asked = pd.state == AppState::HiddenInput(HiddenStep::Ssid);
+            let (prompt, next_step) = match validate_ssid(&ssid) {
+                Ok(()) => (c"security", HiddenStep::Security { ssid }),
+                Err(_) if !asked && ssid.is_empty() => (c"ssid", HiddenStep::Ssid),
+                Err(prompt) => (prompt, HiddenStep::Ssid),
             };
+            view.borrow_mut().set_display_name(prompt);
+            pd.hidden = None;
+            pd.state = AppState::HiddenInput(next_step);
+        }
+        HiddenStep::Security { ssid } => {
+            let value = input.to_string_lossy();
+            // Either picked from the list, or typed out.
+            let Some(&(class, _)) = HIDDEN_SECURITY.get(selected_line).or_else(|| {
+                HIDDEN_SECURITY
+                    .iter()
+                    .find(|(_, label)| label.eq_ignore_ascii_case(&value))
+            }) else {
+                return;
+            };
+            pd.hidden = Some(AccessPoint::hidden(ssid, class));
+            drop(pd);
+            set_wifi_mode_password(view, data, usize::MAX, input, 0);
+        }
+    }
+}
+
+pub fn set_radio(data: &SharedData, radio: Radio) {
+    let backend = Rc::clone(&data.borrow().backend);
+    let glib_context = glib::MainContext::default();
+    match glib_context.block_on(backend.set_radio(radio)) {
+        Ok(()) => data.borrow_mut().radio = radio,
+        Err(e) => {
+            eprintln!("Failed to switch the radio: {}", e);
+            // Part of it may have been switched, e.g. Wi-Fi but not WWAN.
+            match glib_context.block_on(backend.radio()) {
+                Ok(radio) => data.borrow_mut().radio = radio,
+                Err(e) => eprintln!("Failed to read the radio state: {}", e),
+            }
         }
     }
 }
 
-pub fn set_wifi_mode_scan(
-    sw: Rc<RefCell<&'static mut ffi::Mode>>,
-    pd: &mut &'static mut PrivateData,
-    // execution_context_signal: Rc<RefCell<u8>>,
-) -> glib::SourceId {
+// Keeps `pd.radio` in sync when the radio is switched outside of the plugin, e.g. by a hardware key.
+// The access points of a disabled radio are removed by the backend, and access_point_events_task follows.
+pub async fn radio_task(view: SharedView) {
+    let Some(data) = view.borrow().private_data() else {
+        return;
+    };
+    let backend = Rc::clone(&data.borrow().backend);
+
+    let mut changes = match backend.radio_changes().await {
+        Ok(changes) => changes,
+        Err(e) => {
+            eprintln!("Failed to subscribe to radio changes: {}", e);
+            return;
+        }
+    };
+
+    while changes.next().await.is_some() {
+        match backend.radio().await {
+            Ok(radio) => data.borrow_mut().radio = radio,
+            Err(e) => {
+                eprintln!("Failed to read the radio state: {}", e);
+                continue;
+            }
+        }
+
+        if !data.borrow().is_text_input() {
+            view.borrow_mut().reload();
+        }
+    }
+}
+
+// Deactivates the active connection, keeping its profile saved.
+pub fn disconnect(data: &SharedData) {
+    let backend = Rc::clone(&data.borrow().backend);
+    let glib_context = glib::MainContext::default();
+    if let Err(e) = glib_context.block_on(backend.disconnect()) {
+        eprintln!("Failed to disconnect: {}", e);
+        return;
+    }
+    data.borrow_mut().active_connection = None;
+}
+
+// Stores the answer of the current enterprise step and moves to the next one.
+// Once the last step is answered, the collected credentials are used to connect.
+pub fn set_wifi_mode_enterprise_step(
+    view: SharedView,
+    data: &SharedData,
+    bssid: String,
+    step: EapStep,
+    selected_line: usize,
+    input: &std::ffi::CStr,
+) {
+    let mut pd = data.borrow_mut();
+    let value = input.to_string_lossy().to_string();
+    let credentials = pd
+        .eap_credentials
+        .get_or_insert_with(EapCredentials::default);
+
+    let next_step = match step {
+        EapStep::Method => {
+            // Either picked from the list, or typed out.
+            let Some(method) = EAP_METHODS
+                .get(selected_line)
+                .or_else(|| EAP_METHODS.iter().find(|m| **m == value))
+            else {
+                return;
+            };
+            credentials.method = method.to_string();
+            view.borrow_mut().set_display_name(c"identity");
+            Some(EapStep::Identity)
+        }
+        EapStep::Identity => {
+            credentials.identity = value;
+            view.borrow_mut().set_display_name(c"password");
+            Some(EapStep::Password)
+        }
+        EapStep::Password => {
+            if let Err(prompt) = Security::Enterprise.validate_password(&value) {
+                view.borrow_mut().set_display_name(prompt);
+                return;
+            }
+            credentials.password = value;
+            view.borrow_mut().set_display_name(c"ca cert (optional)");
+            Some(EapStep::CaCert)
+        }
+        EapStep::CaCert => {
+            credentials.ca_cert = (!value.is_empty()).then_some(value);
+            None
+        }
+    };
+
+    match next_step {
+        Some(step) => pd.state = AppState::EnterpriseInput { bssid, step },
+        None => {
+            pd.anim_connecting.index = 0;
+            drop(pd);
+            set_mode_connecting_and_handle(view, data, bssid, None, 0);
+        }
+    }
+}
+
+// Switches to the picked interface, then lists its access points.
+pub fn set_wifi_mode_interface(view: SharedView, data: &SharedData, selected_line: usize) {
+    let backend = Rc::clone(&data.borrow().backend);
+    let Some(iface) = backend.interfaces().get(selected_line).cloned() else {
+        return;
+    };
+
+    let glib_context = glib::MainContext::default();
+    let select_result = glib_context.block_on(async {
+        let backend = backend.select_interface(&iface).await?;
+        let aps = backend.access_points().await?;
+        let active_ap_bssid_opt = backend.active_ap().await?;
+        anyhow::Ok((backend, aps, active_ap_bssid_opt))
+    });
+
+    let (selected, aps, active_ap_bssid_opt) = match select_result {
+        Ok(result) => result,
+        Err(e) => {
+            eprintln!("Failed to switch to {}: {}", iface, e);
+            return;
+        }
+    };
+
+    if let Err(e) = utils::save_last_interface(&iface) {
+        eprintln!("Failed to remember the interface: {}", e);
+    }
+
+    // The cached list belongs to the previous interface.
+    let mut pd = data.borrow_mut();
+    pd.backend = selected.into();
+    pd.aps = aps;
+    pd.expanded = None;
+    pd.active_connection = None;
+    pd.set_connected(active_ap_bssid_opt);
+    pd.sort_accesspoints();
+    pd.state = AppState::Idle;
+
+    view.borrow_mut().set_display_name(&pd.display_name);
+    let switched = pd.backend.iface() != backend.iface();
+    drop(pd);
+    if switched {
+        glib::MainContext::default().spawn_local(access_point_events_task(Rc::clone(&view)));
+        glib::MainContext::default().spawn_local(connection_details_task(Rc::clone(&view)));
+    }
+    spawn_scan(view);
+}
+
+// Rescans in the background, the list itself is updated by access_point_events_task as the backend finds access points.
+// Does nothing unless idle, or with the radio off.
+pub fn spawn_scan(view: SharedView) {
+    glib::MainContext::default().spawn_local(async move {
+        let Some(data) = view.borrow().private_data() else {
+            return;
+        };
+        let backend = {
+            let pd = data.borrow();
+
+            if pd.state != AppState::Idle {
+                return;
+            }
+
+            // NetworkManager refuses to scan with the radio off.
+            if !pd.radio.wifi {
+                return;
+            }
+            Rc::clone(&pd.backend)
+        };
+
+        // The animation is asked to stop once the scan is over, and it acknowledges,
+        // so its last frame can't override the prompt set afterwards, see `PrivateData::shut_scan`.
+        data.borrow_mut().allow_execute(VFBTask::Scan);
+        set_wifi_mode_scan(Rc::clone(&view), &data);
+
+        if let Err(e) = backend.scan().await {
+            eprintln!("Failed to scan ap: {}", e);
+        }
+
+        if data.borrow().state != AppState::Scanning {
+            // The mode switched while scanning, and the new mode owns the prompt now.
+            return;
+        }
+        PrivateData::shut_scan(&data).await;
+
+        data.borrow_mut().state = AppState::Idle;
+        let pd = data.borrow();
+        view.borrow_mut().set_prompt(&pd.display_name);
+    });
+}
+
+// Keeps `pd.aps` in sync with the access points of the current device, one at a time.
+// New access points are appended and nothing is re-sorted, so the rows don't jump under the cursor.
+// Stops once the device is switched, the task of the new device takes over.
+pub async fn access_point_events_task(view: SharedView) {
+    let Some(data) = view.borrow().private_data() else {
+        return;
+    };
+    let backend = Rc::clone(&data.borrow().backend);
+
+    let iface = backend.iface().to_string();
+    let mut events = match backend.access_point_events().await {
+        Ok(events) => events,
+        Err(e) => {
+            eprintln!("Failed to subscribe to access point changes: {}", e);
+            return;
+        }
+    };
+
+    while let Some(event) = events.next().await {
+        if data.borrow().backend.iface() != iface {
+            return;
+        }
+
+        // Read before the private data is borrowed, it takes a round trip to the backend.
+        let added = match event {
+            AccessPointEvent::Added(ref path) => {
+                if data.borrow().aps.iter().any(|ap| ap.path == *path) {
+                    continue;
+                }
+                match backend.access_point(path.clone()).await {
+                    Ok(ap) => Some(ap),
+                    // Access points may vanish before their properties are read.
+                    Err(_) => continue,
+                }
+            }
+            _ => None,
+        };
+
+        // A new access point or a changed signal may move it in the list.
+        let resort = matches!(
+            event,
+            AccessPointEvent::Added(_) | AccessPointEvent::Strength(..)
+        );
+        let mut guard = data.borrow_mut();
+        let pd = &mut *guard;
+        match event {
+            AccessPointEvent::Added(_) => pd.aps.extend(added),
+            AccessPointEvent::Removed(path) => {
+                let Some(index) = pd.aps.iter().position(|ap| ap.path == path) else {
+                    continue;
+                };
+                // The access point being connected to, or asked for, must stay until the mode is left.
+                let in_use = match &pd.state {
+                    AppState::Connecting(bssid)
+                    | AppState::PasswordInput { bssid, .. }
+                    | AppState::EnterpriseInput { bssid, .. } => *bssid == pd.aps[index].bssid,
+                    _ => false,
+                };
+                if in_use {
+                    continue;
+                }
+                pd.aps.remove(index);
+            }
+            AccessPointEvent::Profiles => pd.backend.match_profiles(&mut pd.aps),
+            AccessPointEvent::Strength(path, strength) => {
+                if let Some(ap) = pd.aps.iter_mut().find(|ap| ap.path == path) {
+                    ap.signal_strength = strength;
+                }
+            }
+        }
+
+        if resort {
+            pd.sort_accesspoints();
+        } else {
+            pd.build_rows();
+        }
+        let is_text_input = pd.is_text_input();
+        drop(guard);
+        if !is_text_input {
+            view.borrow_mut().reload();
+        }
+    }
+}
+
+// Keeps the details of the message bar in sync with the device's connection and addresses.
+// Stops once the device is switched, like access_point_events_task.
+pub async fn connection_details_task(view: SharedView) {
+    let Some(data) = view.borrow().private_data() else {
+        return;
+    };
+    let backend = Rc::clone(&data.borrow().backend);
+
+    let iface = backend.iface().to_string();
+    let mut changes = match backend.details_changes().await {
+        Ok(changes) => changes,
+        Err(e) => {
+            eprintln!("Failed to subscribe to device changes: {}", e);
+            return;
+        }
+    };
+
+    loop {
+        match backend.connection_details().await {
+            Ok(details) => data.borrow_mut().connection_details = details,
+            Err(e) => eprintln!("Failed to read the connection details: {}", e),
+        }
+
+        // rofi asks for the message again when the mode is switched, i.e. by set_prompt.
+        // Other states animate the prompt, which does the same.
+        {
+            let pd = data.borrow();
+            if pd.state == AppState::Idle {
+                view.borrow_mut().set_prompt(&pd.display_name);
+            }
+        }
+
+        if changes.next().await.is_none() || data.borrow().backend.iface() != iface {
+            return;
+        }
+    }
+}
+
+pub fn set_wifi_mode_scan(view: SharedView, data: &SharedData) -> glib::SourceId {
+    let mut pd = data.borrow_mut();
     pd.anim_scan.index = 0;
     pd.state = AppState::Scanning;
     let fps = pd.anim_scan.fps;
-
-    // let original_prompt = unsafe { std::ffi::CString::from_raw(sw.display_name) };
+    drop(pd);
 
     let interval = Duration::from_millis(1000 / fps as u64);
     glib::timeout_add_local(interval, move || {
-        // There won't be any race condition between this and .
-        let mut state_guard = sw.borrow_mut();
-        let state = state_guard.deref_mut();
-
-        let Some(pd) = rofi::get_private_state_mut::<PrivateData>(*state) else {
+        let Some(data) = view.borrow().private_data() else {
             return glib::ControlFlow::Break;
         };
 
+        let mut pd = data.borrow_mut();
         if pd.pool_shut_signal(VFBTask::Scan) {
             return glib::ControlFlow::Break;
         }
 
-        let anim_frame = &pd.anim_scan.frames[pd.anim_scan.index % pd.anim_scan.frames.len()];
+        let frame = pd.anim_scan.index % pd.anim_scan.frames.len();
         pd.anim_scan.index = pd.anim_scan.index.saturating_add(1);
-        rofi::set_prompt(state, anim_frame);
+        drop(pd);
+
+        let pd = data.borrow();
+        view.borrow_mut().set_prompt(&pd.anim_scan.frames[frame]);
         glib::ControlFlow::Continue
     })
 }
 
-// seems like incase, if the response wasn't made quick enough or not blocked, then, the
-// same events will be fired again by rofi.
-
-// so, connecting handler has been move to background task
+// The connect runs in a background task, rofi fires the same event again when it's kept waiting.
 pub fn set_mode_connecting_and_handle(
-    sw: Rc<RefCell<&'static mut ffi::Mode>>,
-    pd: &'static mut PrivateData,
+    view: SharedView,
+    data: &SharedData,
     bssid: String,
     password: Option<&std::ffi::CStr>,
     reason: u32,
 ) {
-    if pd.state == AppState::Scanning || matches!(pd.state, AppState::Connecting(_)) {
+    if matches!(
+        data.borrow().state,
+        AppState::Scanning | AppState::Connecting(_)
+    ) {
         let glib_context = glib::MainContext::default();
-        glib_context.block_on(async {
-            pd.shut_scan().await;
-        });
+        glib_context.block_on(PrivateData::shut_scan(data));
     }
-    pd.state = AppState::Connecting(bssid.clone());
+
+    let access_point = data.borrow().find_ap(&bssid).cloned();
+    let Some(access_point) = access_point else {
+        // Only the access point being connected is kept when it goes out of range, so this shouldn't happen.
+        eprintln!("Failed to connect: {} is no longer listed", bssid);
+        {
+            let mut pd = data.borrow_mut();
+            pd.hidden = None;
+            pd.state = AppState::Idle;
+        }
+        view.borrow_mut().set_prompt(c"connect failed");
+        return;
+    };
+
+    let mut pd = data.borrow_mut();
     pd.active_connection = None;
-    let fps = pd.anim_connecting.fps;
+    pd.last_failure = None;
+    pd.retry = None;
+    drop(pd);
+    set_mode_connecting(Rc::clone(&view), data, bssid.clone());
 
-    pd.sort_accesspoints();
+    let mut pd = data.borrow_mut();
+    let (cancel, canceled) = oneshot::channel();
+    pd.connect_cancel = Some(cancel);
 
-    sw.borrow_mut().display_name = c"wifi".as_ptr() as *mut i8;
+    let is_hidden = access_point.bssid.is_empty();
 
-    let interval = Duration::from_millis(1000 / fps as u64);
+    let is_pre_existing = access_point.setting_path.is_some() && reason == 0;
+    let own_password = password.map(|p| p.to_string_lossy().to_string());
+    let lock_bssid = std::mem::take(&mut pd.lock_bssid);
+    let eap_credentials = pd.eap_credentials.take();
+    let retry = RetryCredentials {
+        bssid: bssid.clone(),
+        password: own_password.clone(),
+        eap: eap_credentials.clone(),
+    };
+    let new_profile = (!is_pre_existing).then_some(NewProfile {
+        password: own_password,
+        eap: eap_credentials,
+        lock_bssid,
+    });
+    let timeout = pd.connect_timeout;
+    let backend = Rc::clone(&pd.backend);
+    drop(pd);
 
-    let access_point = if pd.hidden_ssid.is_none() {
-        // need ownership, but don't wanna rc this rc that
-        pd.aps.iter().find(|x| {
-            x.bssid == bssid
-        }).expect("unexpected error, proabbly perodic scan removed the out of range ap, however, a scan shouldn't have occuered duing this state").clone()
-    } else {
-        AccessPoint {
-            bssid: bssid.clone(),
-            frequency: 0,
-            is_protected: true,
-            signal_strength: 0,
-            ssid: bssid.clone(),
-            setting_path: None,
+    let glib_context = MainContext::default();
+
+    let data = Rc::clone(data);
+    glib_context.spawn_local(async move {
+        // Esc cancels, a failure to follow the backend is handled the same.
+        let verdict = match backend::connect_and_wait(
+            &*backend,
+            &access_point,
+            new_profile,
+            connect_timeout(Rc::clone(&view), timeout),
+            async {
+                // Dropped without sending when a newer connect took over.
+                if canceled.await.is_err() {
+                    future::pending::<()>().await;
+                }
+            },
+        )
+        .await
+        {
+            Ok(verdict) => verdict,
+            // The backend refused to start, e.g. a profile it can't create, the list is shown again.
+            Err(e) => {
+                eprintln!("Failed to connect: {}", e);
+                {
+                    let mut pd = data.borrow_mut();
+                    pd.hidden = None;
+                    pd.state = AppState::Idle;
+                }
+                PrivateData::shut_connect(&data).await;
+
+                view.borrow_mut().set_prompt(c"connect failed");
+                return;
+            }
+        };
+
+        match verdict {
+            Verdict::Canceled => {
+                {
+                    let mut pd = data.borrow_mut();
+                    pd.hidden = None;
+                    pd.state = AppState::Idle;
+                }
+                PrivateData::shut_connect(&data).await;
+
+                let pd = data.borrow();
+                view.borrow_mut().set_prompt(&pd.display_name);
+            }
+            Verdict::Failed(reason) => {
+                let index = {
+                    let mut pd = data.borrow_mut();
+                    // The profile is gone, so its credentials are kept for a retry instead.
+                    if !is_pre_existing
+                        && !is_hidden
+                        && reason::describe(reason).action == FailureAction::Retry
+                    {
+                        pd.retry = Some(retry);
+                    }
+                    pd.aps
+                        .iter()
+                        .position(|a| a.bssid == access_point.bssid)
+                        .unwrap_or(usize::MAX)
+                };
+
+                // The row may go back to Idle on a retryable failure, where the animation would keep running.
+                PrivateData::shut_connect(&data).await;
+                set_wifi_mode_password(
+                    Rc::clone(&view),
+                    &data,
+                    index,
+                    std::ffi::CString::new(bssid.clone()).unwrap().as_c_str(),
+                    reason,
+                );
+                view.borrow_mut().reset();
+            }
+            Verdict::Connected(wifi_config) => {
+                // Unless locked, the backend may have picked another access point of the network.
+                let connected = backend
+                    .active_ap()
+                    .await
+                    .ok()
+                    .flatten()
+                    .unwrap_or((bssid, wifi_config));
+                {
+                    let mut pd = data.borrow_mut();
+                    pd.set_connected(Some(connected));
+                    pd.hidden = None;
+                    pd.state = AppState::Idle;
+                }
+                PrivateData::shut_connect(&data).await;
+
+                let mut view = view.borrow_mut();
+                view.set_prompt(c"wifi");
+                view.reload();
+            }
         }
+    });
+}
+
+// Resolves once NetworkManager has spent `timeout` connecting, the time spent on the password prompt isn't counted.
+async fn connect_timeout(view: SharedView, timeout: Duration) {
+    if timeout.is_zero() {
+        return future::pending().await;
+    }
+
+    let step = Duration::from_secs(1);
+    let mut elapsed = Duration::ZERO;
+    while elapsed < timeout {
+        glib::timeout_future(step).await;
+        let waiting_on_user = view
+            .borrow()
+            .private_data()
+            .is_some_and(|pd| pd.borrow().is_text_input());
+        if !waiting_on_user {
+            elapsed += step;
+        }
+    }
+}
+
+// Cancels the connect in progress, the connect task deactivates it and cleans up after itself.
+pub fn cancel_connect(view: &mut dyn View, data: &SharedData) {
+    let cancel = data.borrow_mut().connect_cancel.take();
+    if let Some(cancel) = cancel.filter(|c| !c.is_canceled()) {
+        let _ = cancel.send(());
+        return;
+    }
+
+    // Activated externally, e.g. autoconnect after answering the secret agent.
+    disconnect(data);
+    data.borrow_mut().state = AppState::Idle;
+    glib::MainContext::default().block_on(PrivateData::shut_connect(data));
+    view.set_display_name(&data.borrow().display_name);
+}
+
+// Back to the list from a prompt, e.g. on Esc, forgetting whatever was answered so far.
+// Dismissing the secret agent's prompt gives back the input it took over instead.
+pub fn dismiss_input(view: &mut dyn View, pd: &mut PrivateData) {
+    pd.secret_input.clear();
+    pd.reveal_password = false;
+    // Dropping the pending request tells NetworkManager the prompt was dismissed.
+    if pd.pending_secret.take().is_some() && restore_input(view, pd) {
+        return;
+    }
+
+    pd.state = AppState::Idle;
+    pd.eap_credentials = None;
+    pd.hidden = None;
+    pd.lock_bssid = false;
+    pd.last_failure = None;
+    view.set_display_name(c"wifi");
+}
+
+// Goes back to the input the secret agent's request took over, if any, with the prompt it was entered with.
+fn restore_input(view: &mut dyn View, pd: &mut PrivateData) -> bool {
+    let Some(state) = pd.interrupted_input.take() else {
+        return false;
     };
 
-    let sw_render = Rc::clone(&sw);
-    let glib_context = MainContext::default();
+    let prompt = match state {
+        AppState::PasswordInput { reason, .. } if reason > 0 => reason::describe(reason).prompt,
+        AppState::EnterpriseInput { step, .. } => match step {
+            EapStep::Method => c"eap method",
+            EapStep::Identity => c"identity",
+            EapStep::Password => c"password",
+            EapStep::CaCert => c"ca cert (optional)",
+        },
+        AppState::HiddenInput(HiddenStep::Ssid) => c"ssid",
+        AppState::HiddenInput(HiddenStep::Security { .. }) => c"security",
+        _ => c"password",
+    };
+    view.set_display_name(prompt);
+    pd.state = state;
+    true
+}
+
+// Starts the connecting animation on the row of `bssid`, without touching the connection itself.
+fn set_mode_connecting(view: SharedView, data: &SharedData, bssid: String) {
+    let mut pd = data.borrow_mut();
+    pd.state = AppState::Connecting(bssid);
+    let fps = pd.anim_connecting.fps;
+
+    pd.sort_accesspoints();
+
+    view.borrow_mut().set_display_name(c"wifi");
+
+    let interval = Duration::from_millis(1000 / fps as u64);
 
     pd.allow_execute(VFBTask::Connect);
+    drop(pd);
     glib::timeout_add_local(interval, move || {
-        let mut state_guard = sw_render.borrow_mut();
-        let state = state_guard.deref_mut();
-
-        let Some(pd) = rofi::get_private_state_mut::<PrivateData>(*state) else {
+        let Some(data) = view.borrow().private_data() else {
             return glib::ControlFlow::Break;
         };
 
+        let mut pd = data.borrow_mut();
         if pd.pool_shut_signal(VFBTask::Connect) {
             return glib::ControlFlow::Break;
         }
         pd.anim_connecting.index += 1;
-        // rofi::set_prompt(state, anim_frame);
-        rofi::reload_view();
+        drop(pd);
+        // view.set_prompt(anim_frame);
+        view.borrow_mut().reload();
         glib::ControlFlow::Continue
     });
+}
 
-    let sw_state = Rc::clone(&sw);
-    let own_password = password.map(|p| p.to_string_lossy().to_string());
-    glib_context.spawn_local(async move {
-        let pd = {
-            let mut state_guard = sw_state.borrow_mut();
-            let state = state_guard.deref_mut();
-
-            rofi::get_private_state_mut::<PrivateData>(*state)
-                .ok_or_else(|| anyhow::anyhow!("Error, failed to get private data"))?
+// Routes the secret requests of NetworkManager's agent into the password prompt.
+// NetworkManager keeps the activation pending until the prompt is answered or dismissed.
+pub async fn secret_agent_task(
+    view: SharedView,
+    mut events: mpsc::UnboundedReceiver<SecretAgentEvent>,
+) {
+    while let Some(event) = events.next().await {
+        let Some(data) = view.borrow().private_data() else {
+            return;
         };
 
-        let wifi_config;
-        if access_point.setting_path.is_some() && reason == 0 {
-            wifi_config = network_manager::connect_pre_existing_access_point(
-                &pd.nm_dbus.con,
-                &access_point,
-                &pd.nm_dbus.dev_path,
-            )
-            .await?;
-        } else {
-            wifi_config = network_manager::create_and_connect_access_point(
-                &pd.nm_dbus.con,
-                &access_point,
-                &pd.nm_dbus.dev_path,
-                own_password,
-                pd.hidden_ssid.take(),
-            )
-            .await?;
-        }
-        let reason = network_manager::network_state(&pd.nm_dbus.con, &pd.nm_dbus.dev_path)
-            .await
-            .unwrap();
-        if reason > 0 {
-            network_manager::forget_config(&pd.nm_dbus.con, &wifi_config).await?;
-            let index = pd
-                .aps
-                .iter()
-                .position(|a| a.bssid == access_point.bssid)
-                .unwrap_or(usize::MAX);
-
-            set_wifi_mode_password(
-                Rc::clone(&sw),
-                pd,
-                index,
-                std::ffi::CString::new(bssid.clone()).unwrap().as_c_str(),
-                reason,
-            );
-
-            // pd.state = AppState::PasswordInput {
-            //     bssid: bssid,
-            //     reason,
-            // };
-            // pd.shut_connect().await;
-            rofi::view_reset(&mut sw.borrow_mut());
-        } else {
-            pd.set_connected(Some((bssid, wifi_config)));
-            pd.state = AppState::Idle;
-            pd.shut_connect().await;
+        match event {
+            SecretAgentEvent::Request(request) => {
+                if matches!(data.borrow().state, AppState::Connecting(_)) {
+                    PrivateData::shut_connect(&data).await;
+                } else if data.borrow().state == AppState::Scanning {
+                    PrivateData::shut_scan(&data).await;
+                }
+
+                let mut guard = data.borrow_mut();
+                let pd = &mut *guard;
+
+                let bssid = match pd.state {
+                    AppState::Connecting(ref bssid) => bssid.clone(),
+                    // Activated externally, e.g. autoconnect.
+                    // A network that isn't listed has no row to show the progress on.
+                    _ => pd
+                        .aps
+                        .iter()
+                        .find(|ap| ap.ssid == request.ssid)
+                        .map(|ap| ap.bssid.clone())
+                        .unwrap_or_default(),
+                };
+
+                // The answers typed so far are kept, and given back if the request goes away unanswered.
+                // A request replacing another one takes over the same input.
+                let is_input = matches!(
+                    pd.state,
+                    AppState::PasswordInput { .. }
+                        | AppState::EnterpriseInput { .. }
+                        | AppState::HiddenInput(_)
+                );
+                if is_input && pd.pending_secret.is_none() {
+                    pd.interrupted_input = Some(std::mem::replace(&mut pd.state, AppState::Idle));
+                }
 
-            let mut sw_guard = sw_state.borrow_mut();
-            rofi::set_prompt(&mut sw_guard, &std::ffi::CString::new("wifi").unwrap());
-            rofi::reload_view();
+                pd.pending_secret = Some(request);
+                pd.state = AppState::PasswordInput { bssid, reason: 0 };
+                drop(guard);
+                let mut view = view.borrow_mut();
+                view.set_display_name(c"password");
+                view.reset();
+            }
+            SecretAgentEvent::Cancel(connection_path) => {
+                let mut pd = data.borrow_mut();
+                if pd
+                    .pending_secret
+                    .as_ref()
+                    .is_none_or(|request| request.connection_path != connection_path)
+                {
+                    continue;
+                }
+
+                pd.pending_secret = None;
+                let mut view = view.borrow_mut();
+                if restore_input(&mut *view, &mut pd) {
+                    drop(pd);
+                    view.reset();
+                } else {
+                    pd.state = AppState::Idle;
+                    drop(pd);
+                    view.set_prompt(&data.borrow().display_name);
+                }
+            }
         }
-        anyhow::Ok(())
-    });
+    }
 }
 
-// Experementing this apporach
-// glib::MainContext::default().spawn_local(async move {
-//     while pd.state == AppState::Scanning {
-//         // Wait for next frame
-//         glib::timeout_future(Duration::from_millis(100)).await;
-//     }
-// });
+// Follows connections made or dropped outside of the plugin, e.g. by nmcli or on roaming.
+pub async fn connection_background_task(view: SharedView) -> anyhow::Result<()> {
+    let Some(data) = view.borrow().private_data() else {
+        return Ok(());
+    };
+    let backend = Rc::clone(&data.borrow().backend);
+
+    let mut notifications = backend.state_changes().await?;
+    while notifications.next().await.is_some() {
+        match backend.connection_state().await? {
+            ConnectionState::Connected => (),
+            // the disconnection occurred on this interface, or a connect started outside of the plugin.
+            ConnectionState::Connecting | ConnectionState::Disconnected => {
+                data.borrow_mut().active_connection = None
+            }
+        }
+
+        let Some((bssid, conf)) = backend.active_ap().await? else {
+            continue;
+        };
+
+        let mut pd = data.borrow_mut();
+        if let Some(ap) = pd.aps.iter_mut().find(|ap| ap.bssid == bssid) {
+            ap.setting_path = Some(conf);
+        }
+
+        pd.active_connection = Some(bssid);
+        pd.sort_accesspoints();
+    }
+    Ok(())
+}
@@ -1,7 +1,18 @@
-use std::time::Duration;
+use std::{
+    cell::RefCell,
+    ffi::CStr,
+    rc::Rc,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use futures_channel::oneshot;
+use futures_util::future::LocalBoxFuture;
 
 use zbus::zvariant::OwnedObjectPath;
 
+use crate::{backend::Backend, reason::Failure, secret_agent::SecretRequest};
+
 pub type BSSID = String;
 
 #[derive(PartialEq, Debug)]
@@ -84,26 +95,450 @@ pub enum AppState {
         // else
         reason: u32,
     },
+    /// The application is collecting 802.1X credentials for an enterprise network, one step at a time.
+    EnterpriseInput { bssid: String, step: EapStep },
+    /// The application is waiting for the user to pick one of several Wi-Fi interfaces.
+    InterfaceSelect,
+    /// The application is asking for the name, then the security of a hidden network.
+    HiddenInput(HiddenStep),
+}
+
+// Steps of the hidden network flow, its credentials are asked by PasswordInput or EnterpriseInput afterwards.
+#[derive(PartialEq, Debug, Clone)]
+pub enum HiddenStep {
+    Ssid,
+    /// Choose the security of `ssid` from `HIDDEN_SECURITY`, shown as a list.
+    Security {
+        ssid: String,
+    },
+}
+
+// The security of a hidden network can't be detected, so it's picked by the user.
+// Transition mode networks are connected with either of WPA2 and WPA3.
+pub const HIDDEN_SECURITY: [(Security, &str); 6] = [
+    (Security::WpaPsk, "WPA/WPA2-Personal"),
+    (Security::Sae, "WPA3-Personal"),
+    (Security::Enterprise, "WPA/WPA2-Enterprise"),
+    (Security::Wep, "WEP"),
+    (Security::Owe, "OWE (Enhanced Open)"),
+    (Security::Open, "Open"),
+];
+
+/// Checks the name of a hidden network, the error is the prompt shown while asking again.
+pub fn validate_ssid(ssid: &str) -> Result<(), &'static CStr> {
+    // NetworkManager takes at most 32 bytes.
+    if ssid.is_empty() {
+        Err(c"ssid can't be empty")
+    } else if ssid.len() > 32 {
+        Err(c"too long, max 32 bytes")
+    } else {
+        Ok(())
+    }
+}
+
+// Steps of the enterprise credential flow, in the order they are asked.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum EapStep {
+    /// Choose the EAP method from `EAP_METHODS`, shown as a list.
+    Method,
+    Identity,
+    Password,
+    /// Optional, an empty input skips CA validation.
+    CaCert,
+}
+
+// TLS is left out, as it requires a client certificate and private key.
+// An inner method after the slash overrides the default one of `EapCredentials::phase2`.
+pub const EAP_METHODS: [&str; 4] = ["peap", "ttls", "ttls/mschapv2", "pwd"];
+
+#[derive(Debug, Clone, Default)]
+// Credentials collected by AppState::EnterpriseInput, used to build the `802-1x` section.
+pub struct EapCredentials {
+    pub method: String,
+    pub identity: String,
+    pub password: String,
+    pub ca_cert: Option<String>,
+}
+
+impl EapCredentials {
+    /// The outer method, e.g. `ttls` of `ttls/mschapv2`.
+    pub fn eap(&self) -> &str {
+        self.method.split('/').next().unwrap_or_default()
+    }
+
+    /// The inner authentication of PEAP and TTLS, MSCHAPv2 for PEAP and PAP for TTLS unless picked.
+    /// pwd is a single phase method.
+    pub fn phase2(&self) -> Option<&str> {
+        if let Some((_, inner)) = self.method.split_once('/') {
+            return Some(inner);
+        }
+        match self.method.as_str() {
+            "peap" => Some("mschapv2"),
+            "ttls" => Some("pap"),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+// Credentials of a connect that failed for a reason unrelated to them, reused when the network is selected again.
+pub struct RetryCredentials {
+    pub bssid: BSSID,
+    pub password: Option<String>,
+    pub eap: Option<EapCredentials>,
 }
 
 #[derive(Debug, Clone)]
 // List of available aps
 pub struct AccessPoint {
+    /// The D-Bus object of the access point, `/` if it isn't backed by one, e.g. hidden network.
+    pub path: OwnedObjectPath,
     /// The name of the Wi-Fi network
     pub ssid: String,
-    /// This MAC address of the WiFi network.
+    /// This MAC address of the WiFi network, empty for a hidden network entered by the user.
     pub bssid: BSSID,
     /// The frequency of the Wi-Fi network in MHz
     #[allow(unused)]
     pub frequency: u32,
     /// The signal strength of the Wi-Fi network.
     pub signal_strength: u8,
-    // Whether the network requires a password to connect.
-    pub is_protected: bool,
+    // How the network authenticates, decides the profile's `802-11-wireless-security` section.
+    pub security: SecurityDescriptor,
     // Whether the network configuration exits.
     pub setting_path: Option<zbus::zvariant::OwnedObjectPath>,
 }
 
+// https://people.freedesktop.org/~lkundrak/nm-dbus-api/nm-dbus-types.html#NM80211ApFlags
+pub const AP_FLAGS_PRIVACY: u32 = 0x1;
+// https://people.freedesktop.org/~lkundrak/nm-dbus-api/nm-dbus-types.html#NM80211ApSecurityFlags
+pub const AP_SEC_KEY_MGMT_PSK: u32 = 0x100;
+pub const AP_SEC_KEY_MGMT_802_1X: u32 = 0x200;
+pub const AP_SEC_KEY_MGMT_SAE: u32 = 0x400;
+pub const AP_SEC_KEY_MGMT_OWE: u32 = 0x800;
+const AP_SEC_KEY_MGMT_OWE_TM: u32 = 0x1000;
+pub const AP_SEC_KEY_MGMT_EAP_SUITE_B_192: u32 = 0x2000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+// The security of a network, decoded from the access point's flags.
+pub enum Security {
+    Open,
+    /// Opportunistic Wireless Encryption (Enhanced Open), encrypted without a password.
+    Owe,
+    Wep,
+    /// WPA/WPA2-Personal
+    WpaPsk,
+    /// WPA3-Personal
+    Sae,
+    /// WPA2/WPA3-Personal transition mode, both WPA-PSK and SAE are accepted.
+    Mixed,
+    /// WPA/WPA2/WPA3-Enterprise (802.1X)
+    Enterprise,
+}
+
+/// What the message bar shows in place of each character of a password.
+pub const PASSWORD_MASK: char = '•';
+
+impl Security {
+    pub fn from_flags(flags: u32, wpa_flags: u32, rsn_flags: u32) -> Self {
+        let key_mgmt = wpa_flags | rsn_flags;
+        if key_mgmt & (AP_SEC_KEY_MGMT_802_1X | AP_SEC_KEY_MGMT_EAP_SUITE_B_192) != 0 {
+            Security::Enterprise
+        } else if key_mgmt & AP_SEC_KEY_MGMT_SAE != 0 {
+            if key_mgmt & AP_SEC_KEY_MGMT_PSK != 0 {
+                Security::Mixed
+            } else {
+                Security::Sae
+            }
+        } else if key_mgmt & AP_SEC_KEY_MGMT_PSK != 0 {
+            Security::WpaPsk
+        } else if key_mgmt & (AP_SEC_KEY_MGMT_OWE | AP_SEC_KEY_MGMT_OWE_TM) != 0 {
+            // The open side of a transition mode network advertises OWE_TM, NM then connects to its OWE side.
+            Security::Owe
+        } else if flags & AP_FLAGS_PRIVACY != 0 {
+            // Privacy without any WPA key management is WEP.
+            Security::Wep
+        } else {
+            Security::Open
+        }
+    }
+
+    /// The `key-mgmt` a new profile uses, `None` for an open network, i.e. no security section.
+    /// Transition mode networks use WPA-PSK, NetworkManager upgrades it to SAE when the device supports it,
+    /// and a PSK given as 64 hex digits still works.
+    pub fn key_mgmt(&self) -> Option<&'static str> {
+        match self {
+            Security::Open => None,
+            Security::Owe => Some("owe"),
+            Security::Wep => Some("none"),
+            Security::WpaPsk | Security::Mixed => Some("wpa-psk"),
+            Security::Sae => Some("sae"),
+            Security::Enterprise => Some("wpa-eap"),
+        }
+    }
+
+    /// Whether a saved profile with this `key-mgmt` can connect to the network.
+    pub fn accepts(&self, key_mgmt: Option<&str>) -> bool {
+        match self {
+            Security::Mixed => matches!(key_mgmt, Some("wpa-psk" | "sae")),
+            Security::Enterprise => matches!(key_mgmt, Some("wpa-eap" | "wpa-eap-suite-b-192")),
+            _ => self.key_mgmt() == key_mgmt,
+        }
+    }
+
+    /// The security of a saved profile with this `key-mgmt`, the reverse of `key_mgmt`.
+    pub fn from_key_mgmt(key_mgmt: &str) -> Option<Self> {
+        match key_mgmt {
+            "none" => Some(Security::Wep),
+            "wpa-psk" => Some(Security::WpaPsk),
+            "sae" => Some(Security::Sae),
+            "owe" => Some(Security::Owe),
+            "wpa-eap" | "wpa-eap-suite-b-192" | "ieee8021x" => Some(Security::Enterprise),
+            _ => None,
+        }
+    }
+
+    pub fn needs_password(&self) -> bool {
+        !matches!(self, Security::Open | Security::Owe)
+    }
+
+    /// Checks a password the way NetworkManager would, so a malformed one never reaches it.
+    /// The error is the prompt shown while asking again.
+    pub fn validate_password(&self, password: &str) -> Result<(), &'static CStr> {
+        let is_hex =
+            |len: usize| password.len() == len && password.chars().all(|c| c.is_ascii_hexdigit());
+        let is_printable = password.chars().all(|c| matches!(c, ' '..='~'));
+
+        match self {
+            // A WPA passphrase is 8 to 63 printable ASCII characters, or the 64 hex digits of the key itself.
+            // Transition mode networks accept WPA2 clients, so the same holds for them.
+            Security::WpaPsk | Security::Mixed => {
+                if is_hex(64) {
+                    Ok(())
+                } else if !is_printable {
+                    Err(c"invalid character")
+                } else if password.len() < 8 {
+                    Err(c"too short, min 8")
+                } else if password.len() > 63 {
+                    Err(c"too long, max 63")
+                } else {
+                    Ok(())
+                }
+            }
+            // SAE has no length limits, only an empty password is wrong.
+            Security::Sae => {
+                if password.is_empty() {
+                    Err(c"empty password")
+                } else if password.chars().any(char::is_control) {
+                    Err(c"invalid character")
+                } else {
+                    Ok(())
+                }
+            }
+            // The key itself, or a passphrase NetworkManager hashes into one.
+            Security::Wep => {
+                if is_wep_key(password) {
+                    Ok(())
+                } else if !is_printable {
+                    Err(c"invalid character")
+                } else if password.is_empty() {
+                    Err(c"empty password")
+                } else if password.len() > 64 {
+                    Err(c"too long, max 64")
+                } else {
+                    Ok(())
+                }
+            }
+            Security::Open | Security::Owe | Security::Enterprise => Ok(()),
+        }
+    }
+}
+
+/// Whether a WEP password is the key itself, 40 or 104 bits as ASCII characters or hex digits, rather than a passphrase.
+pub fn is_wep_key(key: &str) -> bool {
+    match key.len() {
+        5 | 13 => key.chars().all(|c| matches!(c, ' '..='~')),
+        10 | 26 => key.chars().all(|c| c.is_ascii_hexdigit()),
+        _ => false,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+// The security class of a network, along with the bitfields it was decoded from,
+// which tell the WPA generations apart within a class.
+pub struct SecurityDescriptor {
+    pub class: Security,
+    /// NM80211ApSecurityFlags of the WPA (WPA1) element.
+    pub wpa_flags: u32,
+    /// NM80211ApSecurityFlags of the RSN (WPA2/WPA3) element.
+    pub rsn_flags: u32,
+}
+
+impl SecurityDescriptor {
+    pub fn from_flags(flags: u32, wpa_flags: u32, rsn_flags: u32) -> Self {
+        SecurityDescriptor {
+            class: Security::from_flags(flags, wpa_flags, rsn_flags),
+            wpa_flags,
+            rsn_flags,
+        }
+    }
+
+    /// Descriptor of a network that wasn't seen, e.g. a hidden one, only its class is known.
+    pub fn from_class(class: Security) -> Self {
+        SecurityDescriptor {
+            class,
+            wpa_flags: 0,
+            rsn_flags: 0,
+        }
+    }
+
+    /// A short name shown in the list, e.g. `WPA/WPA2` or `WPA3`.
+    pub fn label(&self) -> &'static str {
+        let wpa = self.wpa_flags != 0;
+        let rsn = self.rsn_flags != 0;
+        match self.class {
+            Security::Open => "Open",
+            Security::Owe => "OWE",
+            Security::Wep => "WEP",
+            Security::WpaPsk => match (wpa, rsn) {
+                (true, true) => "WPA/WPA2",
+                (true, false) => "WPA",
+                _ => "WPA2",
+            },
+            Security::Sae => "WPA3",
+            Security::Mixed => "WPA2/WPA3",
+            Security::Enterprise if self.rsn_flags & AP_SEC_KEY_MGMT_EAP_SUITE_B_192 != 0 => {
+                "WPA3-Enterprise 192-bit"
+            }
+            Security::Enterprise => match (wpa, rsn) {
+                (true, true) => "WPA/WPA2-Enterprise",
+                (true, false) => "WPA-Enterprise",
+                _ => "WPA2-Enterprise",
+            },
+        }
+    }
+}
+
+impl AccessPoint {
+    /// A hidden network entered by the user, it isn't backed by any access point until it's connected.
+    pub fn hidden(ssid: String, class: Security) -> Self {
+        AccessPoint {
+            path: Default::default(),
+            ssid,
+            bssid: BSSID::new(),
+            frequency: 0,
+            signal_strength: 0,
+            security: SecurityDescriptor::from_class(class),
+            setting_path: None,
+        }
+    }
+
+    /// The `key-mgmt` a profile of this network would use, `None` for an open network.
+    pub fn key_mgmt(&self) -> Option<&'static str> {
+        self.security.class.key_mgmt()
+    }
+
+    /// Whether both access points belong to the same network, i.e. share SSID and security.
+    /// Hidden networks don't share a name, so they never do.
+    pub fn same_network(&self, other: &AccessPoint) -> bool {
+        !self.ssid.is_empty() && self.ssid == other.ssid && self.key_mgmt() == other.key_mgmt()
+    }
+
+    /// The channel number, 0 if the frequency is unknown.
+    pub fn channel(&self) -> u32 {
+        match self.frequency {
+            2484 => 14,
+            2407..=2483 => (self.frequency - 2407) / 5,
+            5000..=5900 => (self.frequency - 5000) / 5,
+            5950..=7125 => (self.frequency - 5950) / 5,
+            _ => 0,
+        }
+    }
+
+    /// The band in GHz, empty if the frequency is unknown.
+    pub fn band(&self) -> &'static str {
+        match self.frequency {
+            2400..=2500 => "2.4",
+            4900..=5900 => "5",
+            5925..=7125 => "6",
+            _ => "",
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+// Addresses of the active connection, shown in the message bar.
+pub struct ConnectionDetails {
+    /// Addresses with their prefix, e.g. `192.168.1.2/24`.
+    pub ipv4: Vec<String>,
+    pub ipv6: Vec<String>,
+    pub gateway4: Option<String>,
+    pub gateway6: Option<String>,
+    /// Both IPv4 and IPv6 DNS servers.
+    pub dns: Vec<String>,
+    /// The current bitrate in Kb/s.
+    pub bitrate: u32,
+}
+
+#[derive(Debug, PartialEq)]
+// A row of the list shown by rofi, pointing into `PrivateData::aps`.
+pub enum Row {
+    /// The Wi-Fi radio and airplane mode switch, always the first row.
+    Radio,
+    /// Access points of the same network, collapsed into one row.
+    Group(Vec<usize>),
+    /// An access point of the expanded group, listed right under it.
+    Member(usize),
+    /// Starts the hidden network flow, always the last row.
+    Hidden,
+}
+
+impl Row {
+    pub fn indices(&self) -> &[usize] {
+        match self {
+            Row::Radio | Row::Hidden => &[],
+            Row::Group(group) => group,
+            Row::Member(index) => std::slice::from_ref(index),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+// NetworkManager's radio switches, airplane mode is both turned off.
+pub struct Radio {
+    /// WirelessEnabled
+    pub wifi: bool,
+    /// WwanEnabled
+    pub wwan: bool,
+}
+
+impl Radio {
+    pub fn airplane_mode(&self) -> bool {
+        !self.wifi && !self.wwan
+    }
+
+    /// What selecting the switch row does: airplane mode is left with both radios on,
+    /// otherwise only the Wi-Fi radio is flipped.
+    pub fn toggled(&self) -> Radio {
+        if self.airplane_mode() {
+            Radio::default()
+        } else {
+            Radio {
+                wifi: !self.wifi,
+                ..*self
+            }
+        }
+    }
+}
+
+impl Default for Radio {
+    fn default() -> Self {
+        Radio {
+            wifi: true,
+            wwan: true,
+        }
+    }
+}
+
 #[derive(Debug)]
 // Data for rendering a loading animation during Wi-Fi scanning.
 pub struct IndicatorAnim {
@@ -119,21 +554,126 @@ pub struct IndicatorAnim {
     pub fps: u8,
 }
 
-#[derive(Debug)]
 // This stores blocking and must be converted into an async function.
 // Converting to async is cheap since both are just thin wrappers around the same connection.
 pub struct NetworkManagerDbusProxy {
     pub con: zbus::Connection, //clone of con is cheap
+    /// Name of the interface the proxies point to.
+    pub iface: String,
+    /// Names of every Wi-Fi interface, the last picked first.
+    pub interfaces: Vec<String>,
     pub wifi_proxy: zbus::Proxy<'static>,
     pub property_proxy: zbus::fdo::PropertiesProxy<'static>,
     pub dev_path: zbus::zvariant::OwnedObjectPath,
+    /// The saved Wi-Fi profiles, kept in sync by `access_point_events`,
+    /// so an access point that shows up is matched without asking NetworkManager for every profile.
+    pub profiles: Arc<Mutex<Vec<SavedProfile>>>,
+    /// The verdict of the activation in progress, awaited by `connect_result`.
+    pub pending: RefCell<Option<LocalBoxFuture<'static, anyhow::Result<u32>>>>,
+}
+
+impl std::fmt::Debug for NetworkManagerDbusProxy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NetworkManagerDbusProxy")
+            .field("iface", &self.iface)
+            .field("dev_path", &self.dev_path)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug, Clone)]
+// A saved Wi-Fi profile of NetworkManager, what the access points are matched against.
+pub struct SavedProfile {
+    pub path: OwnedObjectPath,
+    pub ssid: String,
+    pub key_mgmt: Option<String>,
+    /// Set when the profile is locked to an access point.
+    pub bssid: Option<BSSID>,
 }
 
 #[derive(Debug)]
 // Represents Wi-Fi icons for different security modes
 pub struct WiFiIcon {
     pub open: Vec<char>,
+    pub owe: Vec<char>,
+    pub wep: Vec<char>,
     pub psk: Vec<char>,
+    pub sae: Vec<char>,
+    pub eap: Vec<char>,
+}
+
+impl WiFiIcon {
+    /// The icons of a security class, transition mode networks share the WPA3 icons.
+    pub fn for_class(&self, class: Security) -> &[char] {
+        match class {
+            Security::Open => &self.open,
+            Security::Owe => &self.owe,
+            Security::Wep => &self.wep,
+            Security::WpaPsk => &self.psk,
+            Security::Sae | Security::Mixed => &self.sae,
+            Security::Enterprise => &self.eap,
+        }
+    }
+
+    /// The icon of a network, from its class and signal strength.
+    pub fn for_signal(&self, class: Security, signal_strength: u8) -> char {
+        let icons = self.for_class(class);
+        match signal_strength {
+            70..=100 => icons[0], //exec
+            50..=69 => icons[1],  //good
+            30..=49 => icons[2],  // fair
+            10..=29 => icons[3],  // weak
+            _ => icons[4],        // very weak
+        }
+    }
+}
+
+#[derive(Debug)]
+// Rofi's kb-custom-N numbers bound to the plugin's actions.
+pub struct KeyBindings {
+    /// Connects to a new network, with its profile locked to the selected access point.
+    pub lock_bssid: u32,
+    /// Expands or collapses the access points of the selected network.
+    pub expand: u32,
+    /// Triggers a scan right away.
+    pub rescan: u32,
+    /// Disconnects from the connected network, keeping it saved.
+    pub disconnect: u32,
+    /// Turns airplane mode on or off.
+    pub airplane: u32,
+    /// Shows or hides the password being typed.
+    pub reveal: u32,
+}
+
+#[derive(Debug)]
+// When the plugin asks NetworkManager to scan, scans are what drains the battery.
+pub struct ScanPolicy {
+    /// Seconds between background scans.
+    pub interval: u32,
+    /// Scan once when rofi is opened, then only by the rescan key.
+    pub on_open_only: bool,
+}
+
+impl Default for ScanPolicy {
+    fn default() -> Self {
+        ScanPolicy {
+            interval: 10,
+            on_open_only: false,
+        }
+    }
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        KeyBindings {
+            lock_bssid: 1,
+            expand: 2,
+            rescan: 3,
+            disconnect: 4,
+            airplane: 5,
+            reveal: 6,
+        }
+    }
 }
 
 impl Default for AppState {
@@ -146,47 +686,82 @@ impl IndicatorAnim {
     pub fn build_scan(display_name: &str, frame: &str) -> std::ffi::CString {
         std::ffi::CString::new(format!("{} {}", frame, display_name)).unwrap()
     }
-    // pub fn build_connect(display_name: &str, frame: &str) -> std::ffi::CString {
-    //     std::ffi::CString::new(format!("{}{}", display_name, frame)).unwrap()
-    // }
+    // Unlike the scan's, the frames are shown as they are, any name is part of them.
     pub fn build_connect(frame: &str) -> std::ffi::CString {
-        //  Remove the display_name, a name must be attached within frame
         std::ffi::CString::new(frame).unwrap()
     }
 }
 
+// The open ones go without a lock, OWE's is open as it needs no password, and WEP's warns as it's broken.
+// Networks behind a password or an account share the lock, the list names their security.
 impl Default for WiFiIcon {
     fn default() -> Self {
         WiFiIcon {
             open: vec!['󰤨', '󰤥', '󰤢', '󰤟', '󰤯'],
+            owe: vec!['󱛎', '󱛍', '󱛌', '󱛋', '󱛏'],
+            wep: vec!['󰤩', '󰤦', '󰤣', '󰤠', '󰤫'],
             psk: vec!['󰤪', '󰤧', '󰤤', '󰤡', '󰤬'],
+            sae: vec!['󰤪', '󰤧', '󰤤', '󰤡', '󰤬'],
+            eap: vec!['󰤪', '󰤧', '󰤤', '󰤡', '󰤬'],
         }
     }
 }
-// Basically, The entire state of the application
+// The entire state of the application
+
+// Shared by the view, the state machine and its tasks, all on glib's main context.
+// Never borrowed across an await, and only immutably across a redraw, rofi reads it again meanwhile.
+pub type SharedData = Rc<RefCell<PrivateData>>;
 
 #[derive(Debug)]
 pub struct PrivateData {
     pub anim_scan: IndicatorAnim,
     pub anim_connecting: IndicatorAnim,
     pub aps: Vec<AccessPoint>,
+    // Rebuilt from `aps` whenever they are sorted.
+    pub rows: Vec<Row>,
+    // Any access point of the expanded group, its members are listed as rows.
+    pub expanded: Option<AccessPoint>,
+    // The access point of the row rofi last passed to `wifi_mode_result`, e.g. the group expanded, shown in the message bar.
+    pub highlighted: Option<BSSID>,
     // leaked from rust gc
     #[allow(unused)]
     leaked_display_values: Vec<*mut std::ffi::CString>,
     pub state: AppState,
     pub icons: WiFiIcon,
+    pub keys: KeyBindings,
+    pub scan_policy: ScanPolicy,
+    // How long NetworkManager may take to connect before the attempt is given up, zero waits forever.
+    pub connect_timeout: Duration,
+    // Cancels the connect in progress, answered by `state::set_mode_connecting_and_handle`.
+    pub connect_cancel: Option<oneshot::Sender<()>>,
+    pub radio: Radio,
     pub active_connection: Option<BSSID>,
-    pub nm_dbus: NetworkManagerDbusProxy,
+    // Details of the active connection, refreshed by `state::connection_details_task`.
+    pub connection_details: Option<ConnectionDetails>,
+    // NetworkManager, unless another backend was picked or found running.
+    // Cloned out before awaiting it, so the private data isn't borrowed meanwhile.
+    pub backend: Rc<dyn Backend>,
     pub display_name: std::ffi::CString,
-    pub hidden_ssid: Option<String>,
+    // The hidden network being connected, the states refer to it by an empty BSSID.
+    pub hidden: Option<AccessPoint>,
+    pub eap_credentials: Option<EapCredentials>,
+    // Secret requested by NetworkManager's agent, answered by the password prompt.
+    pub pending_secret: Option<SecretRequest>,
+    // The prompt the agent's request took over, given back once the request is canceled or dismissed.
+    pub interrupted_input: Option<AppState>,
+    // Whether the profile being created is locked to the selected BSSID.
+    pub lock_bssid: bool,
+    // The password being typed, the message bar shows it masked unless revealed.
+    pub secret_input: String,
+    pub reveal_password: bool,
+    // Why the last connect failed, shown in the message bar until the next one.
+    pub last_failure: Option<Failure>,
+    pub retry: Option<RetryCredentials>,
     _execution_signal: ExecutionSignals,
 }
 
 impl PrivateData {
-    pub fn new(
-        network_manager_proxy: NetworkManagerDbusProxy,
-        cached_aps: Vec<AccessPoint>,
-    ) -> Self {
+    pub fn new(backend: Box<dyn Backend>, cached_aps: Vec<AccessPoint>) -> Self {
         Self {
             anim_scan: IndicatorAnim {
                 frames: vec![
@@ -210,47 +785,70 @@ impl PrivateData {
                 fps: 4,
             },
             aps: cached_aps,
-            nm_dbus: network_manager_proxy,
+            rows: Vec::new(),
+            expanded: None,
+            highlighted: None,
+            backend: backend.into(),
             active_connection: None,
-            hidden_ssid: None,
+            connection_details: None,
+            hidden: None,
+            eap_credentials: None,
+            pending_secret: None,
+            interrupted_input: None,
+            lock_bssid: false,
+            secret_input: String::new(),
+            reveal_password: false,
+            last_failure: None,
+            retry: None,
             _execution_signal: ExecutionSignals {
                 scan_task: FnSIG::CanRun,
                 connect_task: FnSIG::CanRun,
             },
             display_name: std::ffi::CString::new("wifi").unwrap(),
             icons: WiFiIcon::default(),
+            keys: KeyBindings::default(),
+            scan_policy: ScanPolicy::default(),
+            connect_timeout: Duration::from_secs(60),
+            connect_cancel: None,
+            radio: Radio::default(),
             leaked_display_values: Vec::new(),
             state: AppState::Idle,
         }
     }
 
-    async fn shut(field: &mut FnSIG, fps: u8) {
-        if *field == FnSIG::RespondToShut {
-            // function is already shutdown.
-            return;
-        }
+    // Asks the visual feedback of `task` to shut, and resolves once it responded.
+    async fn shut(data: &SharedData, task: VFBTask) {
+        let fps = {
+            let mut pd = data.borrow_mut();
+            let fps = match task {
+                VFBTask::Scan => pd.anim_scan.fps,
+                VFBTask::Connect => pd.anim_connecting.fps,
+            };
+            let field = task.task_signal(&mut pd._execution_signal);
+            if *field == FnSIG::RespondToShut {
+                // function is already shutdown.
+                return;
+            }
+            *field = FnSIG::ShutGracefull;
+            fps
+        };
 
-        *field = FnSIG::ShutGracefull;
         loop {
             glib::timeout_future(Duration::from_millis(1000 / fps as u64)).await;
 
-            {
-                if *field == FnSIG::RespondToShut {
-                    break;
-                }
+            let mut pd = data.borrow_mut();
+            if *task.task_signal(&mut pd._execution_signal) == FnSIG::RespondToShut {
+                break;
             }
         }
     }
 
-    pub fn shut_scan(&mut self) -> impl Future<Output = ()> {
-        Self::shut(&mut self._execution_signal.scan_task, self.anim_scan.fps)
+    pub async fn shut_scan(data: &SharedData) {
+        Self::shut(data, VFBTask::Scan).await
     }
 
-    pub fn shut_connect(&mut self) -> impl Future<Output = ()> {
-        Self::shut(
-            &mut self._execution_signal.connect_task,
-            self.anim_connecting.fps,
-        )
+    pub async fn shut_connect(data: &SharedData) {
+        Self::shut(data, VFBTask::Connect).await
     }
 
     pub fn allow_execute(&mut self, task: VFBTask) {
@@ -284,7 +882,87 @@ impl PrivateData {
                 self.aps.swap(index, 0);
             }
         }
+        self.build_rows();
+    }
+
+    // Groups the access points of the same network, in the order of their first appearance.
+    pub fn build_rows(&mut self) {
+        let mut groups: Vec<Vec<usize>> = Vec::new();
+        for (index, ap) in self.aps.iter().enumerate() {
+            match groups
+                .iter_mut()
+                .find(|group| self.aps[group[0]].same_network(ap))
+            {
+                Some(group) => group.push(index),
+                None => groups.push(vec![index]),
+            }
+        }
+
+        self.rows.clear();
+        self.rows.push(Row::Radio);
+        for group in groups {
+            let is_expanded = group.len() > 1
+                && self
+                    .expanded
+                    .as_ref()
+                    .is_some_and(|ap| self.aps[group[0]].same_network(ap));
+
+            let members = group.clone();
+            self.rows.push(Row::Group(group));
+            if is_expanded {
+                self.rows.extend(members.into_iter().map(Row::Member));
+            }
+        }
+        self.rows.push(Row::Hidden);
+    }
+
+    /// The access point `bssid` refers to, an empty one is the hidden network being connected.
+    pub fn find_ap(&self, bssid: &str) -> Option<&AccessPoint> {
+        if bssid.is_empty() {
+            return self.hidden.as_ref();
+        }
+        self.aps.iter().find(|ap| ap.bssid == bssid)
     }
+
+    /// The access point a row stands for, a group is represented by its first, i.e. the connected or strongest.
+    pub fn row_ap(&self, selected_line: usize) -> Option<usize> {
+        self.rows.get(selected_line)?.indices().first().copied()
+    }
+
+    /// Expands the group of the selected row, or collapses it if it's already expanded.
+    pub fn toggle_expanded(&mut self, selected_line: usize) {
+        let Some(ap) = self
+            .row_ap(selected_line)
+            .and_then(|index| self.aps.get(index))
+        else {
+            return;
+        };
+
+        if self.expanded.as_ref().is_some_and(|e| e.same_network(ap)) {
+            self.expanded = None;
+        } else {
+            self.expanded = Some(ap.clone());
+        }
+        self.build_rows();
+    }
+    // Whether the rofi entry is used as a free text field, so no rows are listed.
+    pub fn is_text_input(&self) -> bool {
+        match self.state {
+            AppState::PasswordInput { .. } | AppState::HiddenInput(HiddenStep::Ssid) => true,
+            AppState::EnterpriseInput { step, .. } => step != EapStep::Method,
+            _ => false,
+        }
+    }
+
+    // Whether the rofi entry is used for a password, so it is shown masked.
+    pub fn is_secret_input(&self) -> bool {
+        match self.state {
+            AppState::PasswordInput { .. } => true,
+            AppState::EnterpriseInput { step, .. } => step == EapStep::Password,
+            _ => false,
+        }
+    }
+
     pub fn set_connected(&mut self, signature: Option<(BSSID, OwnedObjectPath)>) {
         if let Some((bssid, config)) = signature {
             if let Some(ap) = self.aps.iter_mut().find(|ap| ap.bssid == bssid) {
@@ -294,3 +972,99 @@ impl PrivateData {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECURITIES: [Security; 7] = [
+        Security::Open,
+        Security::Owe,
+        Security::Wep,
+        Security::WpaPsk,
+        Security::Sae,
+        Security::Mixed,
+        Security::Enterprise,
+    ];
+
+    #[test]
+    fn security_is_decoded_from_the_flags() {
+        assert_eq!(Security::from_flags(0, 0, 0), Security::Open);
+        assert_eq!(Security::from_flags(AP_FLAGS_PRIVACY, 0, 0), Security::Wep);
+        let psk_sae = AP_SEC_KEY_MGMT_PSK | AP_SEC_KEY_MGMT_SAE;
+        assert_eq!(Security::from_flags(1, 0, psk_sae), Security::Mixed);
+        assert_eq!(
+            Security::from_flags(1, 0, AP_SEC_KEY_MGMT_SAE),
+            Security::Sae
+        );
+        assert_eq!(
+            Security::from_flags(0, 0, AP_SEC_KEY_MGMT_OWE_TM),
+            Security::Owe
+        );
+        // Enterprise wins over a PSK advertised along with it.
+        let eap_psk = AP_SEC_KEY_MGMT_802_1X | AP_SEC_KEY_MGMT_PSK;
+        assert_eq!(Security::from_flags(1, eap_psk, 0), Security::Enterprise);
+    }
+
+    #[test]
+    fn profile_key_mgmt_is_accepted_by_its_security() {
+        for security in SECURITIES {
+            let key_mgmt = security.key_mgmt();
+            assert!(security.accepts(key_mgmt), "{:?}", security);
+            // A transition mode network is saved as WPA-PSK.
+            let expected = match security {
+                Security::Mixed => Some(Security::WpaPsk),
+                _ => Some(security),
+            };
+            assert_eq!(
+                key_mgmt.map_or(Some(Security::Open), Security::from_key_mgmt),
+                expected
+            );
+        }
+        assert!(Security::Mixed.accepts(Some("sae")));
+        assert!(!Security::WpaPsk.accepts(Some("sae")));
+        assert!(!Security::Open.accepts(Some("owe")));
+    }
+
+    #[test]
+    fn wpa_password_is_a_passphrase_or_the_key_itself() {
+        let security = Security::WpaPsk;
+        assert!(security.validate_password("correct horse").is_ok());
+        assert!(security.validate_password(&"a".repeat(63)).is_ok());
+        assert!(security.validate_password(&"0f".repeat(32)).is_ok());
+        assert_eq!(
+            security.validate_password("short"),
+            Err(c"too short, min 8")
+        );
+        assert_eq!(
+            security.validate_password(&"z".repeat(64)),
+            Err(c"too long, max 63")
+        );
+        assert_eq!(
+            security.validate_password("pässword"),
+            Err(c"invalid character")
+        );
+    }
+
+    #[test]
+    fn sae_and_wep_passwords_have_their_own_limits() {
+        assert!(Security::Sae.validate_password("short").is_ok());
+        assert!(Security::Sae.validate_password("pässwort").is_ok());
+        assert_eq!(Security::Sae.validate_password(""), Err(c"empty password"));
+
+        assert!(Security::Wep.validate_password("abcde").is_ok());
+        assert!(Security::Wep.validate_password("0123456789").is_ok());
+        assert!(Security::Wep.validate_password("any passphrase").is_ok());
+        assert_eq!(Security::Wep.validate_password(""), Err(c"empty password"));
+
+        assert!(Security::Open.validate_password("").is_ok());
+    }
+
+    #[test]
+    fn hidden_ssid_is_at_most_32_bytes() {
+        assert!(validate_ssid("Office").is_ok());
+        assert!(validate_ssid(&"a".repeat(32)).is_ok());
+        assert!(validate_ssid(&"é".repeat(17)).is_err());
+        assert!(validate_ssid("").is_err());
+    }
+}
@@ -1,5 +1,6 @@
 pub const UUIDV4_PREFIX: &str = "12345678";
 use rand;
+use std::path::PathBuf;
 pub fn generate_uuid() -> String {
     let mut ran_bytes: [u8; 16] = rand::random();
     ran_bytes[6] = (ran_bytes[6] & 0x0F) | 0x40; //v4 in the most significant
@@ -22,3 +23,59 @@ pub fn generate_uuid() -> String {
         ran_bytes[15],
     )
 }
+
+// The signal strength as NetworkManager shows it, -100 dBm and below is 0%, -50 dBm and above is 100%.
+pub fn dbm_to_percent(dbm: i32) -> u8 {
+    ((dbm + 100) * 2).clamp(0, 100) as u8
+}
+
+// The last picked interface is remembered, so it is preferred on the next run.
+fn last_interface_path() -> Option<PathBuf> {
+    let cache_dir = std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))?;
+    Some(cache_dir.join("rofi-wifi").join("interface"))
+}
+
+pub fn load_last_interface() -> Option<String> {
+    let iface = std::fs::read_to_string(last_interface_path()?).ok()?;
+    Some(iface.trim().to_string())
+}
+
+pub fn save_last_interface(iface: &str) -> std::io::Result<()> {
+    let path = last_interface_path().ok_or(std::io::ErrorKind::NotFound)?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, iface)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dbm_is_shown_as_networkmanager_does() {
+        assert_eq!(dbm_to_percent(-100), 0);
+        assert_eq!(dbm_to_percent(-120), 0);
+        assert_eq!(dbm_to_percent(-75), 50);
+        assert_eq!(dbm_to_percent(-50), 100);
+        assert_eq!(dbm_to_percent(-30), 100);
+    }
+
+    #[test]
+    fn uuid_is_a_version_4_one() {
+        let uuid = generate_uuid();
+        let groups: Vec<&str> = uuid.split('-').collect();
+        assert_eq!(
+            groups.iter().map(|g| g.len()).collect::<Vec<_>>(),
+            [8, 4, 4, 4, 12]
+        );
+        assert_eq!(groups[0], UUIDV4_PREFIX);
+        assert!(groups[2].starts_with('4'));
+        assert!(matches!(
+            groups[3].chars().next(),
+            Some('8' | '9' | 'a' | 'b')
+        ));
+    }
+}
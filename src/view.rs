@@ -0,0 +1,73 @@
+use crate::structure::{PrivateData, SharedData};
+use futures_channel::mpsc;
+use std::{
+    cell::RefCell,
+    ffi::{CStr, CString},
+    rc::Rc,
+};
+
+// Whatever shows the menu, rofi's mode for the plugin.
+// The state machine only talks to it through this, so it builds and runs without rofi.
+pub trait View {
+    /// The state of the menu, `None` once the menu is gone.
+    fn private_data(&self) -> Option<SharedData>;
+
+    /// Changes the prompt, shown once the menu is drawn again, e.g. after the dialog is reset.
+    /// The prompt may be kept without copying, so it must outlive the next one, i.e. be static or owned by `PrivateData`.
+    fn set_display_name(&mut self, prompt: &CStr);
+
+    /// Changes the prompt and redraws the menu right away.
+    fn set_prompt(&mut self, prompt: &CStr);
+
+    /// Redraws the rows.
+    fn reload(&mut self);
+
+    /// Starts the menu over, e.g. to show the password prompt.
+    fn reset(&mut self);
+}
+
+pub type SharedView = Rc<RefCell<dyn View>>;
+
+// A view for front-ends that draw the menu themselves, e.g. a dmenu compatible launcher run once per prompt.
+// It keeps the prompt for the next draw, and tells whenever the state machine redraws, e.g. once a connect is over.
+pub struct PromptView {
+    pd: SharedData,
+    pub prompt: CString,
+    changes: mpsc::UnboundedSender<()>,
+}
+
+impl PromptView {
+    /// The view owns the private data, the receiver yields on every redraw.
+    pub fn new(pd: PrivateData, prompt: CString) -> (Self, mpsc::UnboundedReceiver<()>) {
+        let (changes, receiver) = mpsc::unbounded();
+        let view = PromptView {
+            pd: Rc::new(RefCell::new(pd)),
+            prompt,
+            changes,
+        };
+        (view, receiver)
+    }
+}
+
+impl View for PromptView {
+    fn private_data(&self) -> Option<SharedData> {
+        Some(Rc::clone(&self.pd))
+    }
+
+    fn set_display_name(&mut self, prompt: &CStr) {
+        self.prompt = prompt.into();
+    }
+
+    fn set_prompt(&mut self, prompt: &CStr) {
+        self.prompt = prompt.into();
+        let _ = self.changes.unbounded_send(());
+    }
+
+    fn reload(&mut self) {
+        let _ = self.changes.unbounded_send(());
+    }
+
+    fn reset(&mut self) {
+        let _ = self.changes.unbounded_send(());
+    }
+}
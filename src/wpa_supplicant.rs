@@ -0,0 +1,893 @@
+use crate::{
+    backend::{AccessPointEvent, Backend, ConnectionState},
+    secret_agent::{SecretAgentEvent, SecretRequest},
+    structure::*,
+    utils,
+};
+use anyhow::{Context, bail};
+use futures_channel::{mpsc, oneshot};
+use futures_util::{
+    FutureExt, StreamExt,
+    future::LocalBoxFuture,
+    stream::{self, BoxStream},
+};
+use std::{cell::RefCell, collections::HashMap};
+use zbus::{
+    Connection, MatchRule, MessageStream, Proxy,
+    fdo::PropertiesProxy,
+    names::InterfaceName,
+    zvariant::{Array, OwnedObjectPath, OwnedValue, Value},
+};
+
+// Bare wpa_supplicant, e.g. started by the init system with `-u`.
+// https://w1.fi/wpa_supplicant/devel/dbus.html
+//
+// Its networks stand for the profiles, they're only kept across restarts with `update_config=1`.
+// Its BSSs are the access points, described by the same NM80211ApSecurityFlags as NetworkManager's.
+
+const SUPPLICANT: &str = "fi.w1.wpa_supplicant1";
+const ROOT_PATH: &str = "/fi/w1/wpa_supplicant1";
+const INTERFACE: &str = "fi.w1.wpa_supplicant1.Interface";
+const BSS: &str = "fi.w1.wpa_supplicant1.BSS";
+const NETWORK: &str = "fi.w1.wpa_supplicant1.Network";
+
+pub struct WpaSupplicant {
+    con: Connection,
+    iface: String,
+    interfaces: Vec<String>,
+    iface_path: OwnedObjectPath,
+    // How the connect in progress goes, followed from before the network is selected, awaited by `connect_result`.
+    progress: RefCell<Option<BoxStream<'static, String>>>,
+}
+
+impl std::fmt::Debug for WpaSupplicant {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WpaSupplicant")
+            .field("iface", &self.iface)
+            .field("iface_path", &self.iface_path)
+            .finish_non_exhaustive()
+    }
+}
+
+// A network of the interface, i.e. a saved profile.
+struct SavedNetwork {
+    path: OwnedObjectPath,
+    ssid: String,
+    key_mgmt: Option<&'static str>,
+    bssid: Option<BSSID>,
+}
+
+// Without an interface, the last picked one is used, otherwise the first found.
+// An interface wpa_supplicant doesn't manage yet is added, as when it's started without `-i`.
+pub async fn setup(iface: Option<&str>) -> anyhow::Result<WpaSupplicant> {
+    let con = Connection::system().await?;
+    let mut interfaces = managed_interfaces(&con).await?;
+
+    // The last picked interface goes first.
+    if let Some(last_iface) = utils::load_last_interface()
+        && let Some(index) = interfaces.iter().position(|(name, _)| *name == last_iface)
+    {
+        let interface = interfaces.remove(index);
+        interfaces.insert(0, interface);
+    }
+
+    let (iface_name, iface_path) = match iface {
+        Some(iface) => match interfaces.iter().find(|(name, _)| name == iface) {
+            Some(interface) => interface.clone(),
+            None => {
+                let root = Proxy::new(&con, SUPPLICANT, ROOT_PATH, SUPPLICANT).await?;
+                let mut args = HashMap::new();
+                args.insert("Ifname", Value::from(iface));
+                let path: OwnedObjectPath = root
+                    .call("CreateInterface", &(args))
+                    .await
+                    .with_context(|| format!("Wi-Fi interface {} not found", iface))?;
+                interfaces.push((iface.to_string(), path.clone()));
+                (iface.to_string(), path)
+            }
+        },
+        None => interfaces
+            .first()
+            .cloned()
+            .context("No Wi-Fi interface found")?,
+    };
+
+    Ok(WpaSupplicant {
+        interfaces: interfaces.into_iter().map(|(name, _)| name).collect(),
+        iface: iface_name,
+        con,
+        iface_path,
+        progress: RefCell::default(),
+    })
+}
+
+// Lists the name and object path of every interface wpa_supplicant manages.
+async fn managed_interfaces(con: &Connection) -> anyhow::Result<Vec<(String, OwnedObjectPath)>> {
+    let root = Proxy::new(con, SUPPLICANT, ROOT_PATH, SUPPLICANT).await?;
+    let paths: Vec<OwnedObjectPath> = root.get_property("Interfaces").await?;
+
+    let mut interfaces = Vec::new();
+    for path in paths {
+        let interface = Proxy::new(con, SUPPLICANT, path.clone(), INTERFACE).await?;
+        let name: String = interface.get_property("Ifname").await?;
+        interfaces.push((name, path));
+    }
+    interfaces.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(interfaces)
+}
+
+// Reads a byte array, e.g. the SSID or the BSSID of a BSS.
+fn bytes(value: &OwnedValue) -> Option<Vec<u8>> {
+    let array = value.downcast_ref::<Array>().ok()?;
+    Some(
+        array
+            .iter()
+            .filter_map(|x| x.downcast_ref::<u8>().ok())
+            .collect(),
+    )
+}
+
+fn format_bssid(bytes: &[u8]) -> BSSID {
+    bytes
+        .iter()
+        .map(|v| format!("{:02X}", v))
+        .collect::<Vec<String>>()
+        .join(":")
+}
+
+fn hex_to_bytes(hex: &str) -> Vec<u8> {
+    (0..hex.len() / 2)
+        .filter_map(|i| u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok())
+        .collect()
+}
+
+// The key management suites of a BSS's `WPA` or `RSN` element, as NM80211ApSecurityFlags.
+fn key_mgmt_flags(element: Option<&OwnedValue>) -> u32 {
+    let Some(suites) = element
+        .and_then(|element| HashMap::<String, OwnedValue>::try_from(element.clone()).ok())
+        .and_then(|element| element.get("KeyMgmt").cloned())
+        .and_then(|suites| Vec::<String>::try_from(suites).ok())
+    else {
+        return 0;
+    };
+
+    suites.iter().fold(0, |flags, suite| {
+        flags
+            | match suite.as_str() {
+                "wpa-psk" | "wpa-ft-psk" | "wpa-psk-sha256" => AP_SEC_KEY_MGMT_PSK,
+                "wpa-eap" | "wpa-ft-eap" | "wpa-eap-sha256" | "wpa-eap-suite-b" => {
+                    AP_SEC_KEY_MGMT_802_1X
+                }
+                "wpa-eap-suite-b-192" => AP_SEC_KEY_MGMT_EAP_SUITE_B_192,
+                "sae" | "ft-sae" => AP_SEC_KEY_MGMT_SAE,
+                "owe" => AP_SEC_KEY_MGMT_OWE,
+                _ => 0,
+            }
+    })
+}
+
+// A BSS out of its properties, `None` when one is missing.
+fn access_point(path: OwnedObjectPath, bss: &HashMap<String, OwnedValue>) -> Option<AccessPoint> {
+    let ssid = bytes(bss.get("SSID")?)?;
+    let bssid = bytes(bss.get("BSSID")?)?;
+    let signal = i16::try_from(bss.get("Signal")?).ok()?;
+    let frequency = u16::try_from(bss.get("Frequency")?).ok()?;
+    let privacy = bool::try_from(bss.get("Privacy")?).ok()?;
+
+    let flags = if privacy { AP_FLAGS_PRIVACY } else { 0 };
+    let wpa_flags = key_mgmt_flags(bss.get("WPA"));
+    let rsn_flags = key_mgmt_flags(bss.get("RSN"));
+
+    Some(AccessPoint {
+        path,
+        ssid: String::from_utf8_lossy(&ssid).to_string(),
+        bssid: format_bssid(&bssid),
+        frequency: frequency.into(),
+        signal_strength: utils::dbm_to_percent(signal.into()),
+        security: SecurityDescriptor::from_flags(flags, wpa_flags, rsn_flags),
+        setting_path: None,
+    })
+}
+
+// A network's SSID is either quoted text or hex.
+fn network_ssid(ssid: &str) -> String {
+    match ssid
+        .strip_prefix('"')
+        .and_then(|ssid| ssid.strip_suffix('"'))
+    {
+        Some(ssid) => ssid.to_string(),
+        None => String::from_utf8_lossy(&hex_to_bytes(ssid)).to_string(),
+    }
+}
+
+// A network's `key_mgmt` as NetworkManager's, so `Security::accepts` applies.
+fn network_key_mgmt(key_mgmt: &str) -> Option<&'static str> {
+    let suites: Vec<&str> = key_mgmt.split_whitespace().collect();
+    if suites
+        .iter()
+        .any(|suite| matches!(*suite, "WPA-EAP" | "WPA-EAP-SHA256" | "IEEE8021X"))
+    {
+        Some("wpa-eap")
+    } else if suites.contains(&"WPA-EAP-SUITE-B-192") {
+        Some("wpa-eap-suite-b-192")
+    } else if suites.contains(&"SAE") {
+        Some("sae")
+    } else if suites.contains(&"WPA-PSK") {
+        Some("wpa-psk")
+    } else if suites.contains(&"OWE") {
+        Some("owe")
+    } else {
+        None
+    }
+}
+
+fn saved_network(
+    path: OwnedObjectPath,
+    properties: &HashMap<String, OwnedValue>,
+) -> Option<SavedNetwork> {
+    let text = |key: &str| -> Option<&str> { properties.get(key)?.try_into().ok() };
+    Some(SavedNetwork {
+        ssid: network_ssid(text("ssid")?),
+        key_mgmt: text("key_mgmt").and_then(network_key_mgmt),
+        bssid: text("bssid")
+            .filter(|bssid| *bssid != "any")
+            .map(str::to_uppercase),
+        path,
+    })
+}
+
+// Marks the access points with a saved network, the same way as `network_manager::match_profiles`.
+fn mark_saved(aps: &mut [AccessPoint], networks: &[SavedNetwork]) {
+    // A network locked to a BSSID only applies to that access point, and wins over one matching by SSID.
+    for network in networks.iter().filter(|network| network.bssid.is_some()) {
+        for ap in aps.iter_mut() {
+            if network.bssid.as_ref() == Some(&ap.bssid) {
+                ap.setting_path = Some(network.path.clone());
+            }
+        }
+    }
+    for network in networks.iter().filter(|network| network.bssid.is_none()) {
+        for ap in aps.iter_mut() {
+            // wpa_supplicant keeps the keys to itself, which is all that tells a WEP network from an open one.
+            let accepts = ap.security.class.accepts(network.key_mgmt)
+                || (ap.security.class == Security::Wep && network.key_mgmt.is_none());
+            if ap.setting_path.is_none() && ap.ssid == network.ssid && accepts {
+                ap.setting_path = Some(network.path.clone());
+            }
+        }
+    }
+}
+
+// The settings of a new network, the strings wpa_supplicant expects quoted are quoted by it, byte arrays are passed as hex.
+fn network_settings(
+    access_point: &AccessPoint,
+    password: Option<String>,
+    hidden: bool,
+    eap: Option<EapCredentials>,
+    lock_bssid: bool,
+) -> anyhow::Result<HashMap<&'static str, Value<'static>>> {
+    let mut settings = HashMap::new();
+    settings.insert("ssid", Value::from(access_point.ssid.as_bytes().to_vec()));
+    // A hidden network doesn't answer broadcast probes, so wpa_supplicant probes for its SSID.
+    if hidden {
+        settings.insert("scan_ssid", Value::from(1i32));
+    }
+    // Without a BSSID, the network follows the SSID across every access point.
+    if lock_bssid && !hidden {
+        settings.insert("bssid", Value::from(access_point.bssid.clone()));
+    }
+
+    // The raw key, 64 hex digits, isn't quoted.
+    let psk = |password: String| {
+        if password.len() == 64 && password.chars().all(|c| c.is_ascii_hexdigit()) {
+            Value::from(hex_to_bytes(&password))
+        } else {
+            Value::from(password)
+        }
+    };
+
+    match access_point.security.class {
+        Security::Open => {
+            settings.insert("key_mgmt", Value::from("NONE"));
+        }
+        Security::Owe => {
+            settings.insert("key_mgmt", Value::from("OWE"));
+            settings.insert("ieee80211w", Value::from(2i32));
+        }
+        Security::Wep => {
+            let key = password.context("WEP access point requires a key")?;
+            // Unlike NetworkManager, wpa_supplicant doesn't hash passphrases.
+            anyhow::ensure!(
+                is_wep_key(&key),
+                "wpa_supplicant only takes a WEP key, not a passphrase"
+            );
+            settings.insert("key_mgmt", Value::from("NONE"));
+            settings.insert("auth_alg", Value::from("OPEN"));
+            settings.insert("wep_tx_keyidx", Value::from(0i32));
+            // Hex keys aren't quoted either.
+            let key = if matches!(key.len(), 10 | 26) {
+                Value::from(hex_to_bytes(&key))
+            } else {
+                Value::from(key)
+            };
+            settings.insert("wep_key0", key);
+        }
+        Security::WpaPsk => {
+            let password = password.context("WPA access point requires a password")?;
+            settings.insert("key_mgmt", Value::from("WPA-PSK"));
+            settings.insert("psk", psk(password));
+        }
+        // SAE has no length limits, unlike `psk`.
+        Security::Sae => {
+            let password = password.context("WPA3 access point requires a password")?;
+            settings.insert("key_mgmt", Value::from("SAE"));
+            settings.insert("sae_password", Value::from(password));
+            settings.insert("ieee80211w", Value::from(2i32));
+        }
+        Security::Mixed => {
+            let password = password.context("WPA3 access point requires a password")?;
+            settings.insert("key_mgmt", Value::from("WPA-PSK SAE"));
+            settings.insert("psk", psk(password));
+            settings.insert("ieee80211w", Value::from(1i32));
+        }
+        Security::Enterprise => {
+            let eap = eap.context("Enterprise access point requires 802.1X credentials")?;
+            settings.insert("key_mgmt", Value::from("WPA-EAP"));
+            settings.insert("eap", Value::from(eap.eap().to_uppercase()));
+            if let Some(phase2) = eap.phase2() {
+                let phase2 = format!("auth={}", phase2.to_uppercase());
+                settings.insert("phase2", Value::from(phase2));
+            }
+            settings.insert("identity", Value::from(eap.identity));
+            settings.insert("password", Value::from(eap.password));
+            if let Some(ca_cert) = eap.ca_cert {
+                settings.insert("ca_cert", Value::from(ca_cert));
+            }
+        }
+    }
+    Ok(settings)
+}
+
+impl WpaSupplicant {
+    async fn interface_proxy(&self) -> zbus::Result<Proxy<'static>> {
+        Proxy::new(&self.con, SUPPLICANT, self.iface_path.clone(), INTERFACE).await
+    }
+
+    async fn state(&self) -> anyhow::Result<String> {
+        Ok(self.interface_proxy().await?.get_property("State").await?)
+    }
+
+    async fn fetch_bss(&self, path: OwnedObjectPath) -> anyhow::Result<AccessPoint> {
+        let properties = PropertiesProxy::new(&self.con, SUPPLICANT, path.clone()).await?;
+        let bss = properties
+            .get_all(InterfaceName::from_static_str_unchecked(BSS))
+            .await?;
+        access_point(path, &bss).context("Missing BSS property")
+    }
+
+    async fn saved_networks(&self) -> anyhow::Result<Vec<SavedNetwork>> {
+        let paths: Vec<OwnedObjectPath> = self
+            .interface_proxy()
+            .await?
+            .get_property("Networks")
+            .await?;
+
+        let mut networks = Vec::new();
+        for path in paths {
+            let network = Proxy::new(&self.con, SUPPLICANT, path.clone(), NETWORK).await?;
+            let properties: HashMap<String, OwnedValue> =
+                network.get_property("Properties").await?;
+            networks.extend(saved_network(path, &properties));
+        }
+        Ok(networks)
+    }
+
+    // Yields whenever one of `properties` of the interface changes.
+    async fn interface_changes(
+        &self,
+        properties: &'static [&'static str],
+    ) -> anyhow::Result<BoxStream<'static, ()>> {
+        let properties_proxy =
+            PropertiesProxy::new(&self.con, SUPPLICANT, self.iface_path.clone()).await?;
+        let changes = properties_proxy.receive_properties_changed().await?;
+        Ok(changes
+            .filter_map(move |signal| async move {
+                let args = signal.args().ok()?;
+                properties
+                    .iter()
+                    .any(|property| args.changed_properties.contains_key(*property))
+                    .then_some(())
+            })
+            .boxed())
+    }
+
+    // SelectNetwork disables every other network, they're enabled again once the connect is done,
+    // so wpa_supplicant still falls back to them.
+    async fn enable_networks(&self) -> anyhow::Result<()> {
+        let paths: Vec<OwnedObjectPath> = self
+            .interface_proxy()
+            .await?
+            .get_property("Networks")
+            .await?;
+        for path in paths {
+            let network = Proxy::new(&self.con, SUPPLICANT, path, NETWORK).await?;
+            network.set_property("Enabled", true).await?;
+        }
+        Ok(())
+    }
+
+    // The states the interface goes through, along with the EAP failures, which wpa_supplicant doesn't tell by a state.
+    async fn watch_progress(
+        &self,
+        interface: &Proxy<'static>,
+    ) -> anyhow::Result<BoxStream<'static, String>> {
+        let properties_proxy =
+            PropertiesProxy::new(&self.con, SUPPLICANT, self.iface_path.clone()).await?;
+        let states = properties_proxy
+            .receive_properties_changed()
+            .await?
+            .filter_map(|signal| async move {
+                let args = signal.args().ok()?;
+                let state: &str = args.changed_properties.get("State")?.try_into().ok()?;
+                Some(state.to_string())
+            });
+        let eap_failures = interface
+            .receive_signal("EAP")
+            .await?
+            .filter_map(|msg| async move {
+                let (status, parameter): (String, String) = msg.body().deserialize().ok()?;
+                (status == "completion" && parameter == "failure")
+                    .then(|| "eap-failure".to_string())
+            });
+        Ok(stream::select(states.boxed(), eap_failures.boxed()).boxed())
+    }
+
+    async fn select(
+        &self,
+        network: OwnedObjectPath,
+    ) -> anyhow::Result<(OwnedObjectPath, OwnedObjectPath)> {
+        let interface = self.interface_proxy().await?;
+        // Followed first, wpa_supplicant may be done before SelectNetwork returns.
+        let progress = self.watch_progress(&interface).await?;
+        *self.progress.borrow_mut() = Some(progress);
+        let _: () = interface.call("SelectNetwork", &(&network)).await?;
+        // The network itself, the interface is disconnected to stop it.
+        Ok((network, self.iface_path.clone()))
+    }
+}
+
+// Fails without `update_config=1`, the networks then only last until wpa_supplicant restarts.
+async fn save_config(interface: &Proxy<'_>) {
+    let _: zbus::Result<()> = interface.call("SaveConfig", &()).await;
+}
+
+impl Backend for WpaSupplicant {
+    fn interfaces(&self) -> &[String] {
+        &self.interfaces
+    }
+
+    fn iface(&self) -> &str {
+        &self.iface
+    }
+
+    fn select_interface<'a>(
+        &'a self,
+        iface: &'a str,
+    ) -> LocalBoxFuture<'a, anyhow::Result<Box<dyn Backend>>> {
+        async move {
+            let (iface_name, iface_path) = managed_interfaces(&self.con)
+                .await?
+                .into_iter()
+                .find(|(name, _)| name == iface)
+                .with_context(|| format!("Wi-Fi interface {} not found", iface))?;
+            Ok(Box::new(WpaSupplicant {
+                con: self.con.clone(),
+                iface: iface_name,
+                interfaces: self.interfaces.clone(),
+                iface_path,
+                // A connect in progress stays with the interface it was started on.
+                progress: RefCell::default(),
+            }) as Box<dyn Backend>)
+        }
+        .boxed_local()
+    }
+
+    // wpa_supplicant leaves the radio to rfkill, it only tells whether the interface is blocked.
+    fn radio(&self) -> LocalBoxFuture<'_, anyhow::Result<Radio>> {
+        async move {
+            let wifi = self.state().await? != "interface_disabled";
+            Ok(Radio { wifi, wwan: true })
+        }
+        .boxed_local()
+    }
+
+    fn set_radio(&self, _radio: Radio) -> LocalBoxFuture<'_, anyhow::Result<()>> {
+        async move { bail!("wpa_supplicant can't switch the radio, use rfkill instead") }
+            .boxed_local()
+    }
+
+    fn radio_changes(&self) -> LocalBoxFuture<'_, anyhow::Result<BoxStream<'static, ()>>> {
+        self.interface_changes(&["State"]).boxed_local()
+    }
+
+    fn scan(&self) -> LocalBoxFuture<'_, anyhow::Result<()>> {
+        async move {
+            let interface = self.interface_proxy().await?;
+            let mut done = interface.receive_signal("ScanDone").await?;
+            let mut args = HashMap::new();
+            args.insert("Type", Value::from("active"));
+            let _: () = interface.call("Scan", &(args)).await?;
+
+            if done.next().await.is_some() {
+                return Ok(());
+            }
+            bail!("Expected Some, got None")
+        }
+        .boxed_local()
+    }
+
+    fn access_points(&self) -> LocalBoxFuture<'_, anyhow::Result<Vec<AccessPoint>>> {
+        async move {
+            let paths: Vec<OwnedObjectPath> =
+                self.interface_proxy().await?.get_property("BSSs").await?;
+            let mut aps = Vec::with_capacity(paths.len());
+            for path in paths {
+                aps.push(self.fetch_bss(path).await?);
+            }
+            mark_saved(&mut aps, &self.saved_networks().await?);
+            Ok(aps)
+        }
+        .boxed_local()
+    }
+
+    fn access_point(
+        &self,
+        path: OwnedObjectPath,
+    ) -> LocalBoxFuture<'_, anyhow::Result<AccessPoint>> {
+        async move {
+            let mut added = [self.fetch_bss(path).await?];
+            // Still listed without its network.
+            match self.saved_networks().await {
+                Ok(networks) => mark_saved(&mut added, &networks),
+                Err(e) => eprintln!("Failed to look up saved networks: {}", e),
+            }
+            let [ap] = added;
+            Ok(ap)
+        }
+        .boxed_local()
+    }
+
+    fn access_point_events(
+        &self,
+    ) -> LocalBoxFuture<'_, anyhow::Result<BoxStream<'static, AccessPointEvent>>> {
+        async move {
+            let interface = self.interface_proxy().await?;
+            let added = interface
+                .receive_signal("BSSAdded")
+                .await?
+                .filter_map(|msg| async move {
+                    let (path, _): (OwnedObjectPath, HashMap<String, OwnedValue>) =
+                        msg.body().deserialize().ok()?;
+                    Some(AccessPointEvent::Added(path))
+                });
+
+            let removed =
+                interface
+                    .receive_signal("BSSRemoved")
+                    .await?
+                    .filter_map(|msg| async move {
+                        let path: OwnedObjectPath = msg.body().deserialize().ok()?;
+                        Some(AccessPointEvent::Removed(path))
+                    });
+
+            // The BSSs are objects below the interface, a single match rule covers all of them.
+            let rule = MatchRule::builder()
+                .msg_type(zbus::message::Type::Signal)
+                .sender(SUPPLICANT)?
+                .interface("org.freedesktop.DBus.Properties")?
+                .member("PropertiesChanged")?
+                .path_namespace(self.iface_path.clone())?
+                .arg(0, BSS)?
+                .build();
+
+            let strength = MessageStream::for_match_rule(rule, &self.con, None)
+                .await?
+                .filter_map(|msg| async move {
+                    let msg = msg.ok()?;
+                    let path = OwnedObjectPath::from(msg.header().path()?.to_owned());
+                    let (_, changed, _): (String, HashMap<String, OwnedValue>, Vec<String>) =
+                        msg.body().deserialize().ok()?;
+                    let signal = i16::try_from(changed.get("Signal")?).ok()?;
+                    Some(AccessPointEvent::Strength(
+                        path,
+                        utils::dbm_to_percent(signal.into()),
+                    ))
+                });
+
+            Ok(stream::select_all([added.boxed(), removed.boxed(), strength.boxed()]).boxed())
+        }
+        .boxed_local()
+    }
+
+    fn active_ap(&self) -> LocalBoxFuture<'_, anyhow::Result<Option<(BSSID, OwnedObjectPath)>>> {
+        async move {
+            let interface = self.interface_proxy().await?;
+            let state: String = interface.get_property("State").await?;
+            let bss_path: OwnedObjectPath = interface.get_property("CurrentBSS").await?;
+            let network_path: OwnedObjectPath = interface.get_property("CurrentNetwork").await?;
+            // Still connecting, or the network vanished meanwhile.
+            if state != "completed" || bss_path.as_str() == "/" || network_path.as_str() == "/" {
+                return Ok(None);
+            }
+
+            let bss = Proxy::new(&self.con, SUPPLICANT, bss_path, BSS).await?;
+            let bssid: Vec<u8> = bss.get_property("BSSID").await?;
+            Ok(Some((format_bssid(&bssid), network_path)))
+        }
+        .boxed_local()
+    }
+
+    fn active_access_point(&self) -> LocalBoxFuture<'_, anyhow::Result<Option<AccessPoint>>> {
+        async move {
+            let bss_path: OwnedObjectPath = self
+                .interface_proxy()
+                .await?
+                .get_property("CurrentBSS")
+                .await?;
+            if bss_path.as_str() == "/" {
+                return Ok(None);
+            }
+            Ok(Some(self.access_point(bss_path).await?))
+        }
+        .boxed_local()
+    }
+
+    fn connection_state(&self) -> LocalBoxFuture<'_, anyhow::Result<ConnectionState>> {
+        async move {
+            Ok(match self.state().await?.as_str() {
+                "completed" => ConnectionState::Connected,
+                "authenticating" | "associating" | "associated" | "4way_handshake"
+                | "group_handshake" => ConnectionState::Connecting,
+                _ => ConnectionState::Disconnected,
+            })
+        }
+        .boxed_local()
+    }
+
+    fn state_changes(&self) -> LocalBoxFuture<'_, anyhow::Result<BoxStream<'static, ()>>> {
+        self.interface_changes(&["State", "CurrentBSS"])
+            .boxed_local()
+    }
+
+    // Addresses are up to the DHCP client, which wpa_supplicant doesn't know of, so only the bitrate is told.
+    fn connection_details(&self) -> LocalBoxFuture<'_, anyhow::Result<Option<ConnectionDetails>>> {
+        async move {
+            let interface = self.interface_proxy().await?;
+            let state: String = interface.get_property("State").await?;
+            if state != "completed" {
+                return Ok(None);
+            }
+            // Not every driver answers the poll.
+            let poll: HashMap<String, OwnedValue> =
+                interface.call("SignalPoll", &()).await.unwrap_or_default();
+            // In Mb/s.
+            let linkspeed = poll
+                .get("linkspeed")
+                .and_then(|value| i32::try_from(value).ok())
+                .unwrap_or_default();
+            Ok(Some(ConnectionDetails {
+                bitrate: linkspeed.max(0) as u32 * 1000,
+                ..Default::default()
+            }))
+        }
+        .boxed_local()
+    }
+
+    fn details_changes(&self) -> LocalBoxFuture<'_, anyhow::Result<BoxStream<'static, ()>>> {
+        self.interface_changes(&["State", "CurrentBSS"])
+            .boxed_local()
+    }
+
+    fn connect<'a>(
+        &'a self,
+        access_point: &'a AccessPoint,
+        password: Option<String>,
+        hidden: bool,
+        eap: Option<EapCredentials>,
+        lock_bssid: bool,
+    ) -> LocalBoxFuture<'a, anyhow::Result<(OwnedObjectPath, OwnedObjectPath)>> {
+        async move {
+            let settings = network_settings(access_point, password, hidden, eap, lock_bssid)?;
+            let interface = self.interface_proxy().await?;
+            let network: OwnedObjectPath = interface.call("AddNetwork", &(settings)).await?;
+
+            // Nothing to forget for the caller, the network is removed right away.
+            match self.select(network.clone()).await {
+                Ok(selected) => Ok(selected),
+                Err(e) => {
+                    let _: zbus::Result<()> = interface.call("RemoveNetwork", &(&network)).await;
+                    Err(e)
+                }
+            }
+        }
+        .boxed_local()
+    }
+
+    fn connect_saved<'a>(
+        &'a self,
+        access_point: &'a AccessPoint,
+    ) -> LocalBoxFuture<'a, anyhow::Result<(OwnedObjectPath, OwnedObjectPath)>> {
+        async move {
+            let network = access_point
+                .setting_path
+                .clone()
+                .context("setting path is required")?;
+            self.select(network).await
+        }
+        .boxed_local()
+    }
+
+    // wpa_supplicant keeps trying on its own, so a failure is only told by how the states go.
+    fn connect_result(&self) -> LocalBoxFuture<'_, anyhow::Result<u32>> {
+        async move {
+            let mut progress = self
+                .progress
+                .borrow_mut()
+                .take()
+                .context("No connect in progress")?;
+
+            let mut handshaking = false;
+            let mut reason = None;
+            while let Some(state) = progress.next().await {
+                match state.as_str() {
+                    "completed" => reason = Some(0),
+                    "4way_handshake" | "group_handshake" => handshaking = true,
+                    // NM_DEVICE_STATE_REASON_SUPPLICANT_DISCONNECT, usually a wrong password.
+                    "disconnected" | "scanning" if handshaking => reason = Some(8),
+                    // NM_DEVICE_STATE_REASON_NO_SECRETS, the credentials were rejected.
+                    "eap-failure" => reason = Some(7),
+                    // NM_DEVICE_STATE_REASON_SUPPLICANT_FAILED, e.g. the interface was blocked.
+                    "inactive" | "interface_disabled" => reason = Some(10),
+                    _ => (),
+                }
+                if reason.is_some() {
+                    break;
+                }
+            }
+            let reason = reason.context("Unexpected result")?;
+
+            let interface = self.interface_proxy().await?;
+            if reason == 0 {
+                save_config(&interface).await;
+            }
+            self.enable_networks().await?;
+            Ok(reason)
+        }
+        .boxed_local()
+    }
+
+    fn deactivate<'a>(
+        &'a self,
+        _active: &'a OwnedObjectPath,
+    ) -> LocalBoxFuture<'a, anyhow::Result<()>> {
+        async move {
+            let interface = self.interface_proxy().await?;
+            let _: () = interface.call("Disconnect", &()).await?;
+            self.enable_networks().await
+        }
+        .boxed_local()
+    }
+
+    fn forget_profile<'a>(
+        &'a self,
+        profile: &'a OwnedObjectPath,
+    ) -> LocalBoxFuture<'a, anyhow::Result<()>> {
+        async move {
+            let interface = self.interface_proxy().await?;
+            let _: () = interface.call("RemoveNetwork", &(profile)).await?;
+            save_config(&interface).await;
+            Ok(())
+        }
+        .boxed_local()
+    }
+
+    fn forget<'a>(&'a self, ssid: &'a str) -> LocalBoxFuture<'a, anyhow::Result<usize>> {
+        async move {
+            let interface = self.interface_proxy().await?;
+            let mut forgotten = 0;
+            for network in self.saved_networks().await? {
+                if network.ssid == ssid {
+                    let _: () = interface.call("RemoveNetwork", &(&network.path)).await?;
+                    forgotten += 1;
+                }
+            }
+
+            if forgotten > 0 {
+                let _: zbus::Result<()> = interface.call("SaveConfig", &()).await;
+            }
+            Ok(forgotten)
+        }
+        .boxed_local()
+    }
+
+    fn disconnect(&self) -> LocalBoxFuture<'_, anyhow::Result<()>> {
+        async move {
+            let interface = self.interface_proxy().await?;
+            let state: String = interface.get_property("State").await?;
+            if matches!(
+                state.as_str(),
+                "disconnected" | "inactive" | "scanning" | "interface_disabled"
+            ) {
+                return Ok(());
+            }
+            let _: () = interface.call("Disconnect", &()).await?;
+            Ok(())
+        }
+        .boxed_local()
+    }
+
+    // wpa_supplicant asks for a missing password through the NetworkRequest signal, answered with NetworkReply.
+    fn register_agent(
+        &self,
+    ) -> LocalBoxFuture<'_, anyhow::Result<mpsc::UnboundedReceiver<SecretAgentEvent>>> {
+        async move {
+            let interface = self.interface_proxy().await?;
+            let mut requests = interface.receive_signal("NetworkRequest").await?;
+            let (events, receiver) = mpsc::unbounded();
+            let con = self.con.clone();
+
+            glib::MainContext::default().spawn_local(async move {
+                while let Some(msg) = requests.next().await {
+                    let Ok((network, field, _)): zbus::Result<(OwnedObjectPath, String, String)> =
+                        msg.body().deserialize()
+                    else {
+                        continue;
+                    };
+                    // Only the secrets the password prompt answers.
+                    if !matches!(field.as_str(), "PSK_PASSPHRASE" | "PASSWORD") {
+                        continue;
+                    }
+
+                    let saved = match Proxy::new(&con, SUPPLICANT, network.clone(), NETWORK).await {
+                        Ok(proxy) => proxy
+                            .get_property::<HashMap<String, OwnedValue>>("Properties")
+                            .await
+                            .ok()
+                            .and_then(|properties| saved_network(network.clone(), &properties)),
+                        Err(_) => continue,
+                    };
+                    let security = match field.as_str() {
+                        "PASSWORD" => Some(Security::Enterprise),
+                        _ => saved
+                            .as_ref()
+                            .and_then(|saved| saved.key_mgmt)
+                            .and_then(Security::from_key_mgmt),
+                    };
+
+                    let (reply, answer) = oneshot::channel();
+                    let request = SecretAgentEvent::Request(SecretRequest {
+                        ssid: saved.map(|saved| saved.ssid).unwrap_or_default(),
+                        connection_path: network.clone(),
+                        security,
+                        reply,
+                    });
+                    if events.unbounded_send(request).is_err() {
+                        return;
+                    }
+                    // Dismissed, wpa_supplicant asks again on its next attempt.
+                    let Ok(secret) = answer.await else {
+                        continue;
+                    };
+                    if let Err(e) = interface
+                        .call::<_, _, ()>("NetworkReply", &(&network, &field, &secret))
+                        .await
+                    {
+                        eprintln!("Failed to answer wpa_supplicant: {}", e);
+                    }
+                }
+            });
+            Ok(receiver)
+        }
+        .boxed_local()
+    }
+}
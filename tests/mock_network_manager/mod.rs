@@ -0,0 +1,753 @@
+use anyhow::Context;
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader},
+    process::{Child, Command, Stdio},
+    sync::{Arc, Mutex},
+};
+use wifi::structure::{AP_FLAGS_PRIVACY, AP_SEC_KEY_MGMT_PSK};
+use zbus::{
+    Connection, ObjectServer, connection, fdo,
+    object_server::SignalEmitter,
+    zvariant::{OwnedObjectPath, OwnedValue, Value},
+};
+
+// A fake NetworkManager, serving the subset of its D-Bus API the plugin uses, on a private dbus-daemon.
+// https://people.freedesktop.org/~lkundrak/nm-dbus-api/spec.html
+//
+// A single Wi-Fi device, `wlan0`. The access points and saved profiles are scripted by the tests, and an activation
+// only ends once the test calls `finish_activation`, with the outcome decided when it started:
+// connected, no secrets (7) for a wrong password, or SSID not found (53) when the access point is gone.
+// After `finish_activations_at_once`, it ends before AddAndActivateConnection or ActivateConnection even return.
+
+const NETWORK_MANAGER: &str = "org.freedesktop.NetworkManager";
+const MANAGER_PATH: &str = "/org/freedesktop/NetworkManager";
+const SETTINGS_PATH: &str = "/org/freedesktop/NetworkManager/Settings";
+pub const DEVICE_PATH: &str = "/org/freedesktop/NetworkManager/Devices/1";
+pub const IFACE: &str = "wlan0";
+
+// https://people.freedesktop.org/~lkundrak/nm-dbus-api/nm-dbus-types.html#NMDeviceState
+const DEVICE_STATE_DISCONNECTED: u32 = 30;
+const DEVICE_STATE_PREPARE: u32 = 40;
+const DEVICE_STATE_ACTIVATED: u32 = 100;
+const DEVICE_STATE_FAILED: u32 = 120;
+
+// https://people.freedesktop.org/~lkundrak/nm-dbus-api/nm-dbus-types.html#NMActiveConnectionState
+const ACTIVE_STATE_ACTIVATING: u32 = 1;
+const ACTIVE_STATE_ACTIVATED: u32 = 2;
+const ACTIVE_STATE_DEACTIVATED: u32 = 4;
+
+// https://people.freedesktop.org/~lkundrak/nm-dbus-api/nm-dbus-types.html#NMDeviceStateReason
+const REASON_NO_SECRETS: u32 = 7;
+const REASON_SSID_NOT_FOUND: u32 = 53;
+const REASON_USER_REQUESTED: u32 = 39;
+
+type Settings = HashMap<String, HashMap<String, OwnedValue>>;
+
+#[derive(Debug, Clone)]
+pub struct MockAp {
+    pub ssid: String,
+    pub bssid: String,
+    pub frequency: u32,
+    pub strength: u8,
+    /// The PSK it accepts, `None` for an open network.
+    pub password: Option<String>,
+}
+
+impl MockAp {
+    pub fn open(ssid: &str, bssid: &str) -> Self {
+        MockAp {
+            ssid: ssid.to_string(),
+            bssid: bssid.to_string(),
+            frequency: 2412,
+            strength: 70,
+            password: None,
+        }
+    }
+
+    pub fn wpa2(ssid: &str, bssid: &str, password: &str) -> Self {
+        MockAp {
+            frequency: 5180,
+            password: Some(password.to_string()),
+            ..MockAp::open(ssid, bssid)
+        }
+    }
+}
+
+// The outcome of the activation in progress, told by `finish_activation`.
+struct Outcome {
+    ap: Option<OwnedObjectPath>,
+    reason: u32,
+}
+
+#[derive(Default)]
+struct State {
+    next_id: u32,
+    aps: Vec<(OwnedObjectPath, MockAp)>,
+    profiles: Vec<(OwnedObjectPath, Settings)>,
+    device_state: u32,
+    active_connection: Option<OwnedObjectPath>,
+    active_ap: Option<OwnedObjectPath>,
+    outcome: Option<Outcome>,
+    finish_at_once: bool,
+    scans: u32,
+}
+
+impl State {
+    fn next_path(&mut self, kind: &str) -> OwnedObjectPath {
+        self.next_id += 1;
+        let path = format!("{}/{}/{}", MANAGER_PATH, kind, self.next_id);
+        OwnedObjectPath::try_from(path).unwrap()
+    }
+
+    // Starts activating a profile, the outcome is decided right away.
+    fn start_activation(&mut self, settings: &Settings) -> fdo::Result<(OwnedObjectPath, Outcome)> {
+        let ssid = wireless_ssid(settings)
+            .ok_or_else(|| fdo::Error::InvalidArgs("Missing 802-11-wireless.ssid".into()))?;
+        let psk = psk(settings);
+
+        let outcome = match self.aps.iter().find(|(_, ap)| ap.ssid == ssid) {
+            None => Outcome {
+                ap: None,
+                reason: REASON_SSID_NOT_FOUND,
+            },
+            Some((_, ap)) if ap.password.is_some() && ap.password != psk => Outcome {
+                ap: None,
+                reason: REASON_NO_SECRETS,
+            },
+            Some((path, _)) => Outcome {
+                ap: Some(path.clone()),
+                reason: 0,
+            },
+        };
+        let active = self.next_path("ActiveConnection");
+        self.device_state = DEVICE_STATE_PREPARE;
+        self.active_connection = Some(active.clone());
+        Ok((active, outcome))
+    }
+}
+
+// Ends the activation in progress, with the outcome decided when it started.
+async fn finish_activation(state: &Arc<Mutex<State>>, conn: &Connection) -> zbus::Result<()> {
+    let (new_state, reason) = {
+        let mut state = state.lock().unwrap();
+        let outcome = state.outcome.take().expect("No activation in progress");
+        if outcome.reason == 0 {
+            state.device_state = DEVICE_STATE_ACTIVATED;
+            state.active_ap = outcome.ap;
+        } else {
+            state.device_state = DEVICE_STATE_FAILED;
+            state.active_connection = None;
+        }
+        (state.device_state, outcome.reason)
+    };
+    let emitter = SignalEmitter::new(conn, DEVICE_PATH)?;
+    Device::device_state_changed(&emitter, new_state, DEVICE_STATE_PREPARE, reason).await
+}
+
+fn root() -> OwnedObjectPath {
+    OwnedObjectPath::try_from("/").unwrap()
+}
+
+fn owned(value: Value<'_>) -> OwnedValue {
+    value.try_into().unwrap()
+}
+
+fn wireless_ssid(settings: &Settings) -> Option<String> {
+    let ssid = settings.get("802-11-wireless")?.get("ssid")?;
+    let bytes = Vec::<u8>::try_from(ssid.clone()).ok()?;
+    Some(String::from_utf8_lossy(&bytes).to_string())
+}
+
+fn psk(settings: &Settings) -> Option<String> {
+    let psk = settings.get("802-11-wireless-security")?.get("psk")?;
+    String::try_from(psk.clone()).ok()
+}
+
+pub struct MockNetworkManager {
+    daemon: Child,
+    server: Connection,
+    client: Connection,
+    state: Arc<Mutex<State>>,
+}
+
+impl MockNetworkManager {
+    /// Runs a test against a fake NetworkManager of its own, on its own main context, like the plugin runs on rofi's.
+    pub fn run<F>(
+        aps: Vec<MockAp>,
+        test: impl FnOnce(MockNetworkManager) -> F,
+    ) -> anyhow::Result<()>
+    where
+        F: Future<Output = anyhow::Result<()>>,
+    {
+        glib::MainContext::new().block_on(async { test(Self::start(aps).await?).await })
+    }
+
+    /// Starts a private bus with the fake NetworkManager on it.
+    pub async fn start(aps: Vec<MockAp>) -> anyhow::Result<Self> {
+        let temp_dir = std::env::temp_dir();
+        let daemon = Command::new("dbus-daemon")
+            .arg("--session")
+            .arg("--nofork")
+            .arg("--print-address=1")
+            .arg(format!("--address=unix:tmpdir={}", temp_dir.display()))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn();
+        // Failing rather than skipping, so a missing dbus-daemon isn't mistaken for passing tests.
+        let mut daemon =
+            daemon.context("Failed to run dbus-daemon, the tests need it for their private bus")?;
+
+        let mut address = String::new();
+        BufReader::new(daemon.stdout.take().unwrap()).read_line(&mut address)?;
+        let address = address.trim();
+
+        let mut state = State {
+            device_state: DEVICE_STATE_DISCONNECTED,
+            ..Default::default()
+        };
+        for ap in aps {
+            let path = state.next_path("AccessPoint");
+            state.aps.push((path, ap));
+        }
+        let state = Arc::new(Mutex::new(state));
+
+        let mut builder = connection::Builder::address(address)?
+            .name(NETWORK_MANAGER)?
+            .serve_at(
+                MANAGER_PATH,
+                Manager {
+                    state: Arc::clone(&state),
+                },
+            )?
+            .serve_at(
+                DEVICE_PATH,
+                Device {
+                    state: Arc::clone(&state),
+                },
+            )?
+            .serve_at(
+                DEVICE_PATH,
+                Wireless {
+                    state: Arc::clone(&state),
+                },
+            )?
+            .serve_at(
+                SETTINGS_PATH,
+                SettingsManager {
+                    state: Arc::clone(&state),
+                },
+            )?;
+        let aps = state.lock().unwrap().aps.clone();
+        for (path, ap) in aps {
+            builder = builder.serve_at(path, AccessPoint { ap })?;
+        }
+        let server = builder.build().await?;
+        let client = connection::Builder::address(address)?.build().await?;
+
+        Ok(MockNetworkManager {
+            daemon,
+            server,
+            client,
+            state,
+        })
+    }
+
+    /// A connection to the private bus, in place of the system bus.
+    pub fn connection(&self) -> Connection {
+        self.client.clone()
+    }
+
+    /// How many scans were requested.
+    pub fn scans(&self) -> u32 {
+        self.state.lock().unwrap().scans
+    }
+
+    /// Saves a WPA2 profile, as if it was created earlier.
+    pub async fn add_profile(&self, ssid: &str, password: &str) -> anyhow::Result<OwnedObjectPath> {
+        self.add_named_profile(ssid, ssid, password).await
+    }
+
+    /// Saves a WPA2 profile named `id` rather than after its SSID, e.g. "Home 1" as nmcli names a second one.
+    pub async fn add_named_profile(
+        &self,
+        id: &str,
+        ssid: &str,
+        password: &str,
+    ) -> anyhow::Result<OwnedObjectPath> {
+        let mut settings = Settings::new();
+        settings.insert(
+            "connection".to_string(),
+            HashMap::from([
+                ("type".to_string(), owned(Value::from("802-11-wireless"))),
+                ("id".to_string(), owned(Value::from(id))),
+            ]),
+        );
+        settings.insert(
+            "802-11-wireless".to_string(),
+            HashMap::from([("ssid".to_string(), owned(Value::from(ssid.as_bytes())))]),
+        );
+        settings.insert(
+            "802-11-wireless-security".to_string(),
+            HashMap::from([
+                ("key-mgmt".to_string(), owned(Value::from("wpa-psk"))),
+                ("psk".to_string(), owned(Value::from(password))),
+            ]),
+        );
+
+        let path = {
+            let mut state = self.state.lock().unwrap();
+            let path = state.next_path("Settings");
+            state.profiles.push((path.clone(), settings));
+            path
+        };
+        self.server
+            .object_server()
+            .at(
+                &path,
+                Profile {
+                    path: path.clone(),
+                    state: Arc::clone(&self.state),
+                },
+            )
+            .await?;
+        let emitter = SignalEmitter::new(&self.server, SETTINGS_PATH)?;
+        SettingsManager::new_connection(&emitter, path.clone()).await?;
+        Ok(path)
+    }
+
+    /// Takes an access point out of range, as NetworkManager does when a scan no longer sees it.
+    pub async fn remove_ap(&self, bssid: &str) -> anyhow::Result<()> {
+        let path = {
+            let mut state = self.state.lock().unwrap();
+            let index = state
+                .aps
+                .iter()
+                .position(|(_, ap)| ap.bssid == bssid)
+                .expect("No such access point");
+            state.aps.remove(index).0
+        };
+        self.server
+            .object_server()
+            .remove::<AccessPoint, _>(&path)
+            .await?;
+        let emitter = SignalEmitter::new(&self.server, DEVICE_PATH)?;
+        Wireless::access_point_removed(&emitter, path).await?;
+        Ok(())
+    }
+
+    /// Ends the activation in progress, with the outcome decided when it started.
+    pub async fn finish_activation(&self) -> anyhow::Result<()> {
+        finish_activation(&self.state, &self.server).await?;
+        Ok(())
+    }
+
+    /// Ends every activation from now on before the call that started it returns, like a network that was in reach.
+    pub fn finish_activations_at_once(&self) {
+        self.state.lock().unwrap().finish_at_once = true;
+    }
+
+    /// Tells the device failed, while the activation in progress goes on, as when a previous activation fails late.
+    pub async fn fail_stale_activation(&self) -> anyhow::Result<()> {
+        let emitter = SignalEmitter::new(&self.server, DEVICE_PATH)?;
+        Device::device_state_changed(
+            &emitter,
+            DEVICE_STATE_FAILED,
+            DEVICE_STATE_PREPARE,
+            REASON_NO_SECRETS,
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Whether an activation is in progress.
+    pub fn activating(&self) -> bool {
+        self.state.lock().unwrap().outcome.is_some()
+    }
+
+    /// The active connection of the device, if any.
+    pub fn active_connection(&self) -> Option<OwnedObjectPath> {
+        self.state.lock().unwrap().active_connection.clone()
+    }
+
+    /// How many profiles are saved.
+    pub fn profile_count(&self) -> usize {
+        self.state.lock().unwrap().profiles.len()
+    }
+}
+
+impl Drop for MockNetworkManager {
+    fn drop(&mut self) {
+        let _ = self.daemon.kill();
+        let _ = self.daemon.wait();
+    }
+}
+
+struct Manager {
+    state: Arc<Mutex<State>>,
+}
+
+#[zbus::interface(name = "org.freedesktop.NetworkManager")]
+impl Manager {
+    fn get_devices(&self) -> Vec<OwnedObjectPath> {
+        vec![OwnedObjectPath::try_from(DEVICE_PATH).unwrap()]
+    }
+
+    // The profile is saved right away, whatever the outcome, like NetworkManager does.
+    async fn add_and_activate_connection(
+        &self,
+        settings: Settings,
+        _device: OwnedObjectPath,
+        _specific_object: OwnedObjectPath,
+        #[zbus(object_server)] server: &ObjectServer,
+        #[zbus(connection)] conn: &Connection,
+    ) -> fdo::Result<(OwnedObjectPath, OwnedObjectPath)> {
+        if wireless_ssid(&settings).is_none() {
+            return Err(fdo::Error::InvalidArgs(
+                "Missing 802-11-wireless.ssid".into(),
+            ));
+        }
+        let profile = {
+            let mut state = self.state.lock().unwrap();
+            let profile = state.next_path("Settings");
+            state.profiles.push((profile.clone(), settings));
+            profile
+        };
+
+        server
+            .at(
+                &profile,
+                Profile {
+                    path: profile.clone(),
+                    state: Arc::clone(&self.state),
+                },
+            )
+            .await?;
+        let emitter = SignalEmitter::new(conn, SETTINGS_PATH)?;
+        SettingsManager::new_connection(&emitter, profile.clone()).await?;
+
+        let active = self.activate(&profile, server, conn).await?;
+        Ok((profile, active))
+    }
+
+    async fn activate_connection(
+        &self,
+        connection: OwnedObjectPath,
+        _device: OwnedObjectPath,
+        _specific_object: OwnedObjectPath,
+        #[zbus(object_server)] server: &ObjectServer,
+        #[zbus(connection)] conn: &Connection,
+    ) -> fdo::Result<OwnedObjectPath> {
+        self.activate(&connection, server, conn).await
+    }
+
+    async fn deactivate_connection(
+        &self,
+        active_connection: OwnedObjectPath,
+        #[zbus(connection)] conn: &Connection,
+    ) -> fdo::Result<()> {
+        let old_state = {
+            let mut state = self.state.lock().unwrap();
+            if state.active_connection.as_ref() != Some(&active_connection) {
+                return Err(fdo::Error::Failed("Not an active connection".into()));
+            }
+            state.active_connection = None;
+            state.active_ap = None;
+            state.outcome = None;
+            std::mem::replace(&mut state.device_state, DEVICE_STATE_DISCONNECTED)
+        };
+        let emitter = SignalEmitter::new(conn, DEVICE_PATH)?;
+        Device::device_state_changed(
+            &emitter,
+            DEVICE_STATE_DISCONNECTED,
+            old_state,
+            REASON_USER_REQUESTED,
+        )
+        .await?;
+        Ok(())
+    }
+}
+
+impl Manager {
+    // Starts activating a saved profile, and ends it right away after `finish_activations_at_once`.
+    async fn activate(
+        &self,
+        profile: &OwnedObjectPath,
+        server: &ObjectServer,
+        conn: &Connection,
+    ) -> fdo::Result<OwnedObjectPath> {
+        let (active, ap, finish_at_once) = {
+            let mut state = self.state.lock().unwrap();
+            let settings = state
+                .profiles
+                .iter()
+                .find(|(path, _)| path == profile)
+                .map(|(_, settings)| settings.clone())
+                .ok_or_else(|| fdo::Error::UnknownObject(profile.to_string()))?;
+            let (active, outcome) = state.start_activation(&settings)?;
+            let ap = outcome.ap.clone();
+            state.outcome = Some(outcome);
+            (active, ap, state.finish_at_once)
+        };
+
+        server
+            .at(
+                &active,
+                ActiveConnection {
+                    path: active.clone(),
+                    connection: profile.clone(),
+                    specific_object: ap.unwrap_or_else(root),
+                    state: Arc::clone(&self.state),
+                },
+            )
+            .await?;
+        if finish_at_once {
+            finish_activation(&self.state, conn).await?;
+        }
+        Ok(active)
+    }
+}
+
+struct Device {
+    state: Arc<Mutex<State>>,
+}
+
+#[zbus::interface(name = "org.freedesktop.NetworkManager.Device")]
+impl Device {
+    #[zbus(property)]
+    fn interface(&self) -> String {
+        IFACE.to_string()
+    }
+
+    // NM_DEVICE_TYPE_WIFI
+    #[zbus(property)]
+    fn device_type(&self) -> u32 {
+        2
+    }
+
+    #[zbus(property)]
+    fn state(&self) -> u32 {
+        self.state.lock().unwrap().device_state
+    }
+
+    #[zbus(property)]
+    fn active_connection(&self) -> OwnedObjectPath {
+        let state = self.state.lock().unwrap();
+        state.active_connection.clone().unwrap_or_else(root)
+    }
+
+    #[zbus(property)]
+    fn ip4_config(&self) -> OwnedObjectPath {
+        root()
+    }
+
+    #[zbus(property)]
+    fn ip6_config(&self) -> OwnedObjectPath {
+        root()
+    }
+
+    // Named apart from the `State` property's own `state_changed`.
+    #[zbus(signal, name = "StateChanged")]
+    async fn device_state_changed(
+        emitter: &SignalEmitter<'_>,
+        new_state: u32,
+        old_state: u32,
+        reason: u32,
+    ) -> zbus::Result<()>;
+}
+
+struct Wireless {
+    state: Arc<Mutex<State>>,
+}
+
+#[zbus::interface(name = "org.freedesktop.NetworkManager.Device.Wireless")]
+impl Wireless {
+    fn get_all_access_points(&self) -> Vec<OwnedObjectPath> {
+        let state = self.state.lock().unwrap();
+        state.aps.iter().map(|(path, _)| path.clone()).collect()
+    }
+
+    // The scan is done right away, `LastScan` tells it.
+    async fn request_scan(
+        &self,
+        _options: HashMap<String, OwnedValue>,
+        #[zbus(signal_emitter)] emitter: SignalEmitter<'_>,
+    ) -> fdo::Result<()> {
+        self.state.lock().unwrap().scans += 1;
+        self.last_scan_changed(&emitter).await?;
+        Ok(())
+    }
+
+    #[zbus(property)]
+    fn last_scan(&self) -> i64 {
+        i64::from(self.state.lock().unwrap().scans) * 1000
+    }
+
+    #[zbus(property)]
+    fn active_access_point(&self) -> OwnedObjectPath {
+        let state = self.state.lock().unwrap();
+        state.active_ap.clone().unwrap_or_else(root)
+    }
+
+    #[zbus(property)]
+    fn bitrate(&self) -> u32 {
+        0
+    }
+
+    #[zbus(signal)]
+    async fn access_point_added(
+        emitter: &SignalEmitter<'_>,
+        access_point: OwnedObjectPath,
+    ) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn access_point_removed(
+        emitter: &SignalEmitter<'_>,
+        access_point: OwnedObjectPath,
+    ) -> zbus::Result<()>;
+}
+
+struct AccessPoint {
+    ap: MockAp,
+}
+
+#[zbus::interface(name = "org.freedesktop.NetworkManager.AccessPoint")]
+impl AccessPoint {
+    #[zbus(property)]
+    fn ssid(&self) -> Vec<u8> {
+        self.ap.ssid.as_bytes().to_vec()
+    }
+
+    #[zbus(property)]
+    fn hw_address(&self) -> String {
+        self.ap.bssid.clone()
+    }
+
+    #[zbus(property)]
+    fn frequency(&self) -> u32 {
+        self.ap.frequency
+    }
+
+    #[zbus(property)]
+    fn strength(&self) -> u8 {
+        self.ap.strength
+    }
+
+    #[zbus(property)]
+    fn flags(&self) -> u32 {
+        if self.ap.password.is_some() {
+            AP_FLAGS_PRIVACY
+        } else {
+            0
+        }
+    }
+
+    #[zbus(property)]
+    fn wpa_flags(&self) -> u32 {
+        0
+    }
+
+    #[zbus(property)]
+    fn rsn_flags(&self) -> u32 {
+        if self.ap.password.is_some() {
+            AP_SEC_KEY_MGMT_PSK
+        } else {
+            0
+        }
+    }
+}
+
+struct SettingsManager {
+    state: Arc<Mutex<State>>,
+}
+
+#[zbus::interface(name = "org.freedesktop.NetworkManager.Settings")]
+impl SettingsManager {
+    fn list_connections(&self) -> Vec<OwnedObjectPath> {
+        let state = self.state.lock().unwrap();
+        state
+            .profiles
+            .iter()
+            .map(|(path, _)| path.clone())
+            .collect()
+    }
+
+    #[zbus(signal)]
+    async fn new_connection(
+        emitter: &SignalEmitter<'_>,
+        connection: OwnedObjectPath,
+    ) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn connection_removed(
+        emitter: &SignalEmitter<'_>,
+        connection: OwnedObjectPath,
+    ) -> zbus::Result<()>;
+}
+
+struct Profile {
+    path: OwnedObjectPath,
+    state: Arc<Mutex<State>>,
+}
+
+// A deleted profile stays on the bus, but is no longer listed nor readable.
+#[zbus::interface(name = "org.freedesktop.NetworkManager.Settings.Connection")]
+impl Profile {
+    fn get_settings(&self) -> fdo::Result<Settings> {
+        let state = self.state.lock().unwrap();
+        state
+            .profiles
+            .iter()
+            .find(|(path, _)| *path == self.path)
+            .map(|(_, settings)| settings.clone())
+            .ok_or_else(|| fdo::Error::UnknownObject(self.path.to_string()))
+    }
+
+    async fn delete(&self, #[zbus(connection)] conn: &Connection) -> fdo::Result<()> {
+        {
+            let mut state = self.state.lock().unwrap();
+            let index = state
+                .profiles
+                .iter()
+                .position(|(path, _)| *path == self.path)
+                .ok_or_else(|| fdo::Error::UnknownObject(self.path.to_string()))?;
+            state.profiles.remove(index);
+        }
+        let emitter = SignalEmitter::new(conn, SETTINGS_PATH)?;
+        SettingsManager::connection_removed(&emitter, self.path.clone()).await?;
+        Ok(())
+    }
+}
+
+struct ActiveConnection {
+    path: OwnedObjectPath,
+    connection: OwnedObjectPath,
+    specific_object: OwnedObjectPath,
+    state: Arc<Mutex<State>>,
+}
+
+#[zbus::interface(name = "org.freedesktop.NetworkManager.Connection.Active")]
+impl ActiveConnection {
+    #[zbus(property)]
+    fn connection(&self) -> OwnedObjectPath {
+        self.connection.clone()
+    }
+
+    #[zbus(property)]
+    fn specific_object(&self) -> OwnedObjectPath {
+        self.specific_object.clone()
+    }
+
+    // Stays on the bus once deactivated, unlike NetworkManager's, but tells it.
+    #[zbus(property)]
+    fn state(&self) -> u32 {
+        let state = self.state.lock().unwrap();
+        if state.active_connection.as_ref() != Some(&self.path) {
+            ACTIVE_STATE_DEACTIVATED
+        } else if state.outcome.is_some() {
+            ACTIVE_STATE_ACTIVATING
+        } else {
+            ACTIVE_STATE_ACTIVATED
+        }
+    }
+}
@@ -0,0 +1,352 @@
+// The NetworkManager client against the fake NetworkManager of `mock_network_manager`, on a private bus.
+// dbus-daemon must be installed, the tests fail without it.
+mod mock_network_manager;
+
+use futures_util::{StreamExt, future};
+use mock_network_manager::{DEVICE_PATH, IFACE, MockAp, MockNetworkManager};
+use std::time::Duration;
+use wifi::{
+    backend::{AccessPointEvent, Backend, NewProfile, Verdict, connect_and_wait},
+    network_manager, reason,
+    structure::{AccessPoint, NetworkManagerDbusProxy, Security},
+};
+
+const HOME_BSSID: &str = "AA:BB:CC:DD:EE:01";
+const CAFE_BSSID: &str = "AA:BB:CC:DD:EE:02";
+
+fn networks() -> Vec<MockAp> {
+    vec![
+        MockAp::wpa2("Home", HOME_BSSID, "correct horse"),
+        MockAp::open("Cafe", CAFE_BSSID),
+    ]
+}
+
+async fn setup(mock: &MockNetworkManager) -> anyhow::Result<NetworkManagerDbusProxy> {
+    network_manager::setup_dbus_with(mock.connection(), Some(IFACE)).await
+}
+
+fn find<'a>(aps: &'a [AccessPoint], ssid: &str) -> &'a AccessPoint {
+    aps.iter()
+        .find(|ap| ap.ssid == ssid)
+        .unwrap_or_else(|| panic!("{} is not listed", ssid))
+}
+
+fn new_profile(password: &str) -> NewProfile {
+    NewProfile {
+        password: Some(password.to_string()),
+        eap: None,
+        lock_bssid: false,
+    }
+}
+
+// Connects with a new profile, and waits for the outcome the mock is told to give.
+async fn connect(
+    mock: &MockNetworkManager,
+    nm: &NetworkManagerDbusProxy,
+    ap: &AccessPoint,
+    password: Option<&str>,
+) -> anyhow::Result<u32> {
+    nm.connect(ap, password.map(str::to_string), false, None, false)
+        .await?;
+    mock.finish_activation().await?;
+    nm.connect_result().await
+}
+
+#[test]
+fn setup_finds_the_wifi_device() -> anyhow::Result<()> {
+    MockNetworkManager::run(networks(), |mock| async move {
+        let nm = network_manager::setup_dbus_with(mock.connection(), None).await?;
+        assert_eq!(nm.iface, IFACE);
+        assert_eq!(nm.interfaces, [IFACE]);
+        assert_eq!(nm.dev_path.as_str(), DEVICE_PATH);
+
+        let missing = network_manager::setup_dbus_with(mock.connection(), Some("wlan9")).await;
+        assert!(missing.is_err());
+        Ok(())
+    })
+}
+
+#[test]
+fn rescan_waits_for_last_scan() -> anyhow::Result<()> {
+    MockNetworkManager::run(networks(), |mock| async move {
+        let nm = setup(&mock).await?;
+        network_manager::trigger_rescan(&nm.property_proxy, &nm.wifi_proxy).await?;
+        assert_eq!(mock.scans(), 1);
+        Ok(())
+    })
+}
+
+#[test]
+fn access_points_are_listed_with_their_profiles() -> anyhow::Result<()> {
+    MockNetworkManager::run(networks(), |mock| async move {
+        let profile = mock.add_profile("Home", "correct horse").await?;
+        let nm = setup(&mock).await?;
+
+        let aps = nm.access_points().await?;
+        assert_eq!(aps.len(), 2);
+
+        let home = find(&aps, "Home");
+        assert_eq!(home.bssid, HOME_BSSID);
+        assert_eq!(home.security.class, Security::WpaPsk);
+        assert_eq!(home.setting_path, Some(profile));
+
+        let cafe = find(&aps, "Cafe");
+        assert_eq!(cafe.security.class, Security::Open);
+        assert_eq!(cafe.setting_path, None);
+        Ok(())
+    })
+}
+
+#[test]
+fn connect_succeeds() -> anyhow::Result<()> {
+    MockNetworkManager::run(networks(), |mock| async move {
+        let nm = setup(&mock).await?;
+        let aps = nm.access_points().await?;
+        assert_eq!(
+            network_manager::get_active_ap(&nm.con, &nm.wifi_proxy).await?,
+            None
+        );
+
+        let reason = connect(&mock, &nm, find(&aps, "Home"), Some("correct horse")).await?;
+        assert_eq!(reason, 0);
+
+        let (bssid, profile) = network_manager::get_active_ap(&nm.con, &nm.wifi_proxy)
+            .await?
+            .expect("connected");
+        assert_eq!(bssid, HOME_BSSID);
+
+        // The new profile is listed along with the access point.
+        let aps = nm.access_points().await?;
+        assert_eq!(find(&aps, "Home").setting_path, Some(profile));
+        Ok(())
+    })
+}
+
+#[test]
+fn disconnect_keeps_the_profile() -> anyhow::Result<()> {
+    MockNetworkManager::run(networks(), |mock| async move {
+        let nm = setup(&mock).await?;
+        let aps = nm.access_points().await?;
+        assert_eq!(
+            connect(&mock, &nm, find(&aps, "Home"), Some("correct horse")).await?,
+            0
+        );
+
+        nm.disconnect().await?;
+        assert_eq!(mock.active_connection(), None);
+        assert_eq!(mock.profile_count(), 1);
+        // Nothing to deactivate anymore.
+        nm.disconnect().await?;
+        Ok(())
+    })
+}
+
+#[test]
+fn bad_password_fails_with_no_secrets() -> anyhow::Result<()> {
+    MockNetworkManager::run(networks(), |mock| async move {
+        let nm = setup(&mock).await?;
+        let aps = nm.access_points().await?;
+
+        let reason = connect(&mock, &nm, find(&aps, "Home"), Some("battery staple")).await?;
+        // NM_DEVICE_STATE_REASON_NO_SECRETS, the password is asked again.
+        assert_eq!(reason, 7);
+        assert_eq!(
+            reason::describe(reason).action,
+            reason::FailureAction::AskPassword
+        );
+        assert_eq!(
+            network_manager::get_active_ap(&nm.con, &nm.wifi_proxy).await?,
+            None
+        );
+        Ok(())
+    })
+}
+
+#[test]
+fn vanished_access_point_fails_with_ssid_not_found() -> anyhow::Result<()> {
+    MockNetworkManager::run(networks(), |mock| async move {
+        let nm = setup(&mock).await?;
+        let aps = nm.access_points().await?;
+        let cafe = find(&aps, "Cafe").clone();
+
+        mock.remove_ap(CAFE_BSSID).await?;
+        let aps = nm.access_points().await?;
+        assert!(aps.iter().all(|ap| ap.ssid != "Cafe"));
+
+        // Picked from the list shown before it went out of range.
+        let reason = connect(&mock, &nm, &cafe, None).await?;
+        // NM_DEVICE_STATE_REASON_SSID_NOT_FOUND
+        assert_eq!(reason, 53);
+        Ok(())
+    })
+}
+
+#[test]
+fn profiles_saved_meanwhile_are_matched() -> anyhow::Result<()> {
+    MockNetworkManager::run(networks(), |mock| async move {
+        let nm = setup(&mock).await?;
+        let mut aps = nm.access_points().await?;
+        let mut events = nm.access_point_events().await?;
+        assert_eq!(find(&aps, "Home").setting_path, None);
+
+        let profile = mock.add_profile("Home", "correct horse").await?;
+        assert!(matches!(
+            events.next().await,
+            Some(AccessPointEvent::Profiles)
+        ));
+        nm.match_profiles(&mut aps);
+        assert_eq!(find(&aps, "Home").setting_path, Some(profile));
+        assert_eq!(find(&aps, "Cafe").setting_path, None);
+
+        nm.forget("Home").await?;
+        assert!(matches!(
+            events.next().await,
+            Some(AccessPointEvent::Profiles)
+        ));
+        nm.match_profiles(&mut aps);
+        assert_eq!(find(&aps, "Home").setting_path, None);
+        Ok(())
+    })
+}
+
+#[test]
+fn forget_deletes_every_profile_of_the_network() -> anyhow::Result<()> {
+    MockNetworkManager::run(networks(), |mock| async move {
+        mock.add_profile("Home", "correct horse").await?;
+        mock.add_named_profile("Home 1", "Home", "old password")
+            .await?;
+        mock.add_profile("Office", "hunter2").await?;
+        // Named after another network, it isn't one of Home's.
+        mock.add_named_profile("Home", "Office", "hunter3").await?;
+        let nm = setup(&mock).await?;
+
+        assert_eq!(nm.forget("Home").await?, 2);
+        assert_eq!(nm.forget("Home").await?, 0);
+        assert_eq!(mock.profile_count(), 2);
+
+        let aps = nm.access_points().await?;
+        assert_eq!(find(&aps, "Home").setting_path, None);
+        Ok(())
+    })
+}
+
+#[test]
+fn activation_done_before_its_call_returns_is_followed() -> anyhow::Result<()> {
+    MockNetworkManager::run(networks(), |mock| async move {
+        mock.finish_activations_at_once();
+        let nm = setup(&mock).await?;
+        let aps = nm.access_points().await?;
+
+        let verdict = connect_and_wait(
+            &nm,
+            find(&aps, "Home"),
+            Some(new_profile("correct horse")),
+            glib::timeout_future(Duration::from_secs(5)),
+            future::pending(),
+        )
+        .await?;
+        assert!(matches!(verdict, Verdict::Connected(_)));
+
+        // A failure that quick deletes the new profile all the same.
+        let verdict = connect_and_wait(
+            &nm,
+            find(&aps, "Home"),
+            Some(new_profile("battery staple")),
+            glib::timeout_future(Duration::from_secs(5)),
+            future::pending(),
+        )
+        .await?;
+        assert_eq!(verdict, Verdict::Failed(7));
+        assert_eq!(mock.profile_count(), 1);
+        Ok(())
+    })
+}
+
+#[test]
+fn stale_failure_is_not_the_verdict() -> anyhow::Result<()> {
+    MockNetworkManager::run(networks(), |mock| async move {
+        let nm = setup(&mock).await?;
+        let aps = nm.access_points().await?;
+
+        nm.connect(
+            find(&aps, "Home"),
+            Some("correct horse".to_string()),
+            false,
+            None,
+            false,
+        )
+        .await?;
+        mock.fail_stale_activation().await?;
+        mock.finish_activation().await?;
+        assert_eq!(nm.connect_result().await?, 0);
+        Ok(())
+    })
+}
+
+#[test]
+fn timed_out_connect_is_stopped_and_its_profile_deleted() -> anyhow::Result<()> {
+    MockNetworkManager::run(networks(), |mock| async move {
+        let nm = setup(&mock).await?;
+        let aps = nm.access_points().await?;
+
+        let verdict = connect_and_wait(
+            &nm,
+            find(&aps, "Home"),
+            Some(new_profile("correct horse")),
+            future::ready(()),
+            future::pending(),
+        )
+        .await?;
+        assert_eq!(verdict, Verdict::Failed(reason::CONNECT_TIMEOUT));
+        assert!(!mock.activating());
+        assert_eq!(mock.active_connection(), None);
+        assert_eq!(mock.profile_count(), 0);
+        Ok(())
+    })
+}
+
+#[test]
+fn canceled_connect_is_stopped_and_its_profile_deleted() -> anyhow::Result<()> {
+    MockNetworkManager::run(networks(), |mock| async move {
+        let nm = setup(&mock).await?;
+        let aps = nm.access_points().await?;
+
+        let verdict = connect_and_wait(
+            &nm,
+            find(&aps, "Home"),
+            Some(new_profile("correct horse")),
+            future::pending(),
+            future::ready(()),
+        )
+        .await?;
+        assert_eq!(verdict, Verdict::Canceled);
+        assert!(!mock.activating());
+        assert_eq!(mock.active_connection(), None);
+        assert_eq!(mock.profile_count(), 0);
+        Ok(())
+    })
+}
+
+#[test]
+fn timed_out_connect_keeps_the_saved_profile() -> anyhow::Result<()> {
+    MockNetworkManager::run(networks(), |mock| async move {
+        let profile = mock.add_profile("Home", "correct horse").await?;
+        let nm = setup(&mock).await?;
+        let aps = nm.access_points().await?;
+
+        let verdict = connect_and_wait(
+            &nm,
+            find(&aps, "Home"),
+            None,
+            future::ready(()),
+            future::pending(),
+        )
+        .await?;
+        assert_eq!(verdict, Verdict::Failed(reason::CONNECT_TIMEOUT));
+        assert_eq!(mock.active_connection(), None);
+
+        let aps = nm.access_points().await?;
+        assert_eq!(find(&aps, "Home").setting_path, Some(profile));
+        Ok(())
+    })
+}
@@ -0,0 +1,212 @@
+// The state machine without rofi, driven through a fake view like another front-end would,
+// against the fake NetworkManager of `mock_network_manager`.
+
+// Only part of the fake is needed here.
+#[allow(dead_code)]
+mod mock_network_manager;
+
+use futures_channel::{mpsc, oneshot};
+use mock_network_manager::{IFACE, MockAp, MockNetworkManager};
+use std::{
+    cell::{Ref, RefCell},
+    ffi::{CStr, CString},
+    rc::Rc,
+};
+use wifi::{
+    backend::Backend,
+    network_manager,
+    secret_agent::{SecretAgentEvent, SecretRequest},
+    state,
+    structure::{AppState, HiddenStep, PrivateData, Row, Security, SharedData},
+    view::{PromptView, View},
+};
+use zbus::zvariant::OwnedObjectPath;
+
+const HOME_BSSID: &str = "AA:BB:CC:DD:EE:01";
+
+// The view of the launcher menu, the private data is only reached through it, like the state machine does.
+struct Menu {
+    view: Rc<RefCell<PromptView>>,
+    pd: SharedData,
+}
+
+impl Menu {
+    async fn open(mock: &MockNetworkManager) -> anyhow::Result<Self> {
+        let nm = network_manager::setup_dbus_with(mock.connection(), Some(IFACE)).await?;
+        let aps = nm.access_points().await?;
+        let mut pd = PrivateData::new(Box::new(nm), aps);
+        pd.sort_accesspoints();
+        // The redraws aren't waited for, each step is checked right after it's handled.
+        let (view, _redraws) = PromptView::new(pd, CString::default());
+        let pd = view.private_data().unwrap();
+        Ok(Self {
+            view: Rc::new(RefCell::new(view)),
+            pd,
+        })
+    }
+
+    fn pd(&self) -> Ref<'_, PrivateData> {
+        self.pd.borrow()
+    }
+
+    fn row_of(&self, ssid: &str) -> usize {
+        let pd = self.pd();
+        (0..pd.rows.len())
+            .find(|&line| pd.row_ap(line).is_some_and(|i| pd.aps[i].ssid == ssid))
+            .unwrap_or_else(|| panic!("{} is not listed", ssid))
+    }
+
+    fn select(&mut self, selected_line: usize, input: &CStr) {
+        state::handle_state(self.view.clone(), selected_line, &self.pd, input);
+    }
+
+    fn type_password(&mut self, password: &str) {
+        self.select(usize::MAX, &CString::new(password).unwrap());
+    }
+
+    // Hands the events to the secret agent's task, as NetworkManager would, and waits until they are handled.
+    async fn agent(&self, events: Vec<SecretAgentEvent>) {
+        let (sender, receiver) = mpsc::unbounded();
+        for event in events {
+            sender.unbounded_send(event).unwrap();
+        }
+        drop(sender);
+        state::secret_agent_task(self.view.clone(), receiver).await;
+    }
+
+    fn prompt(&self) -> String {
+        self.view.borrow().prompt.to_string_lossy().to_string()
+    }
+}
+
+fn with_menu<F>(test: impl FnOnce(Menu) -> F) -> anyhow::Result<()>
+where
+    F: Future<Output = anyhow::Result<()>>,
+{
+    let aps = vec![
+        MockAp::wpa2("Home", HOME_BSSID, "correct horse"),
+        MockAp::open("Cafe", "AA:BB:CC:DD:EE:02"),
+    ];
+    MockNetworkManager::run(
+        aps,
+        |mock| async move { test(Menu::open(&mock).await?).await },
+    )
+}
+
+#[test]
+fn secured_network_asks_a_valid_password() -> anyhow::Result<()> {
+    with_menu(|mut menu| async move {
+        menu.select(menu.row_of("Home"), c"");
+        let asked = AppState::PasswordInput {
+            bssid: HOME_BSSID.to_string(),
+            reason: 0,
+        };
+        assert_eq!(menu.pd().state, asked);
+        assert_eq!(menu.prompt(), "password");
+
+        // No profile is created for a password that can't work, the prompt says why.
+        menu.type_password("short");
+        assert_eq!(menu.pd().state, asked);
+        assert_eq!(menu.prompt(), "too short, min 8");
+        Ok(())
+    })
+}
+
+#[test]
+fn hidden_network_asks_its_name_then_security() -> anyhow::Result<()> {
+    with_menu(|mut menu| async move {
+        let hidden_row = menu
+            .pd()
+            .rows
+            .iter()
+            .position(|row| *row == Row::Hidden)
+            .unwrap();
+        menu.select(hidden_row, c"Home");
+        assert_eq!(menu.pd().state, AppState::HiddenInput(HiddenStep::Ssid));
+        assert_eq!(menu.prompt(), "ssid");
+
+        // Only an answer is complained about.
+        menu.select(usize::MAX, c"");
+        assert_eq!(menu.pd().state, AppState::HiddenInput(HiddenStep::Ssid));
+        assert_eq!(menu.prompt(), "ssid can't be empty");
+
+        menu.select(usize::MAX, c"Office");
+        let ssid = "Office".to_string();
+        assert_eq!(
+            menu.pd().state,
+            AppState::HiddenInput(HiddenStep::Security { ssid })
+        );
+        assert_eq!(menu.prompt(), "security");
+
+        // Typed out instead of picked from the list.
+        menu.select(usize::MAX, c"wpa/wpa2-personal");
+        let pd = menu.pd();
+        let hidden = pd.hidden.as_ref().expect("hidden network");
+        assert_eq!(hidden.ssid, "Office");
+        assert_eq!(hidden.security.class, Security::WpaPsk);
+        assert!(matches!(pd.state, AppState::PasswordInput { ref bssid, .. } if bssid.is_empty()));
+        drop(pd);
+        assert_eq!(menu.prompt(), "password");
+        Ok(())
+    })
+}
+
+// A request for a profile that isn't listed, e.g. autoconnect to a network out of range when the menu was opened.
+fn secret_request(security: Security) -> (SecretRequest, oneshot::Receiver<String>) {
+    let (reply, answer) = oneshot::channel();
+    let request = SecretRequest {
+        ssid: "Lab".to_string(),
+        connection_path: OwnedObjectPath::try_from("/org/freedesktop/NetworkManager/Settings/9")
+            .unwrap(),
+        security: Some(security),
+        reply,
+    };
+    (request, answer)
+}
+
+#[test]
+fn secret_request_checks_the_password_of_its_own_security() -> anyhow::Result<()> {
+    with_menu(|mut menu| async move {
+        let (request, answer) = secret_request(Security::Sae);
+        menu.agent(vec![SecretAgentEvent::Request(request)]).await;
+        let asked = AppState::PasswordInput {
+            bssid: String::new(),
+            reason: 0,
+        };
+        assert_eq!(menu.pd().state, asked);
+
+        // Too short for WPA2, fine for WPA3.
+        menu.type_password("short");
+        assert_eq!(answer.await?, "short");
+        Ok(())
+    })
+}
+
+#[test]
+fn canceled_secret_request_gives_back_the_input_it_took_over() -> anyhow::Result<()> {
+    with_menu(|mut menu| async move {
+        let hidden_row = menu
+            .pd()
+            .rows
+            .iter()
+            .position(|row| *row == Row::Hidden)
+            .unwrap();
+        menu.select(hidden_row, c"");
+        menu.select(usize::MAX, c"Office");
+        let typing = AppState::HiddenInput(HiddenStep::Security {
+            ssid: "Office".to_string(),
+        });
+        assert_eq!(menu.pd().state, typing);
+
+        let (request, _answer) = secret_request(Security::WpaPsk);
+        let connection_path = request.connection_path.clone();
+        menu.agent(vec![SecretAgentEvent::Request(request)]).await;
+        assert!(matches!(menu.pd().state, AppState::PasswordInput { .. }));
+
+        menu.agent(vec![SecretAgentEvent::Cancel(connection_path)])
+            .await;
+        assert_eq!(menu.pd().state, typing);
+        assert_eq!(menu.prompt(), "security");
+        Ok(())
+    })
+}